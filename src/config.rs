@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::logger::audit_journal::JournalConfig;
+use crate::logger::balance_ledger::BalanceLedgerConfig;
+use crate::logger::book_deltas::BookDeltaConfig;
+use crate::logger::candles::CandleConfig;
+use crate::logger::conflation::ConflationConfig;
+use crate::logger::correlator::CorrelatorConfig;
+use crate::logger::fix_sink::FixConfig;
+use crate::logger::holdings_recon::HoldingsReconConfig;
+use crate::logger::ingest_lag::IngestLagConfig;
+use crate::logger::jsonl_sink::JsonlConfig;
+use crate::logger::log_flusher::{FlushPolicy, FlusherGroupConfig};
+use crate::logger::market_quality::MarketQualityConfig;
+use crate::logger::order_latency::OrderLatencyConfig;
+use crate::logger::order_state::OrderStateConfig;
+use crate::logger::parquet_sink::ParquetConfig;
+use crate::logger::rules::RulesConfig;
+use crate::logger::surveillance::SurveillanceConfig;
+use crate::logger::types::Stream;
+use crate::logger::user_activity::UserActivityConfig;
+use crate::server::history::HistoryConfig;
+use crate::server::tail::TailConfig;
+use crate::server::ws::WsConfig;
+use crate::shm::sequence::SequenceConfig;
+
+// optional JSON config , every section falls back to the built in defaults
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoggerConfig {
+    pub poller_core: Option<usize>,
+    pub sequence: SequenceConfig,
+    // empty -> one flusher for every stream
+    pub flushers: Vec<FlusherGroupConfig>,
+    // per table overrides , see FlushPolicy::default_for for the rest
+    pub flush_policies: HashMap<Stream, FlushPolicy>,
+    // shared by every derived table ( candles ... )
+    pub derived_flush_policy: FlushPolicy,
+    // snapshots stored per symbol per interval , every snapshot when absent
+    pub conflation: Option<ConflationConfig>,
+    // stored snapshots written as orderbook_deltas between full keyframes
+    pub book_deltas: Option<BookDeltaConfig>,
+    // lag histograms from producer timestamp to flush , per stream
+    pub ingest_lag: Option<IngestLagConfig>,
+    pub parquet: Option<ParquetConfig>,
+    pub jsonl: Option<JsonlConfig>,
+    // FIX 4.4 ExecutionReport drop copy , needs order_logs and trade_logs in one flusher
+    pub fix: Option<FixConfig>,
+    // hash chained , signed journal of every record , checked with `logger verify`
+    pub journal: Option<JournalConfig>,
+    pub candles: Option<CandleConfig>,
+    pub order_state: Option<OrderStateConfig>,
+    pub balance_ledger: Option<BalanceLedgerConfig>,
+    pub holdings_recon: Option<HoldingsReconConfig>,
+    pub correlator: Option<CorrelatorConfig>,
+    pub market_quality: Option<MarketQualityConfig>,
+    pub surveillance: Option<SurveillanceConfig>,
+    pub user_activity: Option<UserActivityConfig>,
+    pub order_latency: Option<OrderLatencyConfig>,
+    pub rules: Option<RulesConfig>,
+    // live subscription endpoint for dashboards , fed straight from the poller
+    pub tail: Option<TailConfig>,
+    // book and tape over WebSocket for the UI
+    pub ws: Option<WsConfig>,
+    // last minutes of every stream in memory , queried over local HTTP
+    pub history: Option<HistoryConfig>,
+}
+
+impl Default for LoggerConfig {
+    fn default() -> Self {
+        Self {
+            poller_core: Some(1),
+            sequence: SequenceConfig::default(),
+            flushers: Vec::new(),
+            flush_policies: HashMap::new(),
+            derived_flush_policy: FlushPolicy::default(),
+            conflation: None,
+            book_deltas: None,
+            ingest_lag: None,
+            parquet: None,
+            jsonl: None,
+            fix: None,
+            journal: None,
+            candles: None,
+            order_state: None,
+            balance_ledger: None,
+            holdings_recon: None,
+            correlator: None,
+            market_quality: None,
+            surveillance: None,
+            user_activity: None,
+            order_latency: None,
+            rules: None,
+            tail: None,
+            ws: None,
+            history: None,
+        }
+    }
+}
+
+impl LoggerConfig {
+    // path comes from LOGGER_CONFIG , falls back to ./logger.json when present
+    pub fn load() -> Self {
+        let path = std::env::var("LOGGER_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from("logger.json"));
+
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(_) => return Self::default(),
+        };
+        serde_json::from_str(&raw)
+            .unwrap_or_else(|e| panic!("failed to parse config {}: {}", path.display(), e))
+    }
+
+    // every stream has to be owned by exactly one flusher , anything else is a config bug
+    pub fn flusher_groups(&self) -> Vec<FlusherGroupConfig> {
+        if self.flushers.is_empty() {
+            return vec![FlusherGroupConfig::single()];
+        }
+        for stream in Stream::ALL {
+            let owners = self.flushers.iter().filter(|g| g.streams.contains(&stream)).count();
+            match owners {
+                1 => {}
+                0 => panic!("stream {} is not assigned to any flusher", stream.table()),
+                _ => panic!("stream {} is assigned to {} flushers", stream.table(), owners),
+            }
+        }
+        self.flushers.clone()
+    }
+}
@@ -1,2 +1,4 @@
+pub mod config;
 pub mod logger;
-pub mod shm;
\ No newline at end of file
+pub mod shm;pub mod query;
+pub mod server;
@@ -0,0 +1,386 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::logger::processor::now_nanos;
+use crate::logger::serde_fields;
+use crate::logger::sink::LogSink;
+use crate::logger::types::{
+    BalanceLogWrapper, HoldingLogWrapper, OrderBookSnapShot, OrderLogWrapper, Stream, TradeLogs,
+};
+
+// the hash the first line chains from
+pub const GENESIS: [u8; 32] = [0; 32];
+pub const CHECKPOINT: &str = "checkpoint";
+
+// a crash can leave a partial last line , resuming looks this far back for the last full one
+const TAIL_BYTES: u64 = 1 << 20;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct JournalConfig {
+    pub dir: PathBuf,
+    // <dir>/<prefix>.log
+    pub prefix: String,
+    // ed25519 seed as hex , created with a <key>.pub next to it when missing
+    pub key_path: PathBuf,
+    // empty -> every stream
+    pub streams: Vec<Stream>,
+    // a signed checkpoint after this many records ...
+    pub checkpoint_every: u64,
+    // ... or once the last one is this old and records came in since
+    pub checkpoint_ms: u64,
+    pub flush_ms: u64,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("/tmp/logger/journal"),
+            prefix: "journal".to_string(),
+            key_path: PathBuf::from("/tmp/logger/journal.key"),
+            streams: Vec::new(),
+            checkpoint_every: 10_000,
+            checkpoint_ms: 60_000,
+            flush_ms: 100,
+        }
+    }
+}
+
+impl JournalConfig {
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(format!("{}.log", self.prefix))
+    }
+}
+
+// payload of a checkpoint line , signs the line right before it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    #[serde(with = "serde_fields::timestamp")]
+    pub timestamp: i64,
+    pub signed_seq: u64,
+    pub signed_hash: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl Checkpoint {
+    pub fn message(seq: u64, hash: &str) -> String {
+        format!("{}\t{}", seq, hash)
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex<const N: usize>(raw: &str) -> Result<[u8; N], String> {
+    let raw = raw.trim();
+    if raw.len() != N * 2 || !raw.is_ascii() {
+        return Err(format!("expected {} hex characters , got {}", N * 2, raw.len()));
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&raw[i * 2..i * 2 + 2], 16).map_err(|e| format!("invalid hex {}: {}", raw, e))?;
+    }
+    Ok(out)
+}
+
+// sha256( previous hash , "<seq>\t<stream>\t<json>" ) , so a record cannot move , change stream or change content
+pub fn chain_hash(prev: &[u8; 32], seq: u64, kind: &str, payload: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(prev);
+    hasher.update(seq.to_string().as_bytes());
+    hasher.update(b"\t");
+    hasher.update(kind.as_bytes());
+    hasher.update(b"\t");
+    hasher.update(payload);
+    hasher.finalize().into()
+}
+
+// one journal line split into its parts , <seq>\t<stream>\t<hash>\t<json>
+pub struct JournalLine<'a> {
+    pub seq: u64,
+    pub kind: &'a str,
+    pub hash: &'a str,
+    pub payload: &'a str,
+}
+
+impl<'a> JournalLine<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, String> {
+        let mut parts = line.splitn(4, '\t');
+        let (Some(seq), Some(kind), Some(hash), Some(payload)) = (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err("expected <seq> <stream> <hash> <json> separated by tabs".to_string());
+        };
+        let seq = seq.parse().map_err(|e| format!("invalid seq {}: {}", seq, e))?;
+        Ok(Self { seq, kind, hash, payload })
+    }
+}
+
+// a new key is only made while none of the journals signed with it has anything in it yet ,
+// checkpoints signed with another key would not verify against the .pub handed out for the old one
+// called once before the flushers start , they all sign with the same key
+pub fn load_or_create_key(path: &Path, journals: &[PathBuf]) -> io::Result<SigningKey> {
+    if let Ok(raw) = fs::read_to_string(path) {
+        let seed = from_hex::<32>(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        return Ok(SigningKey::from_bytes(&seed));
+    }
+    if let Some(journal) = journals.iter().find(|j| fs::metadata(j).is_ok_and(|m| m.len() > 0)) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("signing key {} is missing but {} has records , restore the key", path.display(), journal.display()),
+        ));
+    }
+    let public = path.with_extension("pub");
+    if public.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("signing key {} is missing but {} exists , not replacing it", path.display(), public.display()),
+        ));
+    }
+    let mut seed = [0u8; 32];
+    File::open("/dev/urandom")?.read_exact(&mut seed)?;
+    let key = SigningKey::from_bytes(&seed);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+    writeln!(file, "{}", to_hex(&seed))?;
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&public)?;
+    writeln!(file, "{}", to_hex(key.verifying_key().as_bytes()))?;
+    eprintln!("audit journal: created signing key {} , public key in {}", path.display(), public.display());
+    Ok(key)
+}
+
+pub fn verify_checkpoint(checkpoint: &Checkpoint, key: &VerifyingKey) -> Result<(), String> {
+    let signature = Signature::from_bytes(&from_hex::<64>(&checkpoint.signature)?);
+    key.verify(Checkpoint::message(checkpoint.signed_seq, &checkpoint.signed_hash).as_bytes(), &signature)
+        .map_err(|_| "signature does not verify".to_string())
+}
+
+// the next seq and the hash to chain from , cutting off a partial last line
+fn resume(file: &mut File) -> io::Result<(u64, [u8; 32])> {
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok((0, GENESIS));
+    }
+    let start = len.saturating_sub(TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let Some(end) = tail.iter().rposition(|b| *b == b'\n') else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no complete line in the journal tail"));
+    };
+    if end + 1 != tail.len() {
+        eprintln!("audit journal: dropping {} bytes of a partial last line", tail.len() - end - 1);
+        file.set_len(start + end as u64 + 1)?;
+    }
+    let body = &tail[..end];
+    let last = body.iter().rposition(|b| *b == b'\n').map(|i| &body[i + 1..]).unwrap_or(body);
+    let last = std::str::from_utf8(last).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let line = JournalLine::parse(last).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let hash = from_hex::<32>(line.hash).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((line.seq + 1, hash))
+}
+
+// append only journal , every record chained to the one before and signed checkpoints in between
+pub struct AuditJournal {
+    pub config: JournalConfig,
+    pub path: PathBuf,
+    pub writer: Option<BufWriter<File>>,
+    pub key: SigningKey,
+    pub next_seq: u64,
+    pub last_hash: [u8; 32],
+    pub since_checkpoint: u64,
+    pub last_checkpoint: Instant,
+    pub last_flush: Instant,
+    pub payload: Vec<u8>,
+    pub line: Vec<u8>,
+}
+
+impl AuditJournal {
+    pub fn new(config: JournalConfig, key: SigningKey) -> io::Result<Self> {
+        let path = config.path();
+        fs::create_dir_all(&config.dir)?;
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        let (next_seq, last_hash) = resume(&mut file)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            config,
+            path,
+            writer: Some(BufWriter::new(file)),
+            key,
+            next_seq,
+            last_hash,
+            since_checkpoint: 0,
+            last_checkpoint: Instant::now(),
+            last_flush: Instant::now(),
+            payload: Vec::with_capacity(2048),
+            line: Vec::with_capacity(2048),
+        })
+    }
+
+    fn wants(&self, stream: Stream) -> bool {
+        self.config.streams.is_empty() || self.config.streams.contains(&stream)
+    }
+
+    // payload holds the json to append
+    fn append(&mut self, kind: &str) {
+        let Some(writer) = self.writer.as_mut() else { return };
+        let hash = chain_hash(&self.last_hash, self.next_seq, kind, &self.payload);
+        self.line.clear();
+        let _ = write!(self.line, "{}\t{}\t{}\t", self.next_seq, kind, to_hex(&hash));
+        self.line.extend_from_slice(&self.payload);
+        self.line.push(b'\n');
+        // a line that did not make it must not be chained from
+        if let Err(e) = writer.write_all(&self.line) {
+            eprintln!("audit journal: write to {} failed , journal stopped: {}", self.path.display(), e);
+            self.writer = None;
+            return;
+        }
+        self.next_seq += 1;
+        self.last_hash = hash;
+        self.since_checkpoint += 1;
+    }
+
+    fn record<T: Serialize>(&mut self, stream: Stream, record: &T) {
+        if !self.wants(stream) {
+            return;
+        }
+        self.payload.clear();
+        if let Err(e) = serde_json::to_writer(&mut self.payload, record) {
+            eprintln!("audit journal: failed to encode record: {}", e);
+            return;
+        }
+        self.append(stream.table());
+    }
+
+    // signs the last line , then makes everything up to the checkpoint durable
+    fn checkpoint(&mut self) {
+        if self.since_checkpoint == 0 || self.next_seq == 0 {
+            return;
+        }
+        let signed_hash = to_hex(&self.last_hash);
+        let signed_seq = self.next_seq - 1;
+        let signature = self.key.sign(Checkpoint::message(signed_seq, &signed_hash).as_bytes());
+        let checkpoint = Checkpoint {
+            timestamp: now_nanos(),
+            signed_seq,
+            signed_hash,
+            public_key: to_hex(self.key.verifying_key().as_bytes()),
+            signature: to_hex(&signature.to_bytes()),
+        };
+        self.payload.clear();
+        let _ = serde_json::to_writer(&mut self.payload, &checkpoint);
+        self.append(CHECKPOINT);
+        self.since_checkpoint = 0;
+        self.last_checkpoint = Instant::now();
+        if let Some(writer) = self.writer.as_mut()
+            && let Err(e) = writer.flush().and_then(|_| writer.get_ref().sync_data())
+        {
+            eprintln!("audit journal: sync of {} failed: {}", self.path.display(), e);
+        }
+    }
+}
+
+impl LogSink for AuditJournal {
+    fn write_order_log(&mut self, log: &OrderLogWrapper) {
+        self.record(Stream::OrderLogs, log);
+    }
+
+    fn write_balance_log(&mut self, log: &BalanceLogWrapper) {
+        self.record(Stream::BalanceLogs, log);
+    }
+
+    fn write_holding_log(&mut self, log: &HoldingLogWrapper) {
+        self.record(Stream::HoldingLogs, log);
+    }
+
+    fn write_trade_log(&mut self, log: &TradeLogs) {
+        self.record(Stream::TradeLogs, log);
+    }
+
+    fn write_snapshot(&mut self, snap: &OrderBookSnapShot) {
+        self.record(Stream::Snapshots, snap);
+    }
+
+    // the journal records what was received , conflated snapshots included
+    fn every_snapshot(&self) -> bool { true }
+
+    fn tick(&mut self) {
+        if self.since_checkpoint >= self.config.checkpoint_every
+            || (self.since_checkpoint > 0
+                && self.last_checkpoint.elapsed() >= Duration::from_millis(self.config.checkpoint_ms))
+        {
+            self.checkpoint();
+        }
+        if self.last_flush.elapsed() < Duration::from_millis(self.config.flush_ms) {
+            return;
+        }
+        self.last_flush = Instant::now();
+        if let Some(writer) = self.writer.as_mut()
+            && let Err(e) = writer.flush()
+        {
+            eprintln!("audit journal: flush of {} failed , journal stopped: {}", self.path.display(), e);
+            self.writer = None;
+        }
+    }
+}
+
+impl Drop for AuditJournal {
+    fn drop(&mut self) {
+        self.checkpoint();
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("logger-journal-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn key_is_created_for_an_empty_journal_and_loaded_after() {
+        let dir = dir("create");
+        let (key_path, journal) = (dir.join("journal.key"), dir.join("journal.log"));
+        let key = load_or_create_key(&key_path, &[journal.clone()]).unwrap();
+        let public = fs::read_to_string(dir.join("journal.pub")).unwrap();
+        assert_eq!(public.trim(), to_hex(key.verifying_key().as_bytes()));
+        assert_eq!(load_or_create_key(&key_path, &[journal]).unwrap().to_bytes(), key.to_bytes());
+    }
+
+    #[test]
+    fn missing_key_is_not_replaced_for_a_journal_with_records() {
+        let dir = dir("records");
+        let key_path = dir.join("journal.key");
+        // one flusher's journal is still empty , the other one's is not
+        let journals = [dir.join("journal-a.log"), dir.join("journal-b.log")];
+        fs::write(&journals[1], "0\ttrade_logs\t00\t{}\n").unwrap();
+        assert!(load_or_create_key(&key_path, &journals).is_err());
+        assert!(!key_path.exists());
+    }
+
+    #[test]
+    fn existing_public_key_is_not_overwritten() {
+        let dir = dir("public");
+        let (key_path, journal) = (dir.join("journal.key"), dir.join("journal.log"));
+        fs::write(dir.join("journal.pub"), "handed out\n").unwrap();
+        assert!(load_or_create_key(&key_path, &[journal]).is_err());
+        assert_eq!(fs::read_to_string(dir.join("journal.pub")).unwrap(), "handed out\n");
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::LogProcessor;
+use crate::logger::types::{reason_str, BalanceLogWrapper, Stream};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BalanceLedgerConfig {
+    // JSON array of { "user_id", "available", "reserved" }
+    pub opening_balances: Option<PathBuf>,
+    // users missing from the opening file start at zero , by default their
+    // negative balances are not reported since the real opening is unknown
+    pub check_unseeded_users: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct OpeningBalance {
+    pub user_id: u64,
+    pub available: i64,
+    pub reserved: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserBalance {
+    pub available: i64,
+    pub reserved: i64,
+    pub seeded: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    NegativeAvailable,
+    NegativeReserved,
+    ReleaseWithoutLock,
+    ReleaseExceedsLock,
+}
+
+impl Violation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Violation::NegativeAvailable => "negative_available",
+            Violation::NegativeReserved => "negative_reserved",
+            Violation::ReleaseWithoutLock => "release_without_lock",
+            Violation::ReleaseExceedsLock => "release_exceeds_lock",
+        }
+    }
+}
+
+pub struct BalanceLedger {
+    pub config: BalanceLedgerConfig,
+    pub balances: HashMap<u64, UserBalance>,
+    // reserve still locked per user as ( order_id , amount ) , oldest first
+    pub locks: HashMap<u64, VecDeque<(u64, i64)>>,
+    // reserve from the opening file , locked by orders placed before startup ,
+    // a release for an order never seen locking comes off it
+    pub opening_reserved: HashMap<u64, i64>,
+    pub violations: u64,
+}
+
+pub fn load_opening_balances(path: &PathBuf) -> Result<Vec<OpeningBalance>, String> {
+    let raw = std::fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&raw).map_err(|e| format!("failed to parse {}: {}", path.display(), e))
+}
+
+impl BalanceLedger {
+    pub fn new(config: BalanceLedgerConfig) -> Result<Self, String> {
+        let mut balances = HashMap::new();
+        let mut opening_reserved = HashMap::new();
+        if let Some(path) = &config.opening_balances {
+            for o in load_opening_balances(path)? {
+                balances.insert(o.user_id, UserBalance { available: o.available, reserved: o.reserved, seeded: true });
+                if o.reserved > 0 {
+                    opening_reserved.insert(o.user_id, o.reserved);
+                }
+            }
+        }
+        Ok(Self {
+            config,
+            balances,
+            locks: HashMap::new(),
+            opening_reserved,
+            violations: 0,
+        })
+    }
+
+    fn locked(&self, user_id: u64, order_id: u64) -> i64 {
+        self.locks.get(&user_id).and_then(|held| held.iter().find(|(id, _)| *id == order_id)).map_or(0, |(_, amount)| *amount)
+    }
+
+    // a release comes off the lock of its own order , then off the user's other locks oldest first
+    // ( a maker's release carries the taker's order id ) , then off the opening reserve
+    // returns what is left uncovered and what the user had locked before the release
+    fn release(&mut self, user_id: u64, order_id: u64, mut released: i64) -> (i64, i64) {
+        let mut locked = 0;
+        if let Some(held) = self.locks.get_mut(&user_id) {
+            locked = held.iter().map(|(_, amount)| amount).sum();
+            if let Some(pos) = held.iter().position(|(id, _)| *id == order_id) {
+                let taken = released.min(held[pos].1);
+                held[pos].1 -= taken;
+                released -= taken;
+            }
+            for (_, amount) in held.iter_mut() {
+                let taken = released.min(*amount);
+                *amount -= taken;
+                released -= taken;
+            }
+            held.retain(|(_, amount)| *amount > 0);
+            if held.is_empty() {
+                self.locks.remove(&user_id);
+            }
+        }
+        if released > 0
+            && let Some(opening) = self.opening_reserved.get_mut(&user_id)
+        {
+            let taken = released.min(*opening);
+            *opening -= taken;
+            released -= taken;
+            if *opening == 0 {
+                self.opening_reserved.remove(&user_id);
+            }
+        }
+        (released, locked)
+    }
+
+    fn emit_ledger(out: &mut Buffer, log: &BalanceLogWrapper, balance: &UserBalance) -> questdb::Result<()> {
+        let delta = &log.balance_delta;
+        out.table("balance_ledger")?
+            .symbol("reason", reason_str(delta.reason))?
+            .column_i64("event_id", delta.event_id as i64)?
+            .column_i64("user_id", delta.user_id as i64)?
+            .column_i64("order_id", delta.order_id as i64)?
+            .column_i64("delta_available_balance", delta.delta_available)?
+            .column_i64("delta_reserved_balance", delta.delta_reserved)?
+            .column_i64("available_balance", balance.available)?
+            .column_i64("reserved_balance", balance.reserved)?
+            .column_bool("seeded", balance.seeded)?
+            .at(TimestampNanos::new(log.timestamp))
+    }
+
+    fn emit_violation(
+        &mut self,
+        out: &mut Buffer,
+        log: &BalanceLogWrapper,
+        balance: &UserBalance,
+        violation: Violation,
+        locked: i64,
+    ) -> questdb::Result<()> {
+        let delta = &log.balance_delta;
+        self.violations += 1;
+        eprintln!(
+            "balance ledger: {} for user {} order {} event {} (available {} reserved {} locked {})",
+            violation.as_str(), delta.user_id, delta.order_id, delta.event_id, balance.available, balance.reserved, locked
+        );
+        out.table("balance_violations")?
+            .symbol("violation", violation.as_str())?
+            .symbol("reason", reason_str(delta.reason))?
+            .column_i64("event_id", delta.event_id as i64)?
+            .column_i64("user_id", delta.user_id as i64)?
+            .column_i64("order_id", delta.order_id as i64)?
+            .column_i64("delta_available_balance", delta.delta_available)?
+            .column_i64("delta_reserved_balance", delta.delta_reserved)?
+            .column_i64("available_balance", balance.available)?
+            .column_i64("reserved_balance", balance.reserved)?
+            .column_i64("locked_for_order", locked)?
+            .at(TimestampNanos::new(log.timestamp))
+    }
+}
+
+impl LogProcessor for BalanceLedger {
+    fn streams(&self) -> &'static [Stream] {
+        &[Stream::BalanceLogs]
+    }
+
+    fn on_balance_log(&mut self, log: &BalanceLogWrapper, out: &mut Buffer) -> questdb::Result<()> {
+        let delta = &log.balance_delta;
+        let balance = self.balances.entry(delta.user_id).or_default();
+        balance.available += delta.delta_available;
+        balance.reserved += delta.delta_reserved;
+        let balance = *balance;
+        Self::emit_ledger(out, log, &balance)?;
+
+        let mut found: Vec<(Violation, i64)> = Vec::new();
+        // an unseeded user may hold reserve locked before startup , like its negative balances
+        // its releases are only checked when asked to
+        let checked = balance.seeded || self.config.check_unseeded_users;
+
+        // the engine puts the taker's order id on every delta of a fill ( see BalanceDelta::order_id ) ,
+        // so only a lock is sure to carry the order of its own user , a release is matched against
+        // that order when the user locked under it and against the user's other locks otherwise
+        if delta.delta_reserved > 0 {
+            let held = self.locks.entry(delta.user_id).or_default();
+            match held.iter_mut().find(|(id, _)| *id == delta.order_id) {
+                Some((_, amount)) => *amount += delta.delta_reserved,
+                None => held.push_back((delta.order_id, delta.delta_reserved)),
+            }
+        } else if delta.delta_reserved < 0 {
+            let own = self.locked(delta.user_id, delta.order_id);
+            let (uncovered, locked) = self.release(delta.user_id, delta.order_id, -delta.delta_reserved);
+            if checked && uncovered > 0 {
+                if locked == 0 {
+                    found.push((Violation::ReleaseWithoutLock, 0));
+                } else {
+                    found.push((Violation::ReleaseExceedsLock, own));
+                }
+            }
+        }
+
+        if checked {
+            let locked = self.locked(delta.user_id, delta.order_id);
+            if balance.available < 0 {
+                found.push((Violation::NegativeAvailable, locked));
+            }
+            if balance.reserved < 0 {
+                found.push((Violation::NegativeReserved, locked));
+            }
+        }
+
+        for (violation, locked) in found {
+            self.emit_violation(out, log, &balance, violation, locked)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::balance;
+    use questdb::ingress::ProtocolVersion;
+
+    fn violations(out: &Buffer) -> Vec<String> {
+        std::str::from_utf8(out.as_bytes())
+            .unwrap()
+            .lines()
+            .filter_map(|row| row.strip_prefix("balance_violations,violation="))
+            .map(|row| row.split(',').next().unwrap().to_string())
+            .collect()
+    }
+
+    fn ledger(check_unseeded_users: bool) -> BalanceLedger {
+        BalanceLedger::new(BalanceLedgerConfig { opening_balances: None, check_unseeded_users }).unwrap()
+    }
+
+    #[test]
+    fn release_of_the_locking_order_is_clean() {
+        let mut ledger = ledger(true);
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(1_000, 0), &mut out).unwrap();
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(-300, 300), &mut out).unwrap();
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(0, -200), &mut out).unwrap();
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(100, -100), &mut out).unwrap();
+
+        assert!(violations(&out).is_empty());
+        assert!(ledger.locks.is_empty());
+    }
+
+    #[test]
+    fn release_exceeding_the_users_locks_is_reported() {
+        let mut ledger = ledger(true);
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(1_000, 0), &mut out).unwrap();
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(-500, 500), &mut out).unwrap();
+        // nothing locked for order 11 , the release comes off the lock of order 10
+        ledger.on_balance_log(&balance(11, 1).at(1).deltas(0, -100), &mut out).unwrap();
+        assert!(violations(&out).is_empty());
+        // order 10 releases more than the user has locked
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(0, -600), &mut out).unwrap();
+
+        assert_eq!(violations(&out), ["release_exceeds_lock", "negative_reserved"]);
+        assert!(std::str::from_utf8(out.as_bytes()).unwrap().contains("locked_for_order=400i"));
+    }
+
+    #[test]
+    fn maker_release_under_the_takers_order_is_clean() {
+        let mut ledger = ledger(true);
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(1_000, 0), &mut out).unwrap();
+        ledger.on_balance_log(&balance(20, 2).at(1).deltas(1_000, 0), &mut out).unwrap();
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(-500, 500), &mut out).unwrap();
+        ledger.on_balance_log(&balance(20, 2).at(1).deltas(-300, 300), &mut out).unwrap();
+        // taker order 10 fills against maker order 20 , both deltas carry order 10
+        ledger.on_balance_log(&balance(10, 1).at(2).deltas(0, -300), &mut out).unwrap();
+        ledger.on_balance_log(&balance(10, 2).at(2).deltas(0, -300), &mut out).unwrap();
+
+        assert!(violations(&out).is_empty());
+        assert_eq!(ledger.locked(1, 10), 200);
+        assert!(!ledger.locks.contains_key(&2));
+    }
+
+    #[test]
+    fn unseeded_users_are_only_checked_when_asked() {
+        let mut quiet = ledger(false);
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        // reserve locked before startup , the ledger never saw the lock
+        quiet.on_balance_log(&balance(10, 1).at(1).deltas(0, -100), &mut out).unwrap();
+        assert!(violations(&out).is_empty());
+
+        let mut strict = ledger(true);
+        strict.on_balance_log(&balance(10, 1).at(1).deltas(0, -100), &mut out).unwrap();
+        assert_eq!(violations(&out), ["release_without_lock", "negative_reserved"]);
+    }
+
+    #[test]
+    fn opening_reserve_covers_releases_of_earlier_orders() {
+        let path = std::env::temp_dir().join(format!("logger-ledger-{}.json", std::process::id()));
+        std::fs::write(&path, r#"[{ "user_id": 1, "available": 0, "reserved": 300 }]"#).unwrap();
+        let mut ledger = BalanceLedger::new(BalanceLedgerConfig { opening_balances: Some(path.clone()), check_unseeded_users: false }).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        ledger.on_balance_log(&balance(10, 1).at(1).deltas(200, -200), &mut out).unwrap();
+        assert!(violations(&out).is_empty());
+        ledger.on_balance_log(&balance(11, 1).at(1).deltas(0, -200), &mut out).unwrap();
+        assert_eq!(violations(&out), ["release_without_lock", "negative_reserved"]);
+    }
+}
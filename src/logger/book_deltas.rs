@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::NANOS_PER_MILLI;
+use crate::logger::types::OrderBookSnapShot;
+
+pub const BID: u8 = 0;
+pub const ASK: u8 = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BookDeltaConfig {
+    // a full snapshot is stored after this many delta snapshots ...
+    pub keyframe_every: u32,
+    // ... or once this much producer time passed since the last one
+    pub keyframe_interval_ms: u64,
+    // a book_delta_stats row with the counts is written every report_ms
+    pub report_ms: u64,
+}
+
+impl Default for BookDeltaConfig {
+    fn default() -> Self {
+        Self {
+            keyframe_every: 100,
+            keyframe_interval_ms: 60_000,
+            report_ms: 10_000,
+        }
+    }
+}
+
+// one level whose quantity changed , new_qty 0 is a level that left the 20 level window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelChange {
+    pub side: u8,
+    pub price: u64,
+    pub old_qty: u32,
+    pub new_qty: u32,
+}
+
+// zero quantity levels are padding , not part of the book
+fn levels(side: &[(u64, u32); 20]) -> HashMap<u64, u32> {
+    side.iter().filter(|(_, q)| *q > 0).copied().collect()
+}
+
+pub fn diff_side(side: u8, prev: &[(u64, u32); 20], cur: &[(u64, u32); 20], out: &mut Vec<LevelChange>) {
+    let prev = levels(prev);
+    let cur = levels(cur);
+    let start = out.len();
+    for (price, new_qty) in &cur {
+        let old_qty = prev.get(price).copied().unwrap_or(0);
+        if old_qty != *new_qty {
+            out.push(LevelChange { side, price: *price, old_qty, new_qty: *new_qty });
+        }
+    }
+    for (price, old_qty) in &prev {
+        if !cur.contains_key(price) {
+            out.push(LevelChange { side, price: *price, old_qty: *old_qty, new_qty: 0 });
+        }
+    }
+    // best price first , so rows of one snapshot read like the book
+    out[start..].sort_by_key(|c| if side == BID { u64::MAX - c.price } else { c.price });
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BookState {
+    pub last: OrderBookSnapShot,
+    pub since_keyframe: u32,
+    pub keyframe_at: i64,
+}
+
+pub struct BookDiffer {
+    pub config: BookDeltaConfig,
+    pub books: HashMap<u32, BookState>,
+    // since the last report
+    pub keyframes: u64,
+    pub deltas: u64,
+    pub changes: u64,
+    pub last_report: i64,
+}
+
+impl BookDiffer {
+    pub fn new(config: BookDeltaConfig) -> Self {
+        Self {
+            config,
+            books: HashMap::new(),
+            keyframes: 0,
+            deltas: 0,
+            changes: 0,
+            last_report: 0,
+        }
+    }
+
+    // changes per delta snapshot is what the deltas save over storing every book in full
+    pub fn report(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        if now - self.last_report < self.config.report_ms as i64 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.last_report = now;
+        if self.keyframes == 0 && self.deltas == 0 {
+            return Ok(());
+        }
+        out.table("book_delta_stats")?
+            .column_i64("keyframes", self.keyframes as i64)?
+            .column_i64("delta_snapshots", self.deltas as i64)?
+            .column_i64("changes", self.changes as i64)?
+            .column_i64("interval_ms", self.config.report_ms as i64)?
+            .at(TimestampNanos::new(now))?;
+        self.keyframes = 0;
+        self.deltas = 0;
+        self.changes = 0;
+        Ok(())
+    }
+
+    // true when the snapshot has to be stored in full , otherwise its changes are in `out`
+    pub fn next(&mut self, snap: &OrderBookSnapShot, out: &mut Vec<LevelChange>) -> bool {
+        out.clear();
+        let interval = self.config.keyframe_interval_ms as i64 * NANOS_PER_MILLI;
+        let keyframe = match self.books.get(&snap.symbol) {
+            None => true,
+            Some(book) => {
+                book.since_keyframe >= self.config.keyframe_every
+                    || snap.timestamp - book.keyframe_at >= interval
+                    // an older snapshot than the last one cannot be expressed as a change on top of it
+                    || snap.timestamp < book.last.timestamp
+            }
+        };
+
+        if keyframe {
+            self.keyframes += 1;
+            self.books.insert(snap.symbol, BookState { last: *snap, since_keyframe: 0, keyframe_at: snap.timestamp });
+            return true;
+        }
+
+        let book = self.books.get_mut(&snap.symbol).unwrap();
+        diff_side(BID, &book.last.bids, &snap.bids, out);
+        diff_side(ASK, &book.last.asks, &snap.asks, out);
+        book.last = *snap;
+        book.since_keyframe += 1;
+        self.deltas += 1;
+        self.changes += out.len() as u64;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::snapshot;
+
+    const SEC: i64 = 1_000 * NANOS_PER_MILLI;
+
+    // what the book query does : the keyframe , then every change on top of it
+    fn replay(book: &mut [HashMap<u64, u32>; 2], changes: &[LevelChange]) {
+        for change in changes {
+            let side = &mut book[change.side as usize];
+            assert_eq!(side.get(&change.price).copied().unwrap_or(0), change.old_qty);
+            if change.new_qty == 0 {
+                side.remove(&change.price);
+            } else {
+                side.insert(change.price, change.new_qty);
+            }
+        }
+    }
+
+    fn differ(keyframe_every: u32) -> BookDiffer {
+        BookDiffer::new(BookDeltaConfig { keyframe_every, keyframe_interval_ms: 60_000, report_ms: 1_000 })
+    }
+
+    #[test]
+    fn first_snapshot_is_a_keyframe_and_an_unchanged_book_has_no_changes() {
+        let mut differ = differ(100);
+        let mut out = Vec::new();
+        let book = snapshot(&[(100, 5)], &[(101, 7)]).at(SEC);
+        assert!(differ.next(&book, &mut out));
+        assert!(!differ.next(&snapshot(&[(100, 5)], &[(101, 7)]).at(2 * SEC), &mut out));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn changes_rebuild_every_book_from_the_keyframe() {
+        let books = [
+            snapshot(&[(100, 5), (99, 3), (98, 1)], &[(101, 7), (102, 2)]).at(SEC),
+            snapshot(&[(100, 4), (99, 3), (98, 1)], &[(101, 7), (102, 2)]).at(2 * SEC),
+            // best bid traded away , a new level came in at the bottom
+            snapshot(&[(99, 3), (98, 1), (97, 9)], &[(101, 7), (102, 2)]).at(3 * SEC),
+            // a level moved inside the 20 level window , prices swapped places in the array
+            snapshot(&[(99, 3), (97, 9), (98, 2)], &[(100, 1), (102, 2)]).at(4 * SEC),
+            snapshot(&[], &[]).at(5 * SEC),
+            snapshot(&[(95, 1)], &[(105, 4), (106, 4)]).at(6 * SEC),
+        ];
+        let mut differ = differ(100);
+        let mut out = Vec::new();
+        assert!(differ.next(&books[0], &mut out));
+        let mut book = [levels(&books[0].bids), levels(&books[0].asks)];
+        for snap in &books[1..] {
+            assert!(!differ.next(snap, &mut out));
+            replay(&mut book, &out);
+            assert_eq!(book, [levels(&snap.bids), levels(&snap.asks)]);
+        }
+        assert_eq!(differ.keyframes, 1);
+        assert_eq!(differ.deltas, 5);
+    }
+
+    #[test]
+    fn changes_come_best_price_first() {
+        let mut differ = differ(100);
+        let mut out = Vec::new();
+        differ.next(&snapshot(&[(100, 1), (99, 1), (98, 1)], &[(101, 1), (102, 1), (103, 1)]).at(SEC), &mut out);
+        differ.next(&snapshot(&[(100, 2), (99, 2), (98, 2)], &[(101, 2), (102, 2), (103, 2)]).at(2 * SEC), &mut out);
+        let prices: Vec<(u8, u64)> = out.iter().map(|c| (c.side, c.price)).collect();
+        assert_eq!(prices, [(BID, 100), (BID, 99), (BID, 98), (ASK, 101), (ASK, 102), (ASK, 103)]);
+    }
+
+    #[test]
+    fn keyframes_by_count_interval_and_out_of_order() {
+        let mut differ = differ(2);
+        let mut out = Vec::new();
+        let at = |ts| snapshot(&[(100, 1)], &[(101, 1)]).at(ts);
+        assert!(differ.next(&at(SEC), &mut out));
+        assert!(!differ.next(&at(2 * SEC), &mut out));
+        assert!(!differ.next(&at(3 * SEC), &mut out));
+        assert!(differ.next(&at(4 * SEC), &mut out));
+        // older than the last snapshot
+        assert!(differ.next(&at(3 * SEC), &mut out));
+        assert!(!differ.next(&at(5 * SEC), &mut out));
+        // a minute of producer time since the keyframe
+        assert!(differ.next(&at(63 * SEC), &mut out));
+        assert_eq!(differ.keyframes, 4);
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::{HashMap, VecDeque};
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::{LogProcessor, NANOS_PER_MILLI};
+use crate::logger::types::{Stream, TradeLogs};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CandleConfig {
+    pub intervals_ms: Vec<u64>,
+    // an open bar is closed this long after its end even if no later trade shows up
+    pub close_grace_ms: u64,
+    // trades for bars closed less than this long ago re-emit the bar with revision + 1
+    pub late_window_ms: u64,
+}
+
+impl Default for CandleConfig {
+    fn default() -> Self {
+        Self {
+            intervals_ms: vec![1_000, 60_000, 300_000, 3_600_000],
+            close_grace_ms: 250,
+            late_window_ms: 60_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Bar {
+    pub start: i64,
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    pub volume: u64,
+    pub buy_volume: u64,
+    pub sell_volume: u64,
+    pub trade_count: u64,
+    pub notional: u128,
+    pub revision: u32,
+}
+
+impl Bar {
+    fn new(start: i64, trade: &TradeLogs) -> Self {
+        let mut bar = Self {
+            start,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: 0,
+            buy_volume: 0,
+            sell_volume: 0,
+            trade_count: 0,
+            notional: 0,
+            revision: 0,
+        };
+        bar.add(trade, false);
+        bar
+    }
+
+    // late trades only widen the range , open and close stay with the in order trades
+    fn add(&mut self, trade: &TradeLogs, late: bool) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        if !late {
+            self.close = trade.price;
+        }
+        let qty = trade.quantity as u64;
+        self.volume += qty;
+        // buyer is maker -> the seller hit the bid
+        if trade.is_buyer_maker {
+            self.sell_volume += qty;
+        } else {
+            self.buy_volume += qty;
+        }
+        self.trade_count += 1;
+        self.notional += trade.price as u128 * qty as u128;
+    }
+
+    fn vwap(&self) -> f64 {
+        if self.volume == 0 {
+            return 0.0;
+        }
+        self.notional as f64 / self.volume as f64
+    }
+}
+
+// bars of one symbol at one interval
+#[derive(Default)]
+pub struct CandleSeries {
+    pub open: Option<Bar>,
+    // closed bars still inside the late window , oldest first
+    pub closed: VecDeque<Bar>,
+}
+
+pub struct CandleAggregator {
+    pub config: CandleConfig,
+    // (interval label , interval in nanos)
+    pub intervals: Vec<(String, i64)>,
+    pub series: HashMap<(u32, usize), CandleSeries>,
+    // wall clock of the last tick , bars ending before now - late window are final
+    pub now: i64,
+    pub dropped_late: u64,
+    // drops already printed , late trades are reported from the tick and not per trade
+    pub drops_reported: u64,
+    pub drops_reported_at: i64,
+}
+
+const DROPS_REPORT_EVERY: i64 = 10_000 * NANOS_PER_MILLI;
+
+pub fn interval_label(ms: u64) -> String {
+    if ms.is_multiple_of(3_600_000) {
+        format!("{}h", ms / 3_600_000)
+    } else if ms.is_multiple_of(60_000) {
+        format!("{}m", ms / 60_000)
+    } else if ms.is_multiple_of(1_000) {
+        format!("{}s", ms / 1_000)
+    } else {
+        format!("{}ms", ms)
+    }
+}
+
+impl CandleAggregator {
+    pub fn new(config: CandleConfig) -> Self {
+        let intervals = config
+            .intervals_ms
+            .iter()
+            .filter(|ms| **ms > 0)
+            .map(|ms| (interval_label(*ms), *ms as i64 * NANOS_PER_MILLI))
+            .collect();
+        Self {
+            config,
+            intervals,
+            series: HashMap::new(),
+            now: 0,
+            dropped_late: 0,
+            drops_reported: 0,
+            drops_reported_at: 0,
+        }
+    }
+
+    // QuestDB side : candles should be created with DEDUP UPSERT KEYS(timestamp, symbol, interval)
+    // so a revised bar replaces the earlier row
+    fn emit(out: &mut Buffer, symbol: u32, interval: &str, bar: &Bar) -> questdb::Result<()> {
+        out.table("candles")?
+            .symbol("symbol", symbol.to_string())?
+            .symbol("interval", interval)?
+            .column_i64("open", bar.open as i64)?
+            .column_i64("high", bar.high as i64)?
+            .column_i64("low", bar.low as i64)?
+            .column_i64("close", bar.close as i64)?
+            .column_i64("volume", bar.volume as i64)?
+            .column_i64("buy_volume", bar.buy_volume as i64)?
+            .column_i64("sell_volume", bar.sell_volume as i64)?
+            .column_i64("trade_count", bar.trade_count as i64)?
+            .column_f64("vwap", bar.vwap())?
+            .column_i64("revision", bar.revision as i64)?
+            .at(TimestampNanos::new(bar.start))
+    }
+
+    fn add_trade(&mut self, idx: usize, trade: &TradeLogs, out: &mut Buffer) -> questdb::Result<()> {
+        let (label, width) = &self.intervals[idx];
+        let start = trade.timestamp - trade.timestamp.rem_euclid(*width);
+        if start + width + self.config.late_window_ms as i64 * NANOS_PER_MILLI < self.now {
+            self.dropped_late += 1;
+            return Ok(());
+        }
+        let series = self.series.entry((trade.symbol, idx)).or_default();
+
+        if let Some(bar) = series.open.as_mut() {
+            if start == bar.start {
+                bar.add(trade, false);
+                return Ok(());
+            }
+            if start > bar.start {
+                let done = *bar;
+                series.open = Some(Bar::new(start, trade));
+                series.closed.push_back(done);
+                return Self::emit(out, trade.symbol, label, &done);
+            }
+        } else if series.closed.back().is_none_or(|last| start > last.start) {
+            series.open = Some(Bar::new(start, trade));
+            return Ok(());
+        }
+
+        // late trade , for a bar that is already closed or was never opened
+        let pos = series.closed.partition_point(|b| b.start < start);
+        match series.closed.get_mut(pos) {
+            Some(bar) if bar.start == start => {
+                bar.add(trade, true);
+                bar.revision += 1;
+                Self::emit(out, trade.symbol, label, bar)
+            }
+            _ => {
+                let bar = Bar::new(start, trade);
+                series.closed.insert(pos, bar);
+                Self::emit(out, trade.symbol, label, &bar)
+            }
+        }
+    }
+}
+
+impl LogProcessor for CandleAggregator {
+    fn streams(&self) -> &'static [Stream] {
+        &[Stream::TradeLogs]
+    }
+
+    fn on_trade_log(&mut self, log: &TradeLogs, out: &mut Buffer) -> questdb::Result<()> {
+        for idx in 0..self.intervals.len() {
+            self.add_trade(idx, log, out)?;
+        }
+        Ok(())
+    }
+
+    fn on_tick(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        self.now = now;
+        let grace = self.config.close_grace_ms as i64 * NANOS_PER_MILLI;
+        let late_window = self.config.late_window_ms as i64 * NANOS_PER_MILLI;
+        for ((symbol, idx), series) in self.series.iter_mut() {
+            let (label, width) = &self.intervals[*idx];
+            if let Some(bar) = series.open
+                && bar.start + width + grace <= now
+            {
+                series.open = None;
+                series.closed.push_back(bar);
+                Self::emit(out, *symbol, label, &bar)?;
+            }
+            while series.closed.front().is_some_and(|b| b.start + width + late_window < now) {
+                series.closed.pop_front();
+            }
+        }
+        self.series.retain(|_, s| s.open.is_some() || !s.closed.is_empty());
+
+        if self.dropped_late > self.drops_reported && now - self.drops_reported_at >= DROPS_REPORT_EVERY {
+            eprintln!(
+                "candles: dropped {} late trades for bars already final ({} so far)",
+                self.dropped_late - self.drops_reported, self.dropped_late
+            );
+            self.drops_reported = self.dropped_late;
+            self.drops_reported_at = now;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::trade;
+    use questdb::ingress::ProtocolVersion;
+
+    const SEC: i64 = 1_000 * NANOS_PER_MILLI;
+
+    fn aggregator() -> CandleAggregator {
+        CandleAggregator::new(CandleConfig { intervals_ms: vec![1_000], close_grace_ms: 250, late_window_ms: 5_000 })
+    }
+
+    fn rows(out: &Buffer) -> Vec<String> {
+        std::str::from_utf8(out.as_bytes()).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(interval_label(500), "500ms");
+        assert_eq!(interval_label(1_000), "1s");
+        assert_eq!(interval_label(300_000), "5m");
+        assert_eq!(interval_label(7_200_000), "2h");
+    }
+
+    #[test]
+    fn next_bar_closes_the_open_one() {
+        let mut candles = aggregator();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        candles.on_trade_log(&trade(1, 2).at(10 * SEC + 100).qty(2), &mut out).unwrap();
+        candles.on_trade_log(&trade(1, 2).at(10 * SEC + 200).price(104).buyer_maker(true), &mut out).unwrap();
+        candles.on_trade_log(&trade(1, 2).at(10 * SEC + 300).price(98).qty(3), &mut out).unwrap();
+        assert_eq!(out.row_count(), 0);
+
+        candles.on_trade_log(&trade(1, 2).at(11 * SEC).price(101), &mut out).unwrap();
+        assert_eq!(
+            rows(&out),
+            [format!(
+                "candles,symbol=7,interval=1s open=100i,high=104i,low=98i,close=98i,volume=6i,buy_volume=5i,\
+                 sell_volume=1i,trade_count=3i,vwap=99.66666666666667,revision=0i {}",
+                10 * SEC
+            )]
+        );
+        let series = &candles.series[&(7, 0)];
+        assert_eq!(series.open.unwrap().start, 11 * SEC);
+        assert_eq!(series.closed.len(), 1);
+    }
+
+    #[test]
+    fn tick_closes_a_quiet_bar_after_the_grace() {
+        let mut candles = aggregator();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        candles.on_trade_log(&trade(1, 2).at(10 * SEC), &mut out).unwrap();
+        candles.on_tick(11 * SEC + 249 * NANOS_PER_MILLI, &mut out).unwrap();
+        assert_eq!(out.row_count(), 0);
+        candles.on_tick(11 * SEC + 250 * NANOS_PER_MILLI, &mut out).unwrap();
+        assert_eq!(out.row_count(), 1);
+        assert!(candles.series[&(7, 0)].open.is_none());
+
+        // closed bars are forgotten once the late window is over
+        candles.on_tick(16 * SEC + 1, &mut out).unwrap();
+        assert!(candles.series.is_empty());
+    }
+
+    #[test]
+    fn late_trade_revises_the_closed_bar() {
+        let mut candles = aggregator();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        candles.on_trade_log(&trade(1, 2).at(10 * SEC), &mut out).unwrap();
+        candles.on_trade_log(&trade(1, 2).at(11 * SEC).price(101), &mut out).unwrap();
+        out.clear();
+
+        candles.on_trade_log(&trade(1, 2).at(10 * SEC + 500).price(90).qty(4).buyer_maker(true), &mut out).unwrap();
+        let rows = rows(&out);
+        assert_eq!(rows.len(), 1);
+        // the range widens , open and close stay with the in order trades
+        assert!(rows[0].contains("open=100i,high=100i,low=90i,close=100i,volume=5i,buy_volume=1i,sell_volume=4i"));
+        assert!(rows[0].contains("revision=1i"));
+        assert!(rows[0].ends_with(&(10 * SEC).to_string()));
+        assert_eq!(candles.series[&(7, 0)].open.unwrap().volume, 1);
+    }
+
+    #[test]
+    fn late_trade_for_a_bar_never_opened_emits_it() {
+        let mut candles = aggregator();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        candles.on_trade_log(&trade(1, 2).at(10 * SEC), &mut out).unwrap();
+        candles.on_trade_log(&trade(1, 2).at(12 * SEC).price(101), &mut out).unwrap();
+        out.clear();
+
+        candles.on_trade_log(&trade(1, 2).at(11 * SEC).price(95).qty(2), &mut out).unwrap();
+        let rows = rows(&out);
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].contains("open=95i") && rows[0].contains("revision=0i"));
+        let starts: Vec<i64> = candles.series[&(7, 0)].closed.iter().map(|b| b.start).collect();
+        assert_eq!(starts, [10 * SEC, 11 * SEC]);
+    }
+
+    #[test]
+    fn trade_past_the_late_window_is_dropped() {
+        let mut candles = aggregator();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        candles.on_tick(20 * SEC, &mut out).unwrap();
+        candles.on_trade_log(&trade(1, 2).at(10 * SEC), &mut out).unwrap();
+        assert_eq!(out.row_count(), 0);
+        assert_eq!(candles.dropped_late, 1);
+        assert!(candles.series.is_empty());
+
+        // the drop is reported from the next tick
+        candles.on_tick(20 * SEC + 1, &mut out).unwrap();
+        assert_eq!(candles.drops_reported, 1);
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::NANOS_PER_MILLI;
+use crate::logger::types::OrderBookSnapShot;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ConflationConfig {
+    pub interval_ms: u64,
+    // snapshot_conflation rows with the kept / conflated counts are written every report_ms
+    pub report_ms: u64,
+}
+
+impl Default for ConflationConfig {
+    fn default() -> Self {
+        Self { interval_ms: 100, report_ms: 10_000 }
+    }
+}
+
+// a snapshot that made it to storage , with how many it replaced
+#[derive(Debug, Clone, Copy)]
+pub struct StoredSnapshot {
+    pub snap: OrderBookSnapShot,
+    pub conflated: u64,
+    // poller dequeue and flusher pick up times , for ingest lag
+    pub dequeued_at: i64,
+    pub picked_up: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SymbolConflation {
+    // latest snapshot of the open interval , not stored yet
+    pub pending: Option<StoredSnapshot>,
+    pub window_end: i64,
+    pub last_seen: i64,
+    // producer timestamp of the latest trade
+    pub last_trade: i64,
+    // since the last report
+    pub kept: u64,
+    pub conflated: u64,
+}
+
+// keeps the latest snapshot per symbol per interval , the first snapshot after a quiet
+// interval and the first after a trade are stored right away
+pub struct SnapshotConflator {
+    pub config: ConflationConfig,
+    pub interval: i64,
+    pub symbols: HashMap<u32, SymbolConflation>,
+    pub last_report: i64,
+}
+
+impl SnapshotConflator {
+    pub fn new(config: ConflationConfig) -> Self {
+        Self {
+            interval: config.interval_ms.max(1) as i64 * NANOS_PER_MILLI,
+            config,
+            symbols: HashMap::new(),
+            last_report: 0,
+        }
+    }
+
+    fn keep(&mut self, symbol: u32, stored: StoredSnapshot, out: &mut Vec<StoredSnapshot>) {
+        if let Some(state) = self.symbols.get_mut(&symbol) {
+            state.kept += 1;
+        }
+        out.push(stored);
+    }
+
+    pub fn offer(&mut self, stored: StoredSnapshot, out: &mut Vec<StoredSnapshot>) {
+        let snap = stored.snap;
+        let ts = snap.timestamp;
+        let window_end = ts - ts.rem_euclid(self.interval) + self.interval;
+        let Some(state) = self.symbols.get_mut(&snap.symbol) else {
+            self.symbols.insert(snap.symbol, SymbolConflation {
+                pending: None,
+                window_end,
+                last_seen: ts,
+                last_trade: 0,
+                kept: 0,
+                conflated: 0,
+            });
+            return self.keep(snap.symbol, stored, out);
+        };
+
+        let quiet = ts - state.last_seen >= self.interval;
+        // trades are noted before the snapshots of the same flusher loop , so the trade
+        // timestamp decides whether it fell between the previous snapshot and this one
+        let trade = state.last_trade > state.last_seen && state.last_trade <= ts;
+        let new_window = ts >= state.window_end;
+        state.last_seen = ts;
+
+        if quiet || trade || new_window {
+            // the pending one was the latest of its interval , or the book right before the trade
+            let pending = state.pending.take();
+            state.window_end = window_end;
+            if let Some(pending) = pending {
+                self.keep(snap.symbol, pending, out);
+            }
+            if quiet || trade {
+                return self.keep(snap.symbol, stored, out);
+            }
+            self.symbols.get_mut(&snap.symbol).unwrap().pending = Some(stored);
+            return;
+        }
+
+        let conflated = match state.pending {
+            Some(replaced) => {
+                state.conflated += 1;
+                replaced.conflated + 1
+            }
+            None => 0,
+        };
+        state.pending = Some(StoredSnapshot { conflated, ..stored });
+    }
+
+    pub fn note_trade(&mut self, symbol: u32, timestamp: i64) {
+        if let Some(state) = self.symbols.get_mut(&symbol) {
+            state.last_trade = state.last_trade.max(timestamp);
+        }
+    }
+
+    // one row per symbol that saw snapshots since the last report
+    pub fn report(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        if now - self.last_report < self.config.report_ms as i64 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.last_report = now;
+        for (symbol, state) in self.symbols.iter_mut() {
+            if state.kept == 0 && state.conflated == 0 {
+                continue;
+            }
+            out.table("snapshot_conflation")?
+                .symbol("symbol", symbol.to_string())?
+                .column_i64("kept", state.kept as i64)?
+                .column_i64("conflated", state.conflated as i64)?
+                .column_i64("report_ms", self.config.report_ms as i64)?
+                .at(TimestampNanos::new(now))?;
+            state.kept = 0;
+            state.conflated = 0;
+        }
+        Ok(())
+    }
+
+    // stores the pending snapshot of every interval that ended by the wall clock
+    pub fn on_tick(&mut self, now: i64, out: &mut Vec<StoredSnapshot>) {
+        let due: Vec<u32> = self
+            .symbols
+            .iter()
+            .filter(|(_, s)| s.pending.is_some() && now >= s.window_end)
+            .map(|(symbol, _)| *symbol)
+            .collect();
+        for symbol in due {
+            let pending = self.symbols.get_mut(&symbol).unwrap().pending.take().unwrap();
+            self.keep(symbol, pending, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::snapshot;
+    use questdb::ingress::ProtocolVersion;
+
+    const MS: i64 = NANOS_PER_MILLI;
+
+    fn stored(timestamp: i64) -> StoredSnapshot {
+        let snap = snapshot(&[], &[]).at(timestamp).event_id(timestamp as u64);
+        StoredSnapshot { snap, conflated: 0, dequeued_at: 0, picked_up: 0 }
+    }
+
+    fn conflator() -> SnapshotConflator {
+        SnapshotConflator::new(ConflationConfig { interval_ms: 100, report_ms: 1_000 })
+    }
+
+    fn kept(out: &[StoredSnapshot]) -> Vec<(i64, u64)> {
+        out.iter().map(|s| (s.snap.timestamp / MS, s.conflated)).collect()
+    }
+
+    #[test]
+    fn latest_of_the_interval_is_stored_by_the_tick() {
+        let mut conflator = conflator();
+        let mut out = Vec::new();
+        for at in [0, 10, 20, 30] {
+            conflator.offer(stored(at * MS), &mut out);
+        }
+        assert_eq!(kept(&out), [(0, 0)]);
+        conflator.on_tick(99 * MS, &mut out);
+        assert_eq!(out.len(), 1);
+        conflator.on_tick(100 * MS, &mut out);
+        assert_eq!(kept(&out), [(0, 0), (30, 2)]);
+    }
+
+    #[test]
+    fn snapshot_after_a_quiet_interval_is_stored_right_away() {
+        let mut conflator = conflator();
+        let mut out = Vec::new();
+        conflator.offer(stored(0), &mut out);
+        conflator.offer(stored(150 * MS), &mut out);
+        assert_eq!(kept(&out), [(0, 0), (150, 0)]);
+    }
+
+    #[test]
+    fn trade_stores_the_book_before_and_after_it() {
+        let mut conflator = conflator();
+        let mut out = Vec::new();
+        conflator.offer(stored(0), &mut out);
+        conflator.offer(stored(10 * MS), &mut out);
+        conflator.note_trade(7, 15 * MS);
+        conflator.offer(stored(20 * MS), &mut out);
+        assert_eq!(kept(&out), [(0, 0), (10, 0), (20, 0)]);
+    }
+
+    #[test]
+    fn new_interval_stores_the_pending_one() {
+        let mut conflator = conflator();
+        let mut out = Vec::new();
+        conflator.offer(stored(0), &mut out);
+        conflator.offer(stored(50 * MS), &mut out);
+        conflator.offer(stored(120 * MS), &mut out);
+        assert_eq!(kept(&out), [(0, 0), (50, 0)]);
+        assert_eq!(conflator.symbols[&7].pending.unwrap().snap.timestamp, 120 * MS);
+    }
+
+    #[test]
+    fn report_writes_the_counts_since_the_last_one() {
+        let mut conflator = conflator();
+        let mut out = Vec::new();
+        for at in [0, 10, 20, 30] {
+            conflator.offer(stored(at * MS), &mut out);
+        }
+        conflator.on_tick(100 * MS, &mut out);
+
+        let mut buffer = Buffer::new(ProtocolVersion::V1);
+        conflator.report(1_000 * MS, &mut buffer).unwrap();
+        let rows = std::str::from_utf8(buffer.as_bytes()).unwrap();
+        assert_eq!(rows, format!("snapshot_conflation,symbol=7 kept=2i,conflated=2i,report_ms=1000i {}\n", 1_000 * MS));
+        buffer.clear();
+        conflator.report(2_000 * MS, &mut buffer).unwrap();
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{HashMap, VecDeque};
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::{LogProcessor, NANOS_PER_MILLI};
+use crate::logger::types::{BalanceLogWrapper, HoldingLogWrapper, OrderLogWrapper, Stream, TradeLogs};
+
+const MATCHED: u8 = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CorrelatorConfig {
+    // how long the other streams get to catch up with a trade or a match
+    pub window_ms: u64,
+    pub require_balance: bool,
+    pub require_holding: bool,
+}
+
+impl Default for CorrelatorConfig {
+    fn default() -> Self {
+        Self {
+            window_ms: 5_000,
+            require_balance: true,
+            require_holding: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MatchEvent {
+    pub event_id: u64,
+    pub qty: u32,
+    pub price: u64,
+    pub timestamp: i64,
+    pub arrived: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PendingTrade {
+    pub trade: TradeLogs,
+    pub arrived: i64,
+}
+
+// balance / holding deltas seen for an order
+#[derive(Debug, Clone, Copy)]
+pub struct DeltaSeen {
+    pub count: u32,
+    pub last_arrived: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inconsistency {
+    MissingOrderMatch,
+    MissingTrade,
+    QuantityMismatch,
+    PriceMismatch,
+    MissingBalance,
+    MissingHolding,
+}
+
+impl Inconsistency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Inconsistency::MissingOrderMatch => "missing_order_match",
+            Inconsistency::MissingTrade => "missing_trade",
+            Inconsistency::QuantityMismatch => "quantity_mismatch",
+            Inconsistency::PriceMismatch => "price_mismatch",
+            Inconsistency::MissingBalance => "missing_balance",
+            Inconsistency::MissingHolding => "missing_holding",
+        }
+    }
+}
+
+// one break , expected is what the trade says and actual what the other stream had
+pub struct Break {
+    pub kind: Inconsistency,
+    pub order_id: u64,
+    // none for an orphan match , which has no trade to take a side or instrument from
+    pub side: Option<&'static str>,
+    pub trade: Option<TradeLogs>,
+    pub expected: i64,
+    pub actual: i64,
+    pub event_id: Option<u64>,
+    // producer timestamp of the trade ( or of the orphan match )
+    pub event_at: i64,
+}
+
+pub struct Correlator {
+    pub config: CorrelatorConfig,
+    pub trades: VecDeque<PendingTrade>,
+    pub matches: HashMap<u64, Vec<MatchEvent>>,
+    pub balances: HashMap<u64, DeltaSeen>,
+    pub holdings: HashMap<u64, DeltaSeen>,
+    pub now: i64,
+    pub last_sweep: i64,
+    pub breaks: u64,
+}
+
+fn seen(map: &mut HashMap<u64, DeltaSeen>, order_id: u64, now: i64) {
+    map.entry(order_id)
+        .and_modify(|d| {
+            d.count += 1;
+            d.last_arrived = now;
+        })
+        .or_insert(DeltaSeen { count: 1, last_arrived: now });
+}
+
+impl Correlator {
+    pub fn new(config: CorrelatorConfig) -> Self {
+        Self {
+            config,
+            trades: VecDeque::new(),
+            matches: HashMap::new(),
+            balances: HashMap::new(),
+            holdings: HashMap::new(),
+            now: 0,
+            last_sweep: 0,
+            breaks: 0,
+        }
+    }
+
+    fn check_side(&mut self, trade: &TradeLogs, order_id: u64, side: &'static str, found: &mut Vec<Break>) {
+        let brk = |kind, expected, actual, event_id| Break {
+            kind,
+            order_id,
+            side: Some(side),
+            trade: Some(*trade),
+            expected,
+            actual,
+            event_id,
+            event_at: trade.timestamp,
+        };
+
+        match self.matches.get_mut(&order_id) {
+            None => found.push(brk(Inconsistency::MissingOrderMatch, trade.quantity as i64, 0, None)),
+            Some(events) => {
+                // prefer the match with the same quantity , partial fills produce several
+                let pos = events.iter().position(|m| m.qty == trade.quantity).unwrap_or(0);
+                let m = events.remove(pos);
+                if events.is_empty() {
+                    self.matches.remove(&order_id);
+                }
+                if m.qty != trade.quantity {
+                    found.push(brk(Inconsistency::QuantityMismatch, trade.quantity as i64, m.qty as i64, Some(m.event_id)));
+                }
+                if m.price != trade.price {
+                    found.push(brk(Inconsistency::PriceMismatch, trade.price as i64, m.price as i64, Some(m.event_id)));
+                }
+            }
+        }
+        if self.config.require_balance && !self.balances.contains_key(&order_id) {
+            found.push(brk(Inconsistency::MissingBalance, 1, 0, None));
+        }
+        if self.config.require_holding && !self.holdings.contains_key(&order_id) {
+            found.push(brk(Inconsistency::MissingHolding, 1, 0, None));
+        }
+    }
+
+    fn emit(&mut self, out: &mut Buffer, b: &Break) -> questdb::Result<()> {
+        self.breaks += 1;
+        eprintln!(
+            "correlator: {} for order {} ({} side , expected {} got {})",
+            b.kind.as_str(), b.order_id, b.side.unwrap_or("no"), b.expected, b.actual
+        );
+        let trade = b.trade;
+        out.table("consistency_breaks")?.symbol("break_type", b.kind.as_str())?;
+        if let Some(side) = b.side {
+            out.symbol("side", side)?;
+        }
+        if let Some(trade) = trade {
+            out.symbol("instrument", trade.symbol.to_string())?;
+        }
+        out.column_i64("order_id", b.order_id as i64)?
+            .column_i64("buyer_order_id", trade.map(|t| t.buyer_order_id as i64).unwrap_or(-1))?
+            .column_i64("seller_order_id", trade.map(|t| t.seller_order_id as i64).unwrap_or(-1))?
+            .column_i64("event_id", b.event_id.map(|e| e as i64).unwrap_or(-1))?
+            .column_i64("expected", b.expected)?
+            .column_i64("actual", b.actual)?
+            .column_ts("event_at", TimestampNanos::new(b.event_at))?
+            .at(TimestampNanos::new(self.now))
+    }
+}
+
+impl LogProcessor for Correlator {
+    fn streams(&self) -> &'static [Stream] {
+        &[Stream::OrderLogs, Stream::BalanceLogs, Stream::HoldingLogs, Stream::TradeLogs]
+    }
+
+    fn on_order_log(&mut self, log: &OrderLogWrapper, _out: &mut Buffer) -> questdb::Result<()> {
+        let delta = &log.order_delta;
+        if delta.order_event_type == MATCHED {
+            self.matches.entry(delta.order_id).or_default().push(MatchEvent {
+                event_id: delta.event_id,
+                qty: delta.shares_qty,
+                price: delta.price,
+                timestamp: log.timestamp,
+                arrived: self.now,
+            });
+        }
+        Ok(())
+    }
+
+    fn on_balance_log(&mut self, log: &BalanceLogWrapper, _out: &mut Buffer) -> questdb::Result<()> {
+        seen(&mut self.balances, log.balance_delta.order_id, self.now);
+        Ok(())
+    }
+
+    fn on_holding_log(&mut self, log: &HoldingLogWrapper, _out: &mut Buffer) -> questdb::Result<()> {
+        seen(&mut self.holdings, log.holding_delta.order_id, self.now);
+        Ok(())
+    }
+
+    fn on_trade_log(&mut self, log: &TradeLogs, _out: &mut Buffer) -> questdb::Result<()> {
+        self.trades.push_back(PendingTrade { trade: *log, arrived: self.now });
+        Ok(())
+    }
+
+    fn on_tick(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        self.now = now;
+        if now - self.last_sweep < 100 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.last_sweep = now;
+        let window = self.config.window_ms as i64 * NANOS_PER_MILLI;
+
+        let mut found = Vec::new();
+        while let Some(pending) = self.trades.front().copied() {
+            if pending.arrived + window > now {
+                break;
+            }
+            self.trades.pop_front();
+            let trade = pending.trade;
+            // maker and taker each get balance and holding deltas under their own order id ,
+            // the same premise the balance ledger and holdings recon match on
+            self.check_side(&trade, trade.buyer_order_id, "buyer", &mut found);
+            self.check_side(&trade, trade.seller_order_id, "seller", &mut found);
+        }
+
+        // matches no trade claimed within two windows
+        for (order_id, events) in self.matches.iter_mut() {
+            events.retain(|m| {
+                if m.arrived + 2 * window > now {
+                    return true;
+                }
+                found.push(Break {
+                    kind: Inconsistency::MissingTrade,
+                    order_id: *order_id,
+                    side: None,
+                    trade: None,
+                    expected: m.qty as i64,
+                    actual: 0,
+                    event_id: Some(m.event_id),
+                    event_at: m.timestamp,
+                });
+                false
+            });
+        }
+        self.matches.retain(|_, events| !events.is_empty());
+
+        // deltas are shared by every fill of an order , keep them while trades may still refer to them
+        self.balances.retain(|_, d| d.last_arrived + 2 * window > now);
+        self.holdings.retain(|_, d| d.last_arrived + 2 * window > now);
+
+        for b in &found {
+            self.emit(out, b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{balance, holding, order, trade};
+    use questdb::ingress::ProtocolVersion;
+
+    const SEC: i64 = 1_000 * NANOS_PER_MILLI;
+
+    // every stream's view of one fill between maker 10 and taker 20 , minus what skip names
+    fn fill(correlator: &mut Correlator, out: &mut Buffer, skip: &[(&str, u64)]) {
+        correlator.on_trade_log(&trade(10, 20).at(1).qty(5).buyer_maker(true), out).unwrap();
+        for order_id in [10, 20] {
+            if !skip.contains(&("match", order_id)) {
+                correlator.on_order_log(&order(order_id, order_id).at(1).event(MATCHED).qty(5), out).unwrap();
+            }
+            if !skip.contains(&("balance", order_id)) {
+                correlator.on_balance_log(&balance(order_id, order_id).at(1), out).unwrap();
+            }
+            if !skip.contains(&("holding", order_id)) {
+                correlator.on_holding_log(&holding(order_id, order_id).at(1), out).unwrap();
+            }
+        }
+    }
+
+    fn breaks(out: &Buffer) -> Vec<String> {
+        std::str::from_utf8(out.as_bytes())
+            .unwrap()
+            .lines()
+            .map(|row| {
+                let kind = row.split(",break_type=").nth(1).unwrap().split([',', ' ']).next().unwrap();
+                let order_id = row.split("order_id=").nth(1).unwrap().split('i').next().unwrap();
+                format!("{} {}", kind, order_id)
+            })
+            .collect()
+    }
+
+    fn run(skip: &[(&str, u64)]) -> (Correlator, Vec<String>) {
+        let mut correlator = Correlator::new(CorrelatorConfig::default());
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        correlator.on_tick(SEC, &mut out).unwrap();
+        fill(&mut correlator, &mut out, skip);
+        correlator.on_tick(7 * SEC, &mut out).unwrap();
+        (correlator, breaks(&out))
+    }
+
+    #[test]
+    fn maker_and_taker_with_their_own_deltas_are_consistent() {
+        let (correlator, found) = run(&[]);
+        assert!(found.is_empty());
+        assert_eq!(correlator.breaks, 0);
+        assert!(correlator.trades.is_empty() && correlator.matches.is_empty());
+    }
+
+    #[test]
+    fn missing_maker_deltas_are_reported_for_the_maker() {
+        let (_, found) = run(&[("balance", 10), ("holding", 10)]);
+        assert_eq!(found, ["missing_balance 10", "missing_holding 10"]);
+    }
+
+    #[test]
+    fn missing_taker_match_is_reported_for_the_taker() {
+        let (_, found) = run(&[("match", 20)]);
+        assert_eq!(found, ["missing_order_match 20"]);
+    }
+
+    #[test]
+    fn unclaimed_match_is_a_missing_trade() {
+        let mut correlator = Correlator::new(CorrelatorConfig::default());
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        correlator.on_tick(SEC, &mut out).unwrap();
+        correlator.on_order_log(&order(30, 30).at(1).event(MATCHED).qty(5), &mut out).unwrap();
+        correlator.on_tick(7 * SEC, &mut out).unwrap();
+        assert!(breaks(&out).is_empty());
+        correlator.on_tick(12 * SEC, &mut out).unwrap();
+        assert_eq!(breaks(&out), ["missing_trade 30"]);
+    }
+}
@@ -0,0 +1,745 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use crossbeam::channel::{Receiver, Sender};
+use serde::Deserialize;
+
+use crate::logger::processor::{now_nanos, NANOS_PER_MILLI};
+use crate::logger::sink::LogSink;
+use crate::logger::types::{OrderLogWrapper, TradeLogs};
+
+const SOH: u8 = 0x01;
+// the session loop wakes up this often to read what the acceptor sent
+const SESSION_POLL: Duration = Duration::from_millis(100);
+// the drop copy file is resumed from the last sequence number in this much of its tail
+const TAIL_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FixConfig {
+    // drop copy log , one ExecutionReport per line , none -> no file
+    pub path: Option<PathBuf>,
+    // host:port of a FIX acceptor , the sink logs on as initiator and streams the reports
+    pub acceptor: Option<String>,
+    pub sender_comp_id: String,
+    pub target_comp_id: String,
+    pub heartbeat_secs: u64,
+    // prices are integer ticks , shown with this many decimals ( 12345 , 2 -> 123.45 )
+    pub price_decimals: u32,
+    // orders without news for this long are forgotten
+    pub order_ttl_ms: u64,
+    // fills of an order whose received log has not shown up yet wait this long for it ,
+    // then they are reported without the order size
+    pub resolve_ms: u64,
+    pub flush_ms: u64,
+    // reports queued for the acceptor , newer ones are dropped while it is away
+    pub queue: usize,
+}
+
+impl Default for FixConfig {
+    fn default() -> Self {
+        Self {
+            path: Some(PathBuf::from("/tmp/logger/dropcopy.fix")),
+            acceptor: None,
+            sender_comp_id: "LOGGER".to_string(),
+            target_comp_id: "DROPCOPY".to_string(),
+            heartbeat_secs: 30,
+            price_decimals: 0,
+            order_ttl_ms: 3_600_000,
+            resolve_ms: 1_000,
+            flush_ms: 100,
+            queue: 65536,
+        }
+    }
+}
+
+// tag 150 / tag 39 , FIX 4.4 uses ExecType F for every fill
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecKind {
+    New,
+    PartialFill,
+    Fill,
+    Canceled,
+}
+
+impl ExecKind {
+    pub fn exec_type(&self) -> &'static str {
+        match self {
+            ExecKind::New => "0",
+            ExecKind::PartialFill | ExecKind::Fill => "F",
+            ExecKind::Canceled => "4",
+        }
+    }
+
+    pub fn ord_status(&self) -> &'static str {
+        match self {
+            ExecKind::New => "0",
+            ExecKind::PartialFill => "1",
+            ExecKind::Fill => "2",
+            ExecKind::Canceled => "4",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FixOrder {
+    pub user_id: Option<u64>,
+    pub symbol: u32,
+    pub side: u8,
+    pub price: u64,
+    // unknown when the order was received before the logger started
+    pub order_qty: Option<u64>,
+    pub cum_qty: u64,
+    // sum of price * qty over the fills , for AvgPx
+    pub notional: u128,
+    pub last_seen: i64,
+}
+
+// header and trailer are added per session , a body is everything from 35 up to the checksum
+pub type FixBody = Vec<(u32, String)>;
+
+// a trade side that came in before the order it fills
+#[derive(Debug, Clone, Copy)]
+pub struct PendingFill {
+    pub side: u8,
+    pub trade: TradeLogs,
+    pub arrived: i64,
+}
+
+pub struct FixEncoder {
+    pub sender_comp_id: String,
+    pub target_comp_id: String,
+    pub seq: u64,
+}
+
+pub fn fix_time(nanos: i64) -> String {
+    DateTime::<Utc>::from_timestamp_nanos(nanos).format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
+
+impl FixEncoder {
+    pub fn new(config: &FixConfig) -> Self {
+        Self { sender_comp_id: config.sender_comp_id.clone(), target_comp_id: config.target_comp_id.clone(), seq: 0 }
+    }
+
+    pub fn encode(&mut self, body: &FixBody, out: &mut Vec<u8>) {
+        self.seq += 1;
+        self.encode_at(self.seq, body, out);
+    }
+
+    // with a sequence number of its own , gap fills go out under the first one they replace
+    pub fn encode_at(&self, seq: u64, body: &FixBody, out: &mut Vec<u8>) {
+        let mut fields = Vec::with_capacity(256);
+        let mut push = |tag: u32, value: &str| {
+            fields.extend_from_slice(tag.to_string().as_bytes());
+            fields.push(b'=');
+            fields.extend_from_slice(value.as_bytes());
+            fields.push(SOH);
+        };
+        // MsgType first , then the session header
+        let (msg_type, rest) = body.split_first().expect("fix body without MsgType");
+        push(msg_type.0, &msg_type.1);
+        push(49, &self.sender_comp_id);
+        push(56, &self.target_comp_id);
+        push(34, &seq.to_string());
+        push(52, &fix_time(now_nanos()));
+        for (tag, value) in rest {
+            push(*tag, value);
+        }
+
+        let start = out.len();
+        out.extend_from_slice(b"8=FIX.4.4\x01");
+        out.extend_from_slice(format!("9={}", fields.len()).as_bytes());
+        out.push(SOH);
+        out.extend_from_slice(&fields);
+        let checksum = out[start..].iter().map(|b| *b as u32).sum::<u32>() % 256;
+        out.extend_from_slice(format!("10={:03}", checksum).as_bytes());
+        out.push(SOH);
+    }
+}
+
+// sequence number of the last message in a drop copy file , 0 for an empty one
+fn last_seq(file: &mut File) -> io::Result<u64> {
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let tail = String::from_utf8_lossy(&tail);
+    let seq = tail
+        .lines()
+        .rev()
+        .find_map(|line| line.split(SOH as char).find_map(|field| field.strip_prefix("34=")?.parse().ok()));
+    Ok(seq.unwrap_or(0))
+}
+
+pub fn field(message: &FixBody, tag: u32) -> Option<&str> {
+    message.iter().find(|(t, _)| *t == tag).map(|(_, v)| v.as_str())
+}
+
+// complete messages at the front of the inbox , a partial one stays for the next read
+pub fn take_messages(inbox: &mut Vec<u8>) -> Vec<FixBody> {
+    let mut messages = Vec::new();
+    let mut fields = Vec::new();
+    let (mut start, mut consumed) = (0, 0);
+    for i in 0..inbox.len() {
+        if inbox[i] != SOH {
+            continue;
+        }
+        let raw = String::from_utf8_lossy(&inbox[start..i]);
+        start = i + 1;
+        if let Some((tag, value)) = raw.split_once('=')
+            && let Ok(tag) = tag.parse::<u32>()
+        {
+            fields.push((tag, value.to_string()));
+            if tag == 10 {
+                messages.push(std::mem::take(&mut fields));
+                consumed = start;
+            }
+        }
+    }
+    inbox.drain(..consumed);
+    messages
+}
+
+// everything the acceptor sent so far , an error once it hung up
+fn read_messages(stream: &mut TcpStream, inbox: &mut Vec<u8>) -> io::Result<Vec<FixBody>> {
+    let mut buf = [0u8; 4096];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "connection closed")),
+            Ok(n) => inbox.extend_from_slice(&buf[..n]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(take_messages(inbox))
+}
+
+// session level answers , false once the acceptor logged out
+fn answer(encoder: &mut FixEncoder, message: &FixBody, addr: &str, out: &mut Vec<u8>) -> bool {
+    match field(message, 35) {
+        Some("A") => eprintln!("fix: logged on to {}", addr),
+        Some("1") => {
+            let mut heartbeat = vec![(35, "0".to_string())];
+            if let Some(test_req_id) = field(message, 112) {
+                heartbeat.push((112, test_req_id.to_string()));
+            }
+            encoder.encode(&heartbeat, out);
+        }
+        // reports are not kept for replay , the range asked for is skipped with a gap fill
+        Some("2") => {
+            let begin = field(message, 7).and_then(|v| v.parse().ok()).unwrap_or(1);
+            // PossDupFlag needs OrigSendingTime , the originals are gone so the gap fill sends its own
+            let gap_fill = vec![
+                (35, "4".to_string()),
+                (43, "Y".to_string()),
+                (122, fix_time(now_nanos())),
+                (123, "Y".to_string()),
+                (36, (encoder.seq + 1).to_string()),
+            ];
+            encoder.encode_at(begin, &gap_fill, out);
+        }
+        Some("5") => {
+            eprintln!("fix: {} logged out: {}", addr, field(message, 58).unwrap_or(""));
+            encoder.encode(&vec![(35, "5".to_string())], out);
+            return false;
+        }
+        _ => {}
+    }
+    true
+}
+
+// initiator towards the acceptor , reconnects and logs on again whenever the link drops
+fn run_session(config: FixConfig, addr: String, reports: Receiver<FixBody>) {
+    let mut encoder = FixEncoder::new(&config);
+    let mut frame = Vec::new();
+    let mut inbox = Vec::new();
+    let heartbeat = Duration::from_secs(config.heartbeat_secs.max(1));
+    let mut first_logon = true;
+    loop {
+        let mut stream = match TcpStream::connect(&addr) {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("fix: cannot reach acceptor {}: {}", addr, e);
+                std::thread::sleep(Duration::from_secs(5));
+                continue;
+            }
+        };
+        let _ = stream.set_read_timeout(Some(Duration::from_millis(1)));
+        frame.clear();
+        inbox.clear();
+        // the first logon of the process starts both sides over at 1 , reconnects carry on
+        // and the acceptor asks for what it missed with a ResendRequest
+        let mut logon = vec![(35, "A".to_string()), (98, "0".to_string()), (108, heartbeat.as_secs().to_string())];
+        if first_logon {
+            encoder.seq = 0;
+            logon.push((141, "Y".to_string()));
+        }
+        encoder.encode(&logon, &mut frame);
+        if let Err(e) = stream.write_all(&frame) {
+            eprintln!("fix: logon to {} failed: {}", addr, e);
+            std::thread::sleep(Duration::from_secs(5));
+            continue;
+        }
+        first_logon = false;
+        let mut last_sent = Instant::now();
+        let mut logged_out = false;
+        while !logged_out {
+            frame.clear();
+            match reports.recv_timeout(SESSION_POLL) {
+                Ok(body) => {
+                    encoder.encode(&body, &mut frame);
+                    for body in reports.try_iter().take(1024) {
+                        encoder.encode(&body, &mut frame);
+                    }
+                }
+                Err(crossbeam::channel::RecvTimeoutError::Timeout) => {}
+                Err(crossbeam::channel::RecvTimeoutError::Disconnected) => return,
+            }
+            match read_messages(&mut stream, &mut inbox) {
+                Ok(messages) => {
+                    for message in &messages {
+                        if !answer(&mut encoder, message, &addr, &mut frame) {
+                            logged_out = true;
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("fix: lost acceptor {}: {}", addr, e);
+                    break;
+                }
+            }
+            if frame.is_empty() && last_sent.elapsed() >= heartbeat {
+                encoder.encode(&vec![(35, "0".to_string())], &mut frame);
+            }
+            if frame.is_empty() {
+                continue;
+            }
+            if let Err(e) = stream.write_all(&frame) {
+                eprintln!("fix: lost acceptor {}: {}", addr, e);
+                break;
+            }
+            last_sent = Instant::now();
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
+
+// ExecutionReports ( 35=8 ) for new , partially filled , filled and canceled orders
+// received and canceled come from the order logs , fills from the trades
+pub struct FixSink {
+    pub config: FixConfig,
+    pub orders: HashMap<u64, FixOrder>,
+    // per order id , fills that arrived before the order's received log
+    pub pending: HashMap<u64, Vec<PendingFill>>,
+    pub file: Option<BufWriter<File>>,
+    pub file_encoder: FixEncoder,
+    pub acceptor: Option<Sender<FixBody>>,
+    pub frame: Vec<u8>,
+    pub last_flush: Instant,
+    pub last_sweep: i64,
+    pub dropped: u64,
+}
+
+impl FixSink {
+    pub fn new(config: FixConfig) -> Self {
+        let mut file_encoder = FixEncoder::new(&config);
+        // the file is appended to across restarts , its sequence numbers carry on
+        let file = config.path.as_ref().and_then(|path| {
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let opened = OpenOptions::new().create(true).append(true).read(true).open(path).and_then(|mut file| {
+                file_encoder.seq = last_seq(&mut file)?;
+                Ok(file)
+            });
+            match opened {
+                Ok(file) => Some(BufWriter::new(file)),
+                Err(e) => {
+                    eprintln!("fix sink: failed to open {}: {}", path.display(), e);
+                    None
+                }
+            }
+        });
+        let acceptor = config.acceptor.clone().map(|addr| {
+            let (tx, rx) = crossbeam::channel::bounded(config.queue.max(1));
+            let session_config = config.clone();
+            std::thread::spawn(move || run_session(session_config, addr, rx));
+            tx
+        });
+        Self {
+            file_encoder,
+            config,
+            orders: HashMap::new(),
+            pending: HashMap::new(),
+            file,
+            acceptor,
+            frame: Vec::with_capacity(512),
+            last_flush: Instant::now(),
+            last_sweep: 0,
+            dropped: 0,
+        }
+    }
+
+    fn price(&self, ticks: u64) -> String {
+        let decimals = self.config.price_decimals;
+        if decimals == 0 {
+            return ticks.to_string();
+        }
+        let scale = 10u64.pow(decimals);
+        format!("{}.{:0width$}", ticks / scale, ticks % scale, width = decimals as usize)
+    }
+
+    fn avg_px(&self, order: &FixOrder) -> String {
+        if order.cum_qty == 0 {
+            return "0".to_string();
+        }
+        let avg = order.notional as f64 / order.cum_qty as f64 / 10f64.powi(self.config.price_decimals as i32);
+        format!("{:.*}", self.config.price_decimals as usize + 4, avg)
+    }
+
+    fn report(&mut self, order_id: u64, order: &FixOrder, kind: ExecKind, exec_id: String, fill: Option<(u64, u64)>, at: i64) {
+        // a received log showing up after fills of the order were reported still says where the order stands
+        let status = match kind {
+            ExecKind::New if order.cum_qty > 0 => match order.order_qty {
+                Some(order_qty) if order.cum_qty >= order_qty => ExecKind::Fill,
+                _ => ExecKind::PartialFill,
+            },
+            _ => kind,
+        };
+        let mut body: FixBody = vec![
+            (35, "8".to_string()),
+            (37, order_id.to_string()),
+            (11, order_id.to_string()),
+            (17, exec_id),
+            (150, kind.exec_type().to_string()),
+            (39, status.ord_status().to_string()),
+        ];
+        if let Some(user_id) = order.user_id {
+            body.push((1, user_id.to_string()));
+        }
+        body.push((55, order.symbol.to_string()));
+        body.push((54, if order.side == 0 { "1" } else { "2" }.to_string()));
+        body.push((40, "2".to_string()));
+        body.push((44, self.price(order.price)));
+        if let Some(order_qty) = order.order_qty {
+            body.push((38, order_qty.to_string()));
+        }
+        if let Some((last_px, last_qty)) = fill {
+            body.push((31, self.price(last_px)));
+            body.push((32, last_qty.to_string()));
+        }
+        // leaves is only known when the order size is
+        let leaves = match status {
+            ExecKind::Canceled | ExecKind::Fill => Some(0),
+            _ => order.order_qty.map(|q| q.saturating_sub(order.cum_qty)),
+        };
+        if let Some(leaves) = leaves {
+            body.push((151, leaves.to_string()));
+        }
+        body.push((14, order.cum_qty.to_string()));
+        body.push((6, self.avg_px(order)));
+        body.push((60, fix_time(at)));
+
+        if let Some(file) = self.file.as_mut() {
+            self.frame.clear();
+            self.file_encoder.encode(&body, &mut self.frame);
+            self.frame.push(b'\n');
+            if let Err(e) = file.write_all(&self.frame) {
+                eprintln!("fix sink: write failed: {}", e);
+                self.file = None;
+            }
+        }
+        if let Some(acceptor) = &self.acceptor
+            && acceptor.try_send(body).is_err()
+        {
+            self.dropped += 1;
+        }
+    }
+
+    // the flusher hands over trades before the order logs of the same loop , so a taker's
+    // fills usually show up before the order does and wait for it
+    fn fill(&mut self, order_id: u64, side: u8, trade: &TradeLogs) {
+        if !self.orders.contains_key(&order_id) {
+            self.pending.entry(order_id).or_default().push(PendingFill { side, trade: *trade, arrived: now_nanos() });
+            return;
+        }
+        self.report_fill(order_id, side, trade);
+    }
+
+    fn report_fill(&mut self, order_id: u64, side: u8, trade: &TradeLogs) {
+        let order = self.orders.entry(order_id).or_insert(FixOrder {
+            user_id: None,
+            symbol: trade.symbol,
+            side,
+            price: trade.price,
+            order_qty: None,
+            cum_qty: 0,
+            notional: 0,
+            last_seen: trade.timestamp,
+        });
+        order.cum_qty += trade.quantity as u64;
+        order.notional += trade.price as u128 * trade.quantity as u128;
+        order.last_seen = trade.timestamp;
+        let order = *order;
+        let kind = match order.order_qty {
+            Some(order_qty) if order.cum_qty >= order_qty => ExecKind::Fill,
+            _ => ExecKind::PartialFill,
+        };
+        let exec_id = format!("{}-{}-{}-{}", trade.timestamp, trade.buyer_order_id, trade.seller_order_id, side);
+        self.report(order_id, &order, kind, exec_id, Some((trade.price, trade.quantity as u64)), trade.timestamp);
+        if kind == ExecKind::Fill {
+            self.orders.remove(&order_id);
+        }
+    }
+}
+
+impl LogSink for FixSink {
+    fn write_order_log(&mut self, log: &OrderLogWrapper) {
+        let d = &log.order_delta;
+        let order = self.orders.entry(d.order_id).or_insert(FixOrder {
+            user_id: Some(d.user_id),
+            symbol: d.symbol,
+            side: d.side,
+            price: d.price,
+            order_qty: None,
+            cum_qty: 0,
+            notional: 0,
+            last_seen: log.timestamp,
+        });
+        order.user_id = Some(d.user_id);
+        order.last_seen = log.timestamp;
+        match d.order_event_type {
+            0 => {
+                order.order_qty = Some(d.shares_qty as u64);
+                order.price = d.price;
+                let order = *order;
+                self.report(d.order_id, &order, ExecKind::New, d.event_id.to_string(), None, log.timestamp);
+                if order.cum_qty >= d.shares_qty as u64 && order.cum_qty > 0 {
+                    self.orders.remove(&d.order_id);
+                }
+                for pending in self.pending.remove(&d.order_id).unwrap_or_default() {
+                    self.report_fill(d.order_id, pending.side, &pending.trade);
+                }
+            }
+            2 => {
+                let order = self.orders.remove(&d.order_id).unwrap();
+                self.report(d.order_id, &order, ExecKind::Canceled, d.event_id.to_string(), None, log.timestamp);
+            }
+            // matches are reported from the trade , which carries price and quantity
+            _ => {}
+        }
+    }
+
+    fn write_trade_log(&mut self, trade: &TradeLogs) {
+        self.fill(trade.buyer_order_id, 0, trade);
+        self.fill(trade.seller_order_id, 1, trade);
+    }
+
+    fn tick(&mut self) {
+        let now = now_nanos();
+        // fills whose order never showed up , it was received before the logger started
+        let resolve = self.config.resolve_ms as i64 * NANOS_PER_MILLI;
+        let due: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, fills)| fills[0].arrived + resolve <= now)
+            .map(|(order_id, _)| *order_id)
+            .collect();
+        for order_id in due {
+            for pending in self.pending.remove(&order_id).unwrap_or_default() {
+                self.report_fill(order_id, pending.side, &pending.trade);
+            }
+        }
+        if now - self.last_sweep >= 1000 * NANOS_PER_MILLI {
+            self.last_sweep = now;
+            let ttl = self.config.order_ttl_ms as i64 * NANOS_PER_MILLI;
+            self.orders.retain(|_, o| now - o.last_seen < ttl);
+        }
+        if self.last_flush.elapsed() < Duration::from_millis(self.config.flush_ms) {
+            return;
+        }
+        self.last_flush = Instant::now();
+        if let Some(file) = self.file.as_mut()
+            && let Err(e) = file.flush()
+        {
+            eprintln!("fix sink: flush failed: {}", e);
+            self.file = None;
+        }
+        if self.dropped > 0 {
+            eprintln!("fix sink: acceptor behind , dropped {} reports", self.dropped);
+            self.dropped = 0;
+        }
+    }
+}
+
+impl Drop for FixSink {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{order, trade};
+
+    fn drop_copy(price_decimals: u32, resolve_ms: u64) -> (FixSink, Receiver<FixBody>) {
+        let config = FixConfig { path: None, price_decimals, resolve_ms, ..FixConfig::default() };
+        let mut sink = FixSink::new(config);
+        let (tx, rx) = crossbeam::channel::unbounded();
+        sink.acceptor = Some(tx);
+        (sink, rx)
+    }
+
+    // ( order id , ExecType , OrdStatus ) per report for the buyer side
+    fn reports(rx: &Receiver<FixBody>) -> Vec<(String, String, String)> {
+        rx.try_iter()
+            .filter(|body| field(body, 37) != Some("99"))
+            .map(|body| {
+                let tag = |tag| field(&body, tag).unwrap_or("").to_string();
+                (tag(37), tag(150), tag(39))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn body_length_and_checksum() {
+        let encoder = FixEncoder { sender_comp_id: "S".to_string(), target_comp_id: "T".to_string(), seq: 0 };
+        let mut out = b"garbage".to_vec();
+        encoder.encode_at(42, &vec![(35, "0".to_string()), (112, "ping".to_string())], &mut out);
+        let frame = &out[7..];
+
+        assert!(frame.starts_with(b"8=FIX.4.4\x019="));
+        let body_start = frame.iter().enumerate().filter(|(_, b)| **b == SOH).nth(1).unwrap().0 + 1;
+        let trailer = frame.len() - 7;
+        assert_eq!(&frame[trailer..trailer + 3], b"10=");
+        let length: usize = std::str::from_utf8(&frame[12..body_start - 1]).unwrap().parse().unwrap();
+        assert_eq!(length, trailer - body_start);
+
+        let checksum = frame[..trailer].iter().map(|b| *b as u32).sum::<u32>() % 256;
+        assert_eq!(&frame[trailer + 3..trailer + 6], format!("{:03}", checksum).as_bytes());
+
+        let mut inbox = frame.to_vec();
+        let message = take_messages(&mut inbox).remove(0);
+        let tags: Vec<u32> = message.iter().map(|(t, _)| *t).collect();
+        assert_eq!(tags, [8, 9, 35, 49, 56, 34, 52, 112, 10]);
+        assert_eq!(field(&message, 34), Some("42"));
+    }
+
+    #[test]
+    fn partial_message_stays_in_the_inbox() {
+        let encoder = FixEncoder { sender_comp_id: "S".to_string(), target_comp_id: "T".to_string(), seq: 0 };
+        let mut inbox = Vec::new();
+        encoder.encode_at(1, &vec![(35, "0".to_string())], &mut inbox);
+        encoder.encode_at(2, &vec![(35, "1".to_string())], &mut inbox);
+        inbox.extend_from_slice(b"8=FIX.4.4\x019=5\x0135=");
+
+        let messages = take_messages(&mut inbox);
+        assert_eq!(messages.iter().map(|m| field(m, 35)).collect::<Vec<_>>(), [Some("0"), Some("1")]);
+        assert_eq!(inbox, b"8=FIX.4.4\x019=5\x0135=");
+        inbox.extend_from_slice(b"A\x0110=000\x01");
+        assert_eq!(field(&take_messages(&mut inbox)[0], 35), Some("A"));
+        assert!(inbox.is_empty());
+    }
+
+    #[test]
+    fn gap_fill_carries_orig_sending_time() {
+        let mut encoder = FixEncoder { sender_comp_id: "S".to_string(), target_comp_id: "T".to_string(), seq: 10 };
+        let mut out = Vec::new();
+        let resend = vec![(35, "2".to_string()), (7, "4".to_string()), (16, "0".to_string())];
+        assert!(answer(&mut encoder, &resend, "test", &mut out));
+        let gap_fill = take_messages(&mut out).remove(0);
+        assert_eq!(field(&gap_fill, 35), Some("4"));
+        assert_eq!(field(&gap_fill, 34), Some("4"));
+        assert_eq!(field(&gap_fill, 36), Some("11"));
+        assert_eq!(field(&gap_fill, 43), Some("Y"));
+        assert!(field(&gap_fill, 122).is_some());
+    }
+
+    #[test]
+    fn prices_and_average_price() {
+        let (sink, _) = drop_copy(2, 1000);
+        assert_eq!(sink.price(12345), "123.45");
+        assert_eq!(sink.price(5), "0.05");
+        let mut order = FixOrder {
+            user_id: None,
+            symbol: 7,
+            side: 0,
+            price: 100,
+            order_qty: None,
+            cum_qty: 0,
+            notional: 0,
+            last_seen: 0,
+        };
+        assert_eq!(sink.avg_px(&order), "0");
+        // 10 @ 1.00 and 20 @ 1.03
+        order.cum_qty = 30;
+        order.notional = 100 * 10 + 103 * 20;
+        assert_eq!(sink.avg_px(&order), "1.020000");
+
+        let (sink, _) = drop_copy(0, 1000);
+        assert_eq!(sink.price(12345), "12345");
+    }
+
+    #[test]
+    fn fills_before_the_order_wait_for_it() {
+        let (mut sink, rx) = drop_copy(0, 60_000);
+        sink.write_trade_log(&trade(1, 99).at(2).qty(4));
+        sink.write_trade_log(&trade(1, 99).at(2).qty(6));
+        assert!(reports(&rx).is_empty());
+
+        sink.write_order_log(&order(1, 9).at(1).qty(10));
+        let kinds = |reports: Vec<(String, String, String)>| {
+            reports.into_iter().map(|(_, exec_type, status)| (exec_type, status)).collect::<Vec<_>>()
+        };
+        let s = |a: &str, b: &str| (a.to_string(), b.to_string());
+        assert_eq!(kinds(reports(&rx)), [s("0", "0"), s("F", "1"), s("F", "2")]);
+        assert!(!sink.pending.contains_key(&1) && !sink.orders.contains_key(&1));
+    }
+
+    #[test]
+    fn unresolved_fills_go_out_on_tick_without_the_order_size() {
+        let (mut sink, rx) = drop_copy(0, 0);
+        // recent enough to outlive the order sweep on tick
+        let now = now_nanos();
+        sink.write_trade_log(&trade(1, 99).at(now).qty(4));
+        sink.tick();
+        let bodies: Vec<FixBody> = rx.try_iter().filter(|b| field(b, 37) == Some("1")).collect();
+        assert_eq!(bodies.len(), 1);
+        assert_eq!((field(&bodies[0], 150), field(&bodies[0], 39)), (Some("F"), Some("1")));
+        assert_eq!((field(&bodies[0], 38), field(&bodies[0], 151)), (None, None));
+        assert_eq!(field(&bodies[0], 14), Some("4"));
+
+        // the received log that turns up later reports the order as partly filled
+        sink.write_order_log(&order(1, 9).at(now).qty(10));
+        let bodies: Vec<FixBody> = rx.try_iter().filter(|b| field(b, 37) == Some("1")).collect();
+        assert_eq!((field(&bodies[0], 150), field(&bodies[0], 39)), (Some("0"), Some("1")));
+        let tags = |tags: [u32; 3]| tags.map(|tag| field(&bodies[0], tag));
+        assert_eq!(tags([38, 151, 14]), [Some("10"), Some("6"), Some("4")]);
+        sink.write_order_log(&order(1, 9).at(now).qty(10).event(2));
+        assert_eq!(reports(&rx), [("1".to_string(), "4".to_string(), "4".to_string())]);
+    }
+
+    #[test]
+    fn late_received_log_of_a_filled_order_reports_it_filled() {
+        let (mut sink, rx) = drop_copy(0, 0);
+        // recent enough to outlive the order sweep on tick
+        let now = now_nanos();
+        sink.write_trade_log(&trade(1, 99).at(now).qty(10));
+        sink.tick();
+        assert_eq!(reports(&rx), [("1".to_string(), "F".to_string(), "1".to_string())]);
+
+        sink.write_order_log(&order(1, 9).at(now).qty(10));
+        let bodies: Vec<FixBody> = rx.try_iter().filter(|b| field(b, 37) == Some("1")).collect();
+        assert_eq!([150, 39, 151].map(|tag| field(&bodies[0], tag)), [Some("0"), Some("2"), Some("0")]);
+        assert!(!sink.orders.contains_key(&1));
+    }
+}
@@ -0,0 +1,206 @@
+use questdb::ingress::Buffer;
+
+// 2^SUB_BITS buckets per power of two , values are kept within 12.5%
+const SUB_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+// log linear histogram of u64 values ( nanoseconds everywhere it is used )
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum: u128,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+}
+
+fn index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let exp = 63 - value.leading_zeros();
+    let sub = (value >> (exp - SUB_BITS)) as usize & (SUB_BUCKETS - 1);
+    (exp - SUB_BITS + 1) as usize * SUB_BUCKETS + sub
+}
+
+// largest value that lands in the bucket
+fn upper_bound(idx: usize) -> u64 {
+    if idx < SUB_BUCKETS {
+        return idx as u64;
+    }
+    let exp = (idx / SUB_BUCKETS) as u32 + SUB_BITS - 1;
+    let sub = (idx % SUB_BUCKETS) as u64;
+    let width = 1u64 << (exp - SUB_BITS);
+    ((SUB_BUCKETS as u64 + sub) << (exp - SUB_BITS)).saturating_add(width - 1)
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        self.counts[index(value)] += 1;
+        self.count += 1;
+        self.sum += value as u128;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    // negative gaps come from clock skew between producers , they count as zero
+    pub fn record_nanos(&mut self, nanos: i64) {
+        self.record(nanos.max(0) as u64);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.count as f64
+    }
+
+    // q in [0 , 1] , the upper bound of the bucket holding the q-th value
+    pub fn percentile(&self, q: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (idx, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return upper_bound(idx).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (mine, theirs) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *mine += theirs;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+// summary columns of a histogram , the caller opens the row and sets its timestamp
+pub fn write_summary(out: &mut Buffer, histogram: &Histogram) -> questdb::Result<()> {
+    out.column_i64("count", histogram.count as i64)?
+        .column_i64("min_ns", histogram.min as i64)?
+        .column_i64("p50_ns", histogram.percentile(0.5) as i64)?
+        .column_i64("p90_ns", histogram.percentile(0.9) as i64)?
+        .column_i64("p99_ns", histogram.percentile(0.99) as i64)?
+        .column_i64("p999_ns", histogram.percentile(0.999) as i64)?
+        .column_i64("max_ns", histogram.max as i64)?
+        .column_f64("mean_ns", histogram.mean())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // powers of two , the values around them and a spread in between
+    fn samples() -> Vec<u64> {
+        let mut values: Vec<u64> = (0..64).flat_map(|bit| {
+            let p = 1u64 << bit;
+            [p - 1, p, p + 1, p + p / 3]
+        }).collect();
+        values.extend([999, 1_000, 123_456_789, u64::MAX - 1, u64::MAX]);
+        values
+    }
+
+    #[test]
+    fn every_value_lands_in_a_bucket_that_bounds_it() {
+        for value in samples() {
+            let idx = index(value);
+            assert!(idx < BUCKETS, "{} -> bucket {}", value, idx);
+            assert!(value <= upper_bound(idx), "{} above bucket {}", value, idx);
+            if idx > 0 {
+                assert!(value > upper_bound(idx - 1), "{} below bucket {}", value, idx);
+            }
+        }
+    }
+
+    #[test]
+    fn bucket_bounds_map_back_to_their_bucket() {
+        for idx in 0..BUCKETS {
+            assert_eq!(index(upper_bound(idx)), idx);
+        }
+        assert_eq!(upper_bound(BUCKETS - 1), u64::MAX);
+    }
+
+    #[test]
+    fn small_values_are_exact_and_large_ones_within_an_eighth() {
+        for value in 0..SUB_BUCKETS as u64 {
+            assert_eq!(upper_bound(index(value)), value);
+        }
+        for value in samples().into_iter().filter(|v| *v >= SUB_BUCKETS as u64) {
+            let bound = upper_bound(index(value));
+            assert!((bound - value) as f64 <= value as f64 / 8.0, "{} reported as {}", value, bound);
+        }
+    }
+
+    #[test]
+    fn percentiles_of_a_uniform_spread() {
+        let mut histogram = Histogram::default();
+        for value in 1..=1_000 {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.percentile(0.0), 1);
+        assert_eq!(histogram.percentile(1.0), 1_000);
+        for (q, exact) in [(0.5, 500.0), (0.9, 900.0), (0.99, 990.0)] {
+            let got = histogram.percentile(q) as f64;
+            assert!(got >= exact && got <= exact * 1.125, "p{} = {}", q, got);
+        }
+        assert_eq!(histogram.mean(), 500.5);
+    }
+
+    #[test]
+    fn percentiles_stay_within_min_and_max() {
+        let mut histogram = Histogram::default();
+        histogram.record(1_000_001);
+        assert_eq!(histogram.percentile(0.5), 1_000_001);
+        assert!(Histogram::default().percentile(0.5) == 0 && Histogram::default().mean() == 0.0);
+    }
+
+    #[test]
+    fn merge_matches_recording_everything_in_one() {
+        let (mut left, mut right, mut both) = (Histogram::default(), Histogram::default(), Histogram::default());
+        for value in samples() {
+            if value % 2 == 0 { left.record(value) } else { right.record(value) }
+            both.record(value);
+        }
+        left.merge(&right);
+        assert_eq!((left.counts, left.count, left.sum, left.min, left.max), (both.counts, both.count, both.sum, both.min, both.max));
+    }
+
+    #[test]
+    fn negative_nanos_count_as_zero() {
+        let mut histogram = Histogram::default();
+        histogram.record_nanos(-5);
+        assert_eq!((histogram.min, histogram.max, histogram.counts[0]), (0, 0, 1));
+        histogram.reset();
+        assert!(histogram.is_empty());
+    }
+}
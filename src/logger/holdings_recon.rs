@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::{LogProcessor, NANOS_PER_MILLI};
+use crate::logger::types::{HoldingLogWrapper, Stream, TradeLogs};
+
+// evidence lists are capped so a runaway order cannot blow up a row
+const MAX_EVIDENCE: usize = 16;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HoldingsReconConfig {
+    // an order is reconciled once neither stream touched it for this long
+    pub settle_ms: u64,
+}
+
+impl Default for HoldingsReconConfig {
+    fn default() -> Self {
+        Self { settle_ms: 2_000 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Position {
+    pub available: i64,
+    pub reserved: i64,
+}
+
+// both sides of one order_id , waiting to settle
+#[derive(Debug, Clone, Default)]
+pub struct OrderRecon {
+    pub user_id: Option<u64>,
+    pub holding_symbol: Option<u32>,
+    // sum of available + reserved changes , a lock moves units between the two and nets to zero
+    pub holding_net: i64,
+    pub holding_events: Vec<u64>,
+    pub holding_count: u32,
+    pub trade_symbol: Option<u32>,
+    // + quantity bought , - quantity sold
+    pub trade_net: i64,
+    pub trades: Vec<String>,
+    pub trade_count: u32,
+    pub last_activity: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldingBreak {
+    MissingHolding,
+    MissingTrade,
+    QuantityMismatch,
+    SymbolMismatch,
+}
+
+impl HoldingBreak {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HoldingBreak::MissingHolding => "missing_holding",
+            HoldingBreak::MissingTrade => "missing_trade",
+            HoldingBreak::QuantityMismatch => "quantity_mismatch",
+            HoldingBreak::SymbolMismatch => "symbol_mismatch",
+        }
+    }
+}
+
+impl OrderRecon {
+    fn check(&self) -> Option<HoldingBreak> {
+        if self.trade_count > 0 && self.holding_count == 0 {
+            return Some(HoldingBreak::MissingHolding);
+        }
+        if self.trade_count == 0 {
+            return (self.holding_net != 0).then_some(HoldingBreak::MissingTrade);
+        }
+        if self.holding_symbol.is_some() && self.holding_symbol != self.trade_symbol {
+            return Some(HoldingBreak::SymbolMismatch);
+        }
+        (self.holding_net != self.trade_net).then_some(HoldingBreak::QuantityMismatch)
+    }
+}
+
+pub struct HoldingsRecon {
+    pub config: HoldingsReconConfig,
+    pub positions: HashMap<(u64, u32), Position>,
+    pub orders: HashMap<u64, OrderRecon>,
+    pub now: i64,
+    pub last_sweep: i64,
+    pub breaks: u64,
+}
+
+impl HoldingsRecon {
+    pub fn new(config: HoldingsReconConfig) -> Self {
+        Self {
+            config,
+            positions: HashMap::new(),
+            orders: HashMap::new(),
+            now: 0,
+            last_sweep: 0,
+            breaks: 0,
+        }
+    }
+
+    fn add_trade_side(&mut self, order_id: u64, trade: &TradeLogs, signed_qty: i64) {
+        let recon = self.orders.entry(order_id).or_default();
+        recon.trade_symbol = Some(trade.symbol);
+        recon.trade_net += signed_qty;
+        recon.trade_count += 1;
+        recon.last_activity = self.now;
+        if recon.trades.len() < MAX_EVIDENCE {
+            recon.trades.push(format!("{}@{}x{}", trade.timestamp, trade.price, signed_qty));
+        }
+    }
+
+    fn emit_break(out: &mut Buffer, order_id: u64, recon: &OrderRecon, kind: HoldingBreak, at: i64) -> questdb::Result<()> {
+        let holding_events = recon.holding_events.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(",");
+        out.table("holding_breaks")?
+            .symbol("break_type", kind.as_str())?
+            .column_i64("order_id", order_id as i64)?
+            .column_i64("user_id", recon.user_id.map(|u| u as i64).unwrap_or(-1))?
+            .column_i64("holding_instrument", recon.holding_symbol.map(|s| s as i64).unwrap_or(-1))?
+            .column_i64("trade_instrument", recon.trade_symbol.map(|s| s as i64).unwrap_or(-1))?
+            .column_i64("holding_net", recon.holding_net)?
+            .column_i64("holding_count", recon.holding_count as i64)?
+            .column_str("holding_event_ids", &holding_events)?
+            .column_i64("trade_net", recon.trade_net)?
+            .column_i64("trade_count", recon.trade_count as i64)?
+            // timestamp@price x signed qty per fill
+            .column_str("trades", recon.trades.join(","))?
+            .at(TimestampNanos::new(at))
+    }
+}
+
+impl LogProcessor for HoldingsRecon {
+    fn streams(&self) -> &'static [Stream] {
+        &[Stream::HoldingLogs, Stream::TradeLogs]
+    }
+
+    fn on_holding_log(&mut self, log: &HoldingLogWrapper, out: &mut Buffer) -> questdb::Result<()> {
+        let delta = &log.holding_delta;
+        let position = self.positions.entry((delta.user_id, delta.symbol)).or_default();
+        position.available += delta.delta_available as i64;
+        position.reserved += delta.delta_reserved as i64;
+        let position = *position;
+
+        let recon = self.orders.entry(delta.order_id).or_default();
+        recon.user_id = Some(delta.user_id);
+        recon.holding_symbol = Some(delta.symbol);
+        recon.holding_net += delta.delta_available as i64 + delta.delta_reserved as i64;
+        recon.holding_count += 1;
+        recon.last_activity = self.now;
+        if recon.holding_events.len() < MAX_EVIDENCE {
+            recon.holding_events.push(delta.event_id);
+        }
+
+        out.table("holding_positions")?
+            .symbol("instrument", delta.symbol.to_string())?
+            .column_i64("user_id", delta.user_id as i64)?
+            .column_i64("order_id", delta.order_id as i64)?
+            .column_i64("event_id", delta.event_id as i64)?
+            .column_i64("available_holding", position.available)?
+            .column_i64("reserved_holding", position.reserved)?
+            .at(TimestampNanos::new(log.timestamp))
+    }
+
+    // like the balance ledger , each side's holding deltas carry that side's own order id ,
+    // so maker and taker are both reconciled under their own order
+    fn on_trade_log(&mut self, log: &TradeLogs, _out: &mut Buffer) -> questdb::Result<()> {
+        self.add_trade_side(log.buyer_order_id, log, log.quantity as i64);
+        self.add_trade_side(log.seller_order_id, log, -(log.quantity as i64));
+        Ok(())
+    }
+
+    fn on_tick(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        self.now = now;
+        if now - self.last_sweep < 100 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.last_sweep = now;
+
+        let settled_before = now - self.config.settle_ms as i64 * NANOS_PER_MILLI;
+        let settled: Vec<u64> = self
+            .orders
+            .iter()
+            .filter(|(_, r)| r.last_activity <= settled_before)
+            .map(|(id, _)| *id)
+            .collect();
+        for order_id in settled {
+            let recon = self.orders.remove(&order_id).unwrap();
+            if let Some(kind) = recon.check() {
+                self.breaks += 1;
+                eprintln!(
+                    "holdings recon: {} for order {} (holding net {} over {} events , trade net {} over {} fills)",
+                    kind.as_str(), order_id, recon.holding_net, recon.holding_count, recon.trade_net, recon.trade_count
+                );
+                Self::emit_break(out, order_id, &recon, kind, now)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{holding, trade};
+    use questdb::ingress::ProtocolVersion;
+
+    const SEC: i64 = 1_000 * NANOS_PER_MILLI;
+
+    fn breaks(out: &Buffer) -> Vec<String> {
+        std::str::from_utf8(out.as_bytes())
+            .unwrap()
+            .lines()
+            .filter_map(|row| row.strip_prefix("holding_breaks,break_type="))
+            .map(|row| row.split([',', ' ']).next().unwrap().to_string())
+            .collect()
+    }
+
+    fn settle(recon: &mut HoldingsRecon, out: &mut Buffer) {
+        recon.on_tick(10 * SEC, out).unwrap();
+    }
+
+    #[test]
+    fn partial_fills_of_maker_and_taker_reconcile() {
+        let mut recon = HoldingsRecon::new(HoldingsReconConfig::default());
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        // seller 20 locks 10 units , fills 4 + 6 against two buyers
+        recon.on_holding_log(&holding(20, 2).at(1).deltas(-10, 10), &mut out).unwrap();
+        for (buyer, qty) in [(10, 4), (11, 6)] {
+            recon.on_trade_log(&trade(buyer, 20).at(1).qty(qty as u32).buyer_maker(true), &mut out).unwrap();
+            recon.on_holding_log(&holding(buyer, 1).at(1).deltas(qty, 0), &mut out).unwrap();
+            recon.on_holding_log(&holding(20, 2).at(1).deltas(0, -qty), &mut out).unwrap();
+        }
+        settle(&mut recon, &mut out);
+
+        assert!(breaks(&out).is_empty());
+        assert!(recon.orders.is_empty());
+        assert_eq!(recon.positions[&(2, 7)].available, -10);
+        assert_eq!(recon.positions[&(2, 7)].reserved, 0);
+    }
+
+    #[test]
+    fn short_delivery_is_a_quantity_mismatch() {
+        let mut recon = HoldingsRecon::new(HoldingsReconConfig::default());
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        recon.on_trade_log(&trade(10, 20).at(1).qty(5).buyer_maker(true), &mut out).unwrap();
+        recon.on_holding_log(&holding(10, 1).at(1).deltas(3, 0), &mut out).unwrap();
+        recon.on_holding_log(&holding(20, 2).at(1).deltas(0, -5), &mut out).unwrap();
+        settle(&mut recon, &mut out);
+
+        assert_eq!(breaks(&out), ["quantity_mismatch"]);
+        assert!(std::str::from_utf8(out.as_bytes()).unwrap().contains("order_id=10i"));
+    }
+
+    #[test]
+    fn orphan_deltas_and_trades_are_breaks() {
+        let mut recon = HoldingsRecon::new(HoldingsReconConfig::default());
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        // a lock without a fill nets to zero and is fine
+        recon.on_holding_log(&holding(30, 1).at(1).deltas(-5, 5), &mut out).unwrap();
+        // units moved for an order that never traded
+        recon.on_holding_log(&holding(31, 1).at(1).deltas(5, 0), &mut out).unwrap();
+        settle(&mut recon, &mut out);
+        assert_eq!(breaks(&out), ["missing_trade"]);
+
+        out.clear();
+        recon.on_trade_log(&trade(40, 41).at(1).qty(5).buyer_maker(true), &mut out).unwrap();
+        recon.on_holding_log(&holding(40, 1).at(1).deltas(5, 0).symbol(8), &mut out).unwrap();
+        recon.on_tick(20 * SEC, &mut out).unwrap();
+        let mut found = breaks(&out);
+        found.sort();
+        assert_eq!(found, ["missing_holding", "symbol_mismatch"]);
+    }
+}
@@ -0,0 +1,189 @@
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::histogram::{write_summary, Histogram};
+use crate::logger::processor::NANOS_PER_MILLI;
+use crate::logger::types::Stream;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IngestLagConfig {
+    // ingest_lag rows are written and the histograms reset every report_ms
+    pub report_ms: u64,
+    // store the poller's dequeue time as an ingest_ts column on every stream table
+    pub ingest_ts_column: bool,
+}
+
+impl Default for IngestLagConfig {
+    fn default() -> Self {
+        Self { report_ms: 10_000, ingest_ts_column: false }
+    }
+}
+
+// where a record spent its time , measured from the producer timestamp to the flush
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagStage {
+    // producer timestamp -> dequeued by the poller
+    Shm,
+    // dequeued -> picked up by the flusher
+    Channel,
+    // picked up -> flushed to QuestDB
+    Buffer,
+    Total,
+}
+
+impl LagStage {
+    pub const ALL: [LagStage; 4] = [LagStage::Shm, LagStage::Channel, LagStage::Buffer, LagStage::Total];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LagStage::Shm => "shm",
+            LagStage::Channel => "channel",
+            LagStage::Buffer => "buffer",
+            LagStage::Total => "total",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IngestStamp {
+    pub produced: i64,
+    pub dequeued: i64,
+    pub picked_up: i64,
+}
+
+pub struct IngestLag {
+    pub config: IngestLagConfig,
+    // stamps of rows sitting in each stream's buffer , indexed by Stream::index
+    pub pending: Vec<Vec<IngestStamp>>,
+    // [stream][stage]
+    pub histograms: Vec<Vec<Histogram>>,
+    pub last_report: i64,
+}
+
+impl IngestLag {
+    pub fn new(config: IngestLagConfig) -> Self {
+        Self {
+            config,
+            pending: Stream::ALL.iter().map(|_| Vec::new()).collect(),
+            histograms: Stream::ALL.iter().map(|_| LagStage::ALL.iter().map(|_| Histogram::default()).collect()).collect(),
+            last_report: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn note(&mut self, stream: Stream, stamp: IngestStamp) {
+        self.pending[stream.index()].push(stamp);
+    }
+
+    // the stream's buffer just went out , every row in it is done
+    pub fn flushed(&mut self, stream: Stream, at: i64) {
+        let histograms = &mut self.histograms[stream.index()];
+        for stamp in self.pending[stream.index()].drain(..) {
+            histograms[0].record_nanos(stamp.dequeued - stamp.produced);
+            histograms[1].record_nanos(stamp.picked_up - stamp.dequeued);
+            histograms[2].record_nanos(at - stamp.picked_up);
+            histograms[3].record_nanos(at - stamp.produced);
+        }
+    }
+
+    // the stream's buffer failed to go out , its rows never reached QuestDB
+    pub fn dropped(&mut self, stream: Stream) {
+        self.pending[stream.index()].clear();
+    }
+
+    pub fn report(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        if now - self.last_report < self.config.report_ms as i64 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.last_report = now;
+        for (stream, histograms) in Stream::ALL.iter().zip(self.histograms.iter_mut()) {
+            for (stage, histogram) in LagStage::ALL.iter().zip(histograms.iter_mut()) {
+                if histogram.is_empty() {
+                    continue;
+                }
+                out.table("ingest_lag")?
+                    .symbol("stream", stream.table())?
+                    .symbol("stage", stage.as_str())?;
+                write_summary(out, histogram)?;
+                out.column_i64("interval_ms", self.config.report_ms as i64)?
+                    .at(TimestampNanos::new(now))?;
+                histogram.reset();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use questdb::ingress::ProtocolVersion;
+
+    const MS: i64 = NANOS_PER_MILLI;
+
+    fn lag() -> IngestLag {
+        IngestLag::new(IngestLagConfig { report_ms: 1_000, ingest_ts_column: false })
+    }
+
+    // ( count , max ) per stage of one stream
+    fn stages(lag: &IngestLag, stream: Stream) -> Vec<(u64, u64)> {
+        lag.histograms[stream.index()].iter().map(|h| (h.count, h.max)).collect()
+    }
+
+    #[test]
+    fn flush_splits_the_lag_into_stages() {
+        let mut lag = lag();
+        lag.note(Stream::OrderLogs, IngestStamp { produced: 0, dequeued: MS, picked_up: 3 * MS });
+        lag.flushed(Stream::OrderLogs, 10 * MS);
+
+        let ms = |n: i64| (n * MS) as u64;
+        assert_eq!(stages(&lag, Stream::OrderLogs), [(1, ms(1)), (1, ms(2)), (1, ms(7)), (1, ms(10))]);
+        assert!(lag.pending[Stream::OrderLogs.index()].is_empty());
+    }
+
+    #[test]
+    fn streams_are_bucketed_apart() {
+        let mut lag = lag();
+        lag.note(Stream::OrderLogs, IngestStamp { produced: 0, dequeued: 0, picked_up: 0 });
+        lag.note(Stream::TradeLogs, IngestStamp { produced: 0, dequeued: 0, picked_up: 0 });
+        lag.note(Stream::TradeLogs, IngestStamp { produced: 0, dequeued: 0, picked_up: 0 });
+        // only the trade buffer went out
+        lag.flushed(Stream::TradeLogs, MS);
+
+        assert_eq!(stages(&lag, Stream::TradeLogs)[3], (2, MS as u64));
+        assert_eq!(stages(&lag, Stream::OrderLogs)[3], (0, 0));
+        assert_eq!(lag.pending[Stream::OrderLogs.index()].len(), 1);
+    }
+
+    #[test]
+    fn failed_flush_records_nothing() {
+        let mut lag = lag();
+        lag.note(Stream::BalanceLogs, IngestStamp { produced: 0, dequeued: 0, picked_up: 0 });
+        lag.dropped(Stream::BalanceLogs);
+        lag.flushed(Stream::BalanceLogs, MS);
+        assert_eq!(stages(&lag, Stream::BalanceLogs)[3], (0, 0));
+    }
+
+    #[test]
+    fn report_writes_a_row_per_stream_and_stage_then_resets() {
+        let mut lag = lag();
+        lag.note(Stream::HoldingLogs, IngestStamp { produced: 0, dequeued: MS, picked_up: 2 * MS });
+        lag.flushed(Stream::HoldingLogs, 4 * MS);
+
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        lag.report(2_000 * MS, &mut out).unwrap();
+        let rows = std::str::from_utf8(out.as_bytes()).unwrap();
+        let stages: Vec<_> = rows
+            .lines()
+            .map(|row| row.strip_prefix("ingest_lag,stream=holding_logs,stage=").unwrap().split(' ').next().unwrap())
+            .collect();
+        assert_eq!(stages, ["shm", "channel", "buffer", "total"]);
+        assert!(lag.histograms[Stream::HoldingLogs.index()].iter().all(Histogram::is_empty));
+
+        // nothing new , nothing written
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        lag.report(4_000 * MS, &mut out).unwrap();
+        assert!(out.is_empty());
+    }
+}
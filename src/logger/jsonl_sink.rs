@@ -0,0 +1,289 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::logger::sink::LogSink;
+use crate::logger::types::{
+    BalanceLogWrapper, HoldingLogWrapper, LogRecord, OrderBookSnapShot, OrderLogWrapper, TradeLogs,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct JsonlConfig {
+    // no dir -> lines go to stdout
+    pub dir: Option<PathBuf>,
+    pub prefix: String,
+    // start a new file once the current one reaches this size ...
+    pub max_file_bytes: u64,
+    // ... or once it has been open this long
+    pub max_file_age_ms: u64,
+    // oldest files are deleted beyond this count , 0 keeps everything
+    pub max_files: usize,
+    pub flush_ms: u64,
+}
+
+impl Default for JsonlConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            prefix: "logs".to_string(),
+            max_file_bytes: 256 * 1024 * 1024,
+            max_file_age_ms: 3_600_000,
+            max_files: 0,
+            flush_ms: 100,
+        }
+    }
+}
+
+pub struct JsonlSink {
+    pub config: JsonlConfig,
+    pub writer: Option<BufWriter<Box<dyn Write + Send>>>,
+    pub file_bytes: u64,
+    pub file_opened: Instant,
+    pub last_flush: Instant,
+    pub line: Vec<u8>,
+}
+
+impl JsonlSink {
+    pub fn new(config: JsonlConfig) -> Self {
+        if let Some(dir) = &config.dir
+            && let Err(e) = fs::create_dir_all(dir)
+        {
+            eprintln!("jsonl sink: failed to create {}: {}", dir.display(), e);
+        }
+        Self {
+            config,
+            writer: None,
+            file_bytes: 0,
+            file_opened: Instant::now(),
+            last_flush: Instant::now(),
+            line: Vec::with_capacity(1024),
+        }
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let out: Box<dyn Write + Send> = match &self.config.dir {
+            None => Box::new(io::stdout()),
+            Some(dir) => {
+                let name = format!("{}-{}.jsonl", self.config.prefix, Utc::now().format("%Y%m%dT%H%M%S%.9f"));
+                Box::new(File::create(dir.join(name))?)
+            }
+        };
+        self.writer = Some(BufWriter::new(out));
+        self.file_bytes = 0;
+        self.file_opened = Instant::now();
+        self.prune();
+        Ok(())
+    }
+
+    fn should_rotate(&self) -> bool {
+        self.config.dir.is_some()
+            && (self.file_bytes >= self.config.max_file_bytes
+                || self.file_opened.elapsed() >= Duration::from_millis(self.config.max_file_age_ms))
+    }
+
+    fn rotate(&mut self) {
+        if let Some(mut writer) = self.writer.take() {
+            let _ = writer.flush();
+        }
+    }
+
+    // file names sort by creation time , drop the oldest beyond max_files
+    fn prune(&self) {
+        let Some(dir) = &self.config.dir else { return };
+        if self.config.max_files == 0 {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(dir) else { return };
+        let prefix = format!("{}-", self.config.prefix);
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".jsonl"))
+            })
+            .collect();
+        files.sort();
+        let excess = files.len().saturating_sub(self.config.max_files);
+        for path in &files[..excess] {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    fn write(&mut self, record: LogRecord) {
+        if self.should_rotate() {
+            self.rotate();
+        }
+        if self.writer.is_none()
+            && let Err(e) = self.open()
+        {
+            eprintln!("jsonl sink: failed to open output: {}", e);
+            return;
+        }
+
+        self.line.clear();
+        if let Err(e) = serde_json::to_writer(&mut self.line, &record) {
+            eprintln!("jsonl sink: failed to encode record: {}", e);
+            return;
+        }
+        self.line.push(b'\n');
+
+        let writer = self.writer.as_mut().unwrap();
+        match writer.write_all(&self.line) {
+            Ok(()) => self.file_bytes += self.line.len() as u64,
+            Err(e) => {
+                eprintln!("jsonl sink: write failed: {}", e);
+                self.writer = None;
+            }
+        }
+    }
+}
+
+impl LogSink for JsonlSink {
+    fn write_order_log(&mut self, log: &OrderLogWrapper) {
+        self.write(LogRecord::OrderLog(*log));
+    }
+
+    fn write_balance_log(&mut self, log: &BalanceLogWrapper) {
+        self.write(LogRecord::BalanceLog(*log));
+    }
+
+    fn write_holding_log(&mut self, log: &HoldingLogWrapper) {
+        self.write(LogRecord::HoldingLog(*log));
+    }
+
+    fn write_trade_log(&mut self, log: &TradeLogs) {
+        self.write(LogRecord::TradeLog(*log));
+    }
+
+    fn write_snapshot(&mut self, snap: &OrderBookSnapShot) {
+        self.write(LogRecord::Snapshot(*snap));
+    }
+
+    fn tick(&mut self) {
+        if self.last_flush.elapsed() < Duration::from_millis(self.config.flush_ms) {
+            return;
+        }
+        self.last_flush = Instant::now();
+        if let Some(writer) = self.writer.as_mut()
+            && let Err(e) = writer.flush()
+        {
+            eprintln!("jsonl sink: flush failed: {}", e);
+            self.writer = None;
+        }
+    }
+}
+
+impl Drop for JsonlSink {
+    fn drop(&mut self) {
+        self.rotate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{order, snapshot, trade};
+    use crate::logger::types::Stream;
+    use chrono::NaiveDateTime;
+    use std::path::Path;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("logger-jsonl-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn config(dir: &Path) -> JsonlConfig {
+        JsonlConfig { dir: Some(dir.to_path_buf()), max_file_bytes: u64::MAX, max_file_age_ms: u64::MAX, ..JsonlConfig::default() }
+    }
+
+    // names in dir , sorted
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> =
+            fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn every_file_past_the_size_limit_is_rotated() {
+        let dir = dir("rotate");
+        let mut sink = JsonlSink::new(JsonlConfig { max_file_bytes: 1, ..config(&dir) });
+        for id in 1..=3 {
+            sink.write_order_log(&order(id, 5));
+        }
+        drop(sink);
+
+        let names = files(&dir);
+        assert_eq!(names.len(), 3);
+        for name in &names {
+            let ts = name.strip_prefix("logs-").and_then(|n| n.strip_suffix(".jsonl")).unwrap();
+            assert!(NaiveDateTime::parse_from_str(ts, "%Y%m%dT%H%M%S%.9f").is_ok(), "{}", name);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pruning_only_touches_files_of_the_prefix() {
+        let dir = dir("prune");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("other-20000101T000000.000000000.jsonl"), "").unwrap();
+        fs::write(dir.join("logs.txt"), "").unwrap();
+        let mut sink = JsonlSink::new(JsonlConfig { max_file_bytes: 1, max_files: 2, ..config(&dir) });
+        for id in 1..=4 {
+            sink.write_order_log(&order(id, 5));
+        }
+        drop(sink);
+
+        let names = files(&dir);
+        assert_eq!(names.iter().filter(|n| n.starts_with("logs-")).count(), 2);
+        assert!(names.contains(&"other-20000101T000000.000000000.jsonl".to_string()));
+        assert!(names.contains(&"logs.txt".to_string()));
+        // the newest two are kept
+        let last = fs::read_to_string(dir.join(names.iter().rfind(|n| n.starts_with("logs-")).unwrap())).unwrap();
+        assert_eq!(serde_json::from_str::<LogRecord>(last.trim()).unwrap().order_ids(), [Some(4), None]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stdout_is_never_rotated() {
+        let mut sink = JsonlSink::new(JsonlConfig { max_file_bytes: 1, max_file_age_ms: 0, ..JsonlConfig::default() });
+        sink.open().unwrap();
+        sink.file_bytes = u64::MAX;
+        assert!(sink.writer.is_some());
+        assert!(!sink.should_rotate());
+    }
+
+    #[test]
+    fn written_lines_read_back_as_records() {
+        let dir = dir("round-trip");
+        let mut sink = JsonlSink::new(config(&dir));
+        sink.write_order_log(&order(1, 5).at(1_000));
+        sink.write_trade_log(&trade(2, 3).at(2_000));
+        sink.write_snapshot(&snapshot(&[(100, 1)], &[(101, 2)]).at(3_000));
+        drop(sink);
+
+        let names = files(&dir);
+        assert_eq!(names.len(), 1);
+        let records: Vec<LogRecord> = fs::read_to_string(dir.join(&names[0]))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let read: Vec<_> = records.iter().map(|r| (r.stream(), r.timestamp(), r.order_ids())).collect();
+        assert_eq!(read, [
+            (Stream::OrderLogs, 1_000, [Some(1), None]),
+            (Stream::TradeLogs, 2_000, [Some(2), Some(3)]),
+            (Stream::Snapshots, 3_000, [None, None]),
+        ]);
+        let LogRecord::Snapshot(snap) = records[2] else { unreachable!() };
+        assert_eq!((snap.bids[0], snap.asks[0]), ((100, 1), (101, 2)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,124 +1,386 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use std::time::{Instant, Duration};
 use crossbeam::channel::Receiver;
 use questdb::ingress::{Sender, Buffer, TimestampNanos};
 
+use serde::Deserialize;
+
+use crate::logger::book_deltas::{BookDeltaConfig, BookDiffer, LevelChange};
+use crate::logger::conflation::{ConflationConfig, SnapshotConflator, StoredSnapshot};
+use crate::logger::ingest_lag::{IngestLag, IngestLagConfig, IngestStamp};
+use crate::logger::processor::{now_nanos, LogProcessor, NANOS_PER_MILLI};
+use crate::logger::sink::LogSink;
+use crate::shm::sequence::{EventGap, SharedCounters};
 use crate::logger::types::{
+    order_event_type_str,
+    severity_str,
+    side_str,
     BalanceLogWrapper,
     HoldingLogWrapper,
+    Ingested,
     OrderBookSnapShot,
     OrderLogWrapper,
+    Stream,
     TradeLogs,
 };
 
 const FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+const ERROR_SEVERITY: u8 = 1;
 
 const TRADE_BATCH: usize = 256;
 const ORDER_BATCH: usize = 256;
 const BALANCE_BATCH: usize = 256;
 const HOLDING_BATCH: usize = 256;
+const POLLER_COUNTERS_EVERY: i64 = 1_000 * NANOS_PER_MILLI;
+
+// one flusher thread , owning its own QuestDB sender and buffer for the streams listed
+#[derive(Debug, Clone, Deserialize)]
+pub struct FlusherGroupConfig {
+    pub name: String,
+    pub streams: Vec<Stream>,
+    // core to pin the thread to , unpinned when absent
+    pub core: Option<usize>,
+}
+
+impl FlusherGroupConfig {
+    // the original topology , every stream on one thread pinned to core 4
+    pub fn single() -> Self {
+        Self {
+            name: "all".to_string(),
+            streams: Stream::ALL.to_vec(),
+            core: Some(4),
+        }
+    }
+}
+
+// when a table's buffer goes to QuestDB , whichever limit is hit first wins
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct FlushPolicy {
+    pub max_rows: usize,
+    // compared against Buffer::len
+    pub max_bytes: usize,
+    pub max_age_ms: u64,
+    // an error severity record flushes its table right away
+    pub flush_on_error: bool,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self {
+            max_rows: 4096,
+            max_bytes: 1024 * 1024,
+            max_age_ms: FLUSH_INTERVAL.as_millis() as u64,
+            flush_on_error: true,
+        }
+    }
+}
+
+impl FlushPolicy {
+    pub fn default_for(stream: Stream) -> Self {
+        match stream {
+            // 🔥 Snapshot correctness > throughput
+            Stream::Snapshots => Self { max_rows: 1, ..Self::default() },
+            _ => Self::default(),
+        }
+    }
+}
+
+pub struct TableBuffer {
+    pub buffer: Buffer,
+    pub policy: FlushPolicy,
+    pub oldest_row: Option<Instant>,
+    pub urgent: bool,
+}
+
+impl TableBuffer {
+    fn new(buffer: Buffer, policy: FlushPolicy) -> Self {
+        Self { buffer, policy, oldest_row: None, urgent: false }
+    }
+
+    #[inline(always)]
+    fn row_added(&mut self) {
+        if self.oldest_row.is_none() {
+            self.oldest_row = Some(Instant::now());
+        }
+    }
+
+    // processors write an unknown number of rows , pick the age up from the buffer itself
+    #[inline(always)]
+    fn note_rows(&mut self) {
+        if self.oldest_row.is_none() && !self.buffer.is_empty() {
+            self.oldest_row = Some(Instant::now());
+        }
+    }
+
+    fn is_due(&self) -> bool {
+        let Some(oldest) = self.oldest_row else {
+            return false;
+        };
+        self.urgent
+            || self.buffer.row_count() >= self.policy.max_rows
+            || self.buffer.len() >= self.policy.max_bytes
+            || oldest.elapsed() >= Duration::from_millis(self.policy.max_age_ms)
+    }
+}
+
+// receivers handed to a flusher , streams owned by another group stay None
+#[derive(Default)]
+pub struct FlusherInputs {
+    pub order_logs: Option<Receiver<Ingested<OrderLogWrapper>>>,
+    pub balance_logs: Option<Receiver<Ingested<BalanceLogWrapper>>>,
+    pub holding_logs: Option<Receiver<Ingested<HoldingLogWrapper>>>,
+    pub trade_logs: Option<Receiver<Ingested<TradeLogs>>>,
+    pub snapshots: Option<Receiver<Ingested<OrderBookSnapShot>>>,
+    // gaps found by the poller and its counters , handed to a single flusher
+    pub event_gaps: Option<Receiver<EventGap>>,
+    pub poller_counters: Option<SharedCounters>,
+}
 
 pub struct LogFlusher {
-    pub order_log_reciver: Receiver<OrderLogWrapper>,
-    pub balance_log_receiver: Receiver<BalanceLogWrapper>,
-    pub holding_log_reciver: Receiver<HoldingLogWrapper>,
-    pub trade_log_reciver: Receiver<TradeLogs>,
-    pub snapshot_reciver: Receiver<OrderBookSnapShot>,
+    pub name: String,
+    pub order_log_reciver: Option<Receiver<Ingested<OrderLogWrapper>>>,
+    pub balance_log_receiver: Option<Receiver<Ingested<BalanceLogWrapper>>>,
+    pub holding_log_reciver: Option<Receiver<Ingested<HoldingLogWrapper>>>,
+    pub trade_log_reciver: Option<Receiver<Ingested<TradeLogs>>>,
+    pub snapshot_reciver: Option<Receiver<Ingested<OrderBookSnapShot>>>,
+    pub event_gap_reciver: Option<Receiver<EventGap>>,
+    pub poller_counters: Option<SharedCounters>,
+    pub counters_reported_at: i64,
+    // drops already printed , per stream
+    pub drops_reported: [u64; 5],
 
     pub sender: Sender,
-    pub buffer: Buffer,
+    // one buffer per table , indexed by Stream::index
+    pub tables: Vec<TableBuffer>,
+
+    pub sinks: Vec<Box<dyn LogSink>>,
+
+    // rows of every derived table share this buffer
+    pub derived: TableBuffer,
+    pub processors: Vec<Box<dyn LogProcessor>>,
+
+    // processors see every snapshot , storage only the conflated ones
+    pub conflator: Option<SnapshotConflator>,
+    pub stored_snapshots: Vec<StoredSnapshot>,
+    // stored snapshots go to QuestDB as level changes between keyframes
+    pub book_differ: Option<BookDiffer>,
+    pub level_changes: Vec<LevelChange>,
+
+    pub ingest_lag: Option<IngestLag>,
+    pub ingest_ts_column: bool,
+    // when the current loop picked records up from the channels
+    pub picked_up: i64,
+}
 
-    pub rows_written: usize,
-    pub last_flush: Instant,
+fn run_processors(
+    processors: &mut [Box<dyn LogProcessor>],
+    derived: &mut TableBuffer,
+    name: &str,
+    f: impl Fn(&mut dyn LogProcessor, &mut Buffer) -> questdb::Result<()>,
+) {
+    for processor in processors.iter_mut() {
+        // a failing processor must not leave half a row behind for the next one
+        let _ = derived.buffer.set_marker();
+        if let Err(e) = f(processor.as_mut(), &mut derived.buffer) {
+            eprintln!("flusher {}: failed to encode derived row: {}", name, e);
+            let _ = derived.buffer.rewind_to_marker();
+        }
+        derived.buffer.clear_marker();
+    }
+    derived.note_rows();
+}
+
+// None when the table was not due , else whether its rows made it to QuestDB
+// rows of a failed flush are dropped , retrying them would only hold up the rows behind
+fn flush_table(sender: &mut Sender, table: &mut TableBuffer, flusher: &str, label: &str) -> Option<bool> {
+    if !table.is_due() {
+        return None;
+    }
+    let flushed = match sender.flush(&mut table.buffer) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("flusher {}: failed to flush {}: {}", flusher, label, e);
+            false
+        }
+    };
+    table.buffer.clear();
+    table.oldest_row = None;
+    table.urgent = false;
+    Some(flushed)
+}
+
+// optional ingest_ts column , then the designated timestamp
+#[inline(always)]
+fn finish_row(buffer: &mut Buffer, ingest_ts: Option<i64>, timestamp: i64) -> questdb::Result<()> {
+    if let Some(ingest_ts) = ingest_ts {
+        buffer.column_ts("ingest_ts", TimestampNanos::new(ingest_ts))?;
+    }
+    buffer.at(TimestampNanos::new(timestamp))?;
+    Ok(())
 }
 
 impl LogFlusher {
-    pub fn new(
-        order_log_reciver: Receiver<OrderLogWrapper>,
-        balance_log_receiver: Receiver<BalanceLogWrapper>,
-        holding_log_reciver: Receiver<HoldingLogWrapper>,
-        trade_log_reciver: Receiver<TradeLogs>,
-        snapshot_reciver: Receiver<OrderBookSnapShot>,
-    ) -> Self {
+    pub fn new(name: &str, inputs: FlusherInputs, policies: &HashMap<Stream, FlushPolicy>) -> Self {
         let sender = Sender::from_conf("http::addr=localhost:9000;")
             .expect("Failed to connect to QuestDB");
+        Self::with_sender(name, inputs, policies, sender)
+    }
 
-        let buffer = sender.new_buffer();
+    pub fn with_sender(name: &str, inputs: FlusherInputs, policies: &HashMap<Stream, FlushPolicy>, sender: Sender) -> Self {
+        let tables = Stream::ALL
+            .iter()
+            .map(|stream| {
+                let policy = policies.get(stream).copied().unwrap_or_else(|| FlushPolicy::default_for(*stream));
+                TableBuffer::new(sender.new_buffer(), policy)
+            })
+            .collect();
+        let derived = TableBuffer::new(sender.new_buffer(), FlushPolicy::default());
 
         Self {
-            order_log_reciver,
-            balance_log_receiver,
-            holding_log_reciver,
-            trade_log_reciver,
-            snapshot_reciver,
+            name: name.to_string(),
+            order_log_reciver: inputs.order_logs,
+            balance_log_receiver: inputs.balance_logs,
+            holding_log_reciver: inputs.holding_logs,
+            trade_log_reciver: inputs.trade_logs,
+            snapshot_reciver: inputs.snapshots,
+            event_gap_reciver: inputs.event_gaps,
+            poller_counters: inputs.poller_counters,
+            counters_reported_at: 0,
+            drops_reported: [0; 5],
             sender,
-            buffer,
-            rows_written: 0,
-            last_flush: Instant::now(),
+            tables,
+            sinks: Vec::new(),
+            derived,
+            processors: Vec::new(),
+            conflator: None,
+            stored_snapshots: Vec::new(),
+            book_differ: None,
+            level_changes: Vec::new(),
+            ingest_lag: None,
+            ingest_ts_column: false,
+            picked_up: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn table_mut(&mut self, stream: Stream) -> &mut TableBuffer {
+        &mut self.tables[stream.index()]
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn LogSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn add_processor(&mut self, processor: Box<dyn LogProcessor>) {
+        self.processors.push(processor);
+    }
+
+    pub fn set_derived_policy(&mut self, policy: FlushPolicy) {
+        self.derived.policy = policy;
+    }
+
+    pub fn set_conflation(&mut self, config: ConflationConfig) {
+        self.conflator = Some(SnapshotConflator::new(config));
+    }
+
+    pub fn set_book_deltas(&mut self, config: BookDeltaConfig) {
+        self.book_differ = Some(BookDiffer::new(config));
+    }
+
+    pub fn set_ingest_lag(&mut self, config: IngestLagConfig) {
+        self.ingest_ts_column = config.ingest_ts_column;
+        self.ingest_lag = Some(IngestLag::new(config));
+    }
+
+    #[inline(always)]
+    fn note_ingest(&mut self, stream: Stream, produced: i64, dequeued: i64, picked_up: i64) {
+        if let Some(lag) = self.ingest_lag.as_mut() {
+            lag.note(stream, IngestStamp { produced, dequeued, picked_up });
         }
     }
 
    
 
     #[inline(always)]
-    fn encode_snapshot(&mut self, snap: OrderBookSnapShot) -> questdb::Result<()> {
+    fn encode_snapshot(&mut self, stored: StoredSnapshot) -> questdb::Result<()> {
+        let snap = stored.snap;
         let bids_json = serde_json::to_string(&snap.bids).unwrap();
         let asks_json = serde_json::to_string(&snap.asks).unwrap();
 
-        self.buffer
+        let ingest_ts = self.ingest_ts_column.then_some(stored.dequeued_at);
+        let table = self.table_mut(Stream::Snapshots);
+        table.buffer
             .table("orderbook_snapshots")?
             .symbol("symbol", snap.symbol.to_string())?
             .column_i64("snapshot_id", snap.event_id as i64)?
             .column_str("bids", &bids_json)?
             .column_str("asks", &asks_json)?
-            .at(TimestampNanos::new(snap.timestamp))?;
-
-        // 🔥 Snapshot correctness > throughput
-        self.sender.flush(&mut self.buffer)?;
-        self.buffer = self.sender.new_buffer();
+            .column_i64("conflated_count", stored.conflated as i64)?;
+        finish_row(&mut table.buffer, ingest_ts, snap.timestamp)?;
 
+        table.row_added();
+        self.note_ingest(Stream::Snapshots, snap.timestamp, stored.dequeued_at, stored.picked_up);
         Ok(())
     }
 
-   
+    fn encode_book_deltas(&mut self, stored: &StoredSnapshot) -> questdb::Result<()> {
+        let snap = &stored.snap;
+        let ingest_ts = self.ingest_ts_column.then_some(stored.dequeued_at);
+        for i in 0..self.level_changes.len() {
+            let change = self.level_changes[i];
+            let table = self.table_mut(Stream::Snapshots);
+            table.buffer
+                .table("orderbook_deltas")?
+                .symbol("symbol", snap.symbol.to_string())?
+                .symbol("side", side_str(change.side))?
+                .column_i64("snapshot_id", snap.event_id as i64)?
+                .column_i64("price", change.price as i64)?
+                .column_i64("old_qty", change.old_qty as i64)?
+                .column_i64("new_qty", change.new_qty as i64)?
+                .column_i64("conflated_count", stored.conflated as i64)?;
+            finish_row(&mut table.buffer, ingest_ts, snap.timestamp)?;
+
+            table.row_added();
+        }
+        // the snapshot's lag is counted once , however many rows it became
+        if !self.level_changes.is_empty() {
+            self.note_ingest(Stream::Snapshots, snap.timestamp, stored.dequeued_at, stored.picked_up);
+        }
+        Ok(())
+    }
 
     #[inline(always)]
-    fn encode_order_log(&mut self, log: OrderLogWrapper) -> questdb::Result<()> {
-        self.buffer
+    fn encode_order_log(&mut self, ingested: Ingested<OrderLogWrapper>) -> questdb::Result<()> {
+        let Ingested { record: log, dequeued_at } = ingested;
+        let ingest_ts = self.ingest_ts_column.then_some(dequeued_at);
+        let table = self.table_mut(Stream::OrderLogs);
+        table.buffer
             .table("order_logs")?
             .symbol("instrument", log.order_delta.symbol.to_string())?
-            .symbol("side", if log.order_delta.side == 0 { "bid" } else { "ask" })?
-            .symbol(
-                "event_type",
-                match log.order_delta.order_event_type {
-                    0 => "received",
-                    1 => "matched",
-                    2 => "canceled",
-                    _ => "unknown",
-                },
-            )?
-            .symbol(
-                "severity",
-                match log.severity {
-                    0 => "info",
-                    1 => "error",
-                    2 => "debug",
-                    _ => "unknown",
-                },
-            )?
+            .symbol("side", side_str(log.order_delta.side))?
+            .symbol("event_type", order_event_type_str(log.order_delta.order_event_type))?
+            .symbol("severity", severity_str(log.severity))?
             .column_i64("event_id", log.order_delta.event_id as i64)?
             .column_i64("order_id", log.order_delta.order_id as i64)?
             .column_i64("user_id", log.order_delta.user_id as i64)?
             .column_i64("price", log.order_delta.price as i64)?
-            .column_i64("shares_qty", log.order_delta.shares_qty as i64)?
-            .at(TimestampNanos::new(log.timestamp))?;
+            .column_i64("shares_qty", log.order_delta.shares_qty as i64)?;
+        finish_row(&mut table.buffer, ingest_ts, log.timestamp)?;
 
-        self.rows_written += 1;
+        table.urgent |= table.policy.flush_on_error && log.severity == ERROR_SEVERITY;
+        table.row_added();
+        self.note_ingest(Stream::OrderLogs, log.timestamp, dequeued_at, self.picked_up);
         Ok(())
     }
 
     #[inline(always)]
-    fn encode_balance_log(&mut self, log: BalanceLogWrapper) -> questdb::Result<()> {
-        self.buffer
+    fn encode_balance_log(&mut self, ingested: Ingested<BalanceLogWrapper>) -> questdb::Result<()> {
+        let Ingested { record: log, dequeued_at } = ingested;
+        let ingest_ts = self.ingest_ts_column.then_some(dequeued_at);
+        let table = self.table_mut(Stream::BalanceLogs);
+        table.buffer
             .table("balance_logs")?
             .symbol("reason", if log.balance_delta.reason == 0 { "lock" } else { "update" })?
             .symbol("severity", "info")?
@@ -126,16 +388,21 @@ impl LogFlusher {
             .column_i64("user_id", log.balance_delta.user_id as i64)?
             .column_i64("order_id", log.balance_delta.order_id as i64)?
             .column_i64("delta_reserved_balance", log.balance_delta.delta_reserved as i64)?
-            .column_i64("delta_available_balance", log.balance_delta.delta_available as i64)?
-            .at(TimestampNanos::new(log.timestamp))?;
+            .column_i64("delta_available_balance", log.balance_delta.delta_available as i64)?;
+        finish_row(&mut table.buffer, ingest_ts, log.timestamp)?;
 
-        self.rows_written += 1;
+        table.urgent |= table.policy.flush_on_error && log.severity == ERROR_SEVERITY;
+        table.row_added();
+        self.note_ingest(Stream::BalanceLogs, log.timestamp, dequeued_at, self.picked_up);
         Ok(())
     }
 
     #[inline(always)]
-    fn encode_holding_log(&mut self, log: HoldingLogWrapper) -> questdb::Result<()> {
-        self.buffer
+    fn encode_holding_log(&mut self, ingested: Ingested<HoldingLogWrapper>) -> questdb::Result<()> {
+        let Ingested { record: log, dequeued_at } = ingested;
+        let ingest_ts = self.ingest_ts_column.then_some(dequeued_at);
+        let table = self.table_mut(Stream::HoldingLogs);
+        table.buffer
             .table("holding_logs")?
             .symbol("instrument", log.holding_delta.symbol.to_string())?
             .symbol("reason", "update")?
@@ -144,40 +411,137 @@ impl LogFlusher {
             .column_i64("user_id", log.holding_delta.user_id as i64)?
             .column_i64("order_id", log.holding_delta.order_id as i64)?
             .column_i64("delta_reserved_holding", log.holding_delta.delta_reserved as i64)?
-            .column_i64("delta_available_holding", log.holding_delta.delta_available as i64)?
-            .at(TimestampNanos::new(log.timestamp))?;
+            .column_i64("delta_available_holding", log.holding_delta.delta_available as i64)?;
+        finish_row(&mut table.buffer, ingest_ts, log.timestamp)?;
 
-        self.rows_written += 1;
+        table.urgent |= table.policy.flush_on_error && log.severity == ERROR_SEVERITY;
+        table.row_added();
+        self.note_ingest(Stream::HoldingLogs, log.timestamp, dequeued_at, self.picked_up);
         Ok(())
     }
 
     #[inline(always)]
-    fn encode_trade_log(&mut self, log: TradeLogs) -> questdb::Result<()> {
-        self.buffer
+    fn encode_trade_log(&mut self, ingested: Ingested<TradeLogs>) -> questdb::Result<()> {
+        let Ingested { record: log, dequeued_at } = ingested;
+        let ingest_ts = self.ingest_ts_column.then_some(dequeued_at);
+        let table = self.table_mut(Stream::TradeLogs);
+        table.buffer
             .table("trade_logs")?
             .symbol("symbol", log.symbol.to_string())?
             .column_i64("price", log.price as i64)?
             .column_i64("quantity", log.quantity as i64)?
             .column_i64("buyer_order_id", log.buyer_order_id as i64)?
             .column_i64("seller_order_id", log.seller_order_id as i64)?
-            .column_bool("is_buyer_maker", log.is_buyer_maker)?
-            .at(TimestampNanos::new(log.timestamp))?;
+            .column_bool("is_buyer_maker", log.is_buyer_maker)?;
+        finish_row(&mut table.buffer, ingest_ts, log.timestamp)?;
 
-        self.rows_written += 1;
+        table.row_added();
+        self.note_ingest(Stream::TradeLogs, log.timestamp, dequeued_at, self.picked_up);
         Ok(())
     }
 
-    fn try_flush(&mut self) {
-        if self.rows_written == 0 {
-            return;
+    fn encode_event_gap(&mut self, gap: EventGap) -> questdb::Result<()> {
+        // logged as well , the event_gaps row is lost while QuestDB is down
+        eprintln!(
+            "poller: {} in {}{} , expected event {} got {} ({} missing)",
+            gap.kind.as_str(), gap.stream.table(), gap.source.map(|s| format!(" source {}", s)).unwrap_or_default(),
+            gap.expected, gap.got, gap.missing()
+        );
+        self.derived.buffer
+            .table("event_gaps")?
+            .symbol("stream", gap.stream.table())?
+            .symbol("kind", gap.kind.as_str())?
+            .column_i64("source", gap.source.map(|s| s as i64).unwrap_or(-1))?
+            .column_i64("expected_event_id", gap.expected as i64)?
+            .column_i64("got_event_id", gap.got as i64)?
+            .column_i64("missing", gap.missing() as i64)?
+            .at(TimestampNanos::new(gap.timestamp))?;
+
+        self.derived.row_added();
+        Ok(())
+    }
+
+    // totals since the poller started , one row per stream
+    fn encode_poller_counters(&mut self, now: i64) -> questdb::Result<()> {
+        let Some(counters) = self.poller_counters.as_ref() else {
+            return Ok(());
+        };
+        if now - self.counters_reported_at < POLLER_COUNTERS_EVERY {
+            return Ok(());
+        }
+        self.counters_reported_at = now;
+        for (stream, c) in Stream::ALL.iter().zip(counters.iter()) {
+            let dropped = c.dropped.load(Ordering::Relaxed);
+            let reported = &mut self.drops_reported[stream.index()];
+            if dropped > *reported {
+                eprintln!(
+                    "poller: dropped {} {} records on a full channel ({} so far)",
+                    dropped - *reported, stream.table(), dropped
+                );
+                *reported = dropped;
+            }
+            self.derived.buffer
+                .table("poller_counters")?
+                .symbol("stream", stream.table())?
+                .column_i64("gaps", c.gaps.load(Ordering::Relaxed) as i64)?
+                .column_i64("missing", c.missing.load(Ordering::Relaxed) as i64)?
+                .column_i64("out_of_order", c.out_of_order.load(Ordering::Relaxed) as i64)?
+                .column_i64("duplicates", c.duplicates.load(Ordering::Relaxed) as i64)?
+                .column_i64("dropped", dropped as i64)?
+                .column_i64("gap_rows_dropped", c.gap_rows_dropped.load(Ordering::Relaxed) as i64)?
+                .at(TimestampNanos::new(now))?;
+            self.derived.row_added();
+        }
+        Ok(())
+    }
+
+    fn store_snapshots(&mut self) {
+        let mut stored = std::mem::take(&mut self.stored_snapshots);
+        for s in stored.drain(..) {
+            for sink in self.sinks.iter_mut().filter(|sink| !sink.every_snapshot()) {
+                sink.write_snapshot(&s.snap);
+            }
+            let keyframe = match self.book_differ.as_mut() {
+                Some(differ) => differ.next(&s.snap, &mut self.level_changes),
+                None => true,
+            };
+            if keyframe {
+                let _ = self.encode_snapshot(s);
+            } else {
+                let _ = self.encode_book_deltas(&s);
+            }
         }
+        self.stored_snapshots = stored;
+    }
 
-        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
-            let _ = self.sender.flush(&mut self.buffer);
-            self.buffer = self.sender.new_buffer();
-            self.rows_written = 0;
-            self.last_flush = Instant::now();
+    fn on_snapshot(&mut self, ingested: Ingested<OrderBookSnapShot>) {
+        let snap = ingested.record;
+        for sink in self.sinks.iter_mut().filter(|sink| sink.every_snapshot()) {
+            sink.write_snapshot(&snap);
+        }
+        run_processors(&mut self.processors, &mut self.derived, &self.name, |p, out| p.on_snapshot(&snap, out));
+        let stored = StoredSnapshot { snap, conflated: 0, dequeued_at: ingested.dequeued_at, picked_up: self.picked_up };
+        match self.conflator.as_mut() {
+            Some(conflator) => conflator.offer(stored, &mut self.stored_snapshots),
+            None => self.stored_snapshots.push(stored),
         }
+        self.store_snapshots();
+    }
+
+    fn try_flush(&mut self) {
+        for (stream, table) in Stream::ALL.iter().zip(self.tables.iter_mut()) {
+            let Some(flushed) = flush_table(&mut self.sender, table, &self.name, stream.table()) else {
+                continue;
+            };
+            if let Some(lag) = self.ingest_lag.as_mut() {
+                if flushed {
+                    lag.flushed(*stream, now_nanos());
+                } else {
+                    lag.dropped(*stream);
+                }
+            }
+        }
+        flush_table(&mut self.sender, &mut self.derived, &self.name, "derived tables");
     }
 
    
@@ -185,42 +549,101 @@ impl LogFlusher {
     pub fn run(&mut self) {
         loop {
             let mut did_work = false;
+            self.picked_up = now_nanos();
 
            
-            while let Ok(snap) = self.snapshot_reciver.try_recv() {
-                let _ = self.encode_snapshot(snap);
-                did_work = true;
-            }
-
+            // trades first , the conflator has to know about a trade before the snapshot after it
             for _ in 0..TRADE_BATCH {
-                if let Ok(log) = self.trade_log_reciver.try_recv() {
-                    let _ = self.encode_trade_log(log);
+                if let Some(Ok(ingested)) = self.trade_log_reciver.as_ref().map(|rx| rx.try_recv()) {
+                    let log = ingested.record;
+                    for sink in self.sinks.iter_mut() {
+                        sink.write_trade_log(&log);
+                    }
+                    run_processors(&mut self.processors, &mut self.derived, &self.name, |p, out| p.on_trade_log(&log, out));
+                    if let Some(conflator) = self.conflator.as_mut() {
+                        conflator.note_trade(log.symbol, log.timestamp);
+                    }
+                    let _ = self.encode_trade_log(ingested);
                     did_work = true;
                 } else { break; }
             }
 
+            while let Some(Ok(ingested)) = self.snapshot_reciver.as_ref().map(|rx| rx.try_recv()) {
+                self.on_snapshot(ingested);
+                did_work = true;
+            }
+
             for _ in 0..ORDER_BATCH {
-                if let Ok(log) = self.order_log_reciver.try_recv() {
-                    let _ = self.encode_order_log(log);
+                if let Some(Ok(ingested)) = self.order_log_reciver.as_ref().map(|rx| rx.try_recv()) {
+                    let log = ingested.record;
+                    for sink in self.sinks.iter_mut() {
+                        sink.write_order_log(&log);
+                    }
+                    run_processors(&mut self.processors, &mut self.derived, &self.name, |p, out| p.on_order_log(&log, out));
+                    let _ = self.encode_order_log(ingested);
                     did_work = true;
                 } else { break; }
             }
 
             for _ in 0..BALANCE_BATCH {
-                if let Ok(log) = self.balance_log_receiver.try_recv() {
-                    let _ = self.encode_balance_log(log);
+                if let Some(Ok(ingested)) = self.balance_log_receiver.as_ref().map(|rx| rx.try_recv()) {
+                    let log = ingested.record;
+                    for sink in self.sinks.iter_mut() {
+                        sink.write_balance_log(&log);
+                    }
+                    run_processors(&mut self.processors, &mut self.derived, &self.name, |p, out| p.on_balance_log(&log, out));
+                    let _ = self.encode_balance_log(ingested);
                     did_work = true;
                 } else { break; }
             }
 
             for _ in 0..HOLDING_BATCH {
-                if let Ok(log) = self.holding_log_reciver.try_recv() {
-                    let _ = self.encode_holding_log(log);
+                if let Some(Ok(ingested)) = self.holding_log_reciver.as_ref().map(|rx| rx.try_recv()) {
+                    let log = ingested.record;
+                    for sink in self.sinks.iter_mut() {
+                        sink.write_holding_log(&log);
+                    }
+                    run_processors(&mut self.processors, &mut self.derived, &self.name, |p, out| p.on_holding_log(&log, out));
+                    let _ = self.encode_holding_log(ingested);
                     did_work = true;
                 } else { break; }
             }
 
+            while let Some(Ok(gap)) = self.event_gap_reciver.as_ref().map(|rx| rx.try_recv()) {
+                let _ = self.encode_event_gap(gap);
+                did_work = true;
+            }
+
+            let now = now_nanos();
+            if let Err(e) = self.encode_poller_counters(now) {
+                eprintln!("flusher {}: failed to encode poller counters: {}", self.name, e);
+            }
+            run_processors(&mut self.processors, &mut self.derived, &self.name, |p, out| p.on_tick(now, out));
+            if let Some(lag) = self.ingest_lag.as_mut() {
+                if let Err(e) = lag.report(now, &mut self.derived.buffer) {
+                    eprintln!("flusher {}: failed to encode ingest lag: {}", self.name, e);
+                }
+                self.derived.note_rows();
+            }
+            if let Some(conflator) = self.conflator.as_mut() {
+                conflator.on_tick(now, &mut self.stored_snapshots);
+                if let Err(e) = conflator.report(now, &mut self.derived.buffer) {
+                    eprintln!("flusher {}: failed to encode conflation counts: {}", self.name, e);
+                }
+                self.derived.note_rows();
+                self.store_snapshots();
+            }
+            if let Some(differ) = self.book_differ.as_mut() {
+                if let Err(e) = differ.report(now, &mut self.derived.buffer) {
+                    eprintln!("flusher {}: failed to encode book delta counts: {}", self.name, e);
+                }
+                self.derived.note_rows();
+            }
+
             self.try_flush();
+            for sink in self.sinks.iter_mut() {
+                sink.tick();
+            }
 
             if !did_work {
                 std::thread::sleep(Duration::from_millis(50));
@@ -228,3 +651,170 @@ impl LogFlusher {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::audit_journal::{AuditJournal, JournalConfig, JournalLine};
+    use crate::logger::types::test_records::{balance, order, snapshot};
+    use questdb::ingress::ProtocolVersion;
+    use crate::shm::sequence::GapKind;
+    use ed25519_dalek::SigningKey;
+
+    fn flusher() -> LogFlusher {
+        let inputs = FlusherInputs {
+            order_logs: None,
+            balance_logs: None,
+            holding_logs: None,
+            trade_logs: None,
+            snapshots: None,
+            event_gaps: None,
+            poller_counters: None,
+        };
+        // a pinned protocol version , the sender does not ask a server for it , and no retries
+        // since nothing listens there
+        let sender = Sender::from_conf("http::addr=localhost:9000;protocol_version=1;retry_timeout=0;").unwrap();
+        LogFlusher::with_sender("test", inputs, &HashMap::new(), sender)
+    }
+
+    #[test]
+    fn conflated_snapshots_still_reach_the_journal() {
+        let dir = std::env::temp_dir().join(format!("logger-flusher-{}-journal", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = JournalConfig { dir: dir.clone(), ..JournalConfig::default() };
+        let path = config.path();
+
+        let mut flusher = flusher();
+        flusher.set_conflation(ConflationConfig { interval_ms: 100, report_ms: 10_000 });
+        flusher.add_sink(Box::new(AuditJournal::new(config, SigningKey::from_bytes(&[7u8; 32])).unwrap()));
+        // one interval , the first goes to storage , the second is replaced by the third
+        for ms in 1..=3 {
+            let snap = snapshot(&[(100, ms as u32)], &[]).at(ms * NANOS_PER_MILLI);
+            flusher.on_snapshot(Ingested::new(snap));
+        }
+        assert_eq!(flusher.conflator.as_ref().unwrap().symbols[&7].conflated, 1);
+        drop(flusher);
+
+        let journal = std::fs::read_to_string(&path).unwrap();
+        let snapshots = journal
+            .lines()
+            .filter(|line| JournalLine::parse(line).unwrap().kind == Stream::Snapshots.table())
+            .count();
+        assert_eq!(snapshots, 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn event_gap_rows_carry_the_source() {
+        let mut flusher = flusher();
+        let gap = |source| EventGap { stream: Stream::Snapshots, source, kind: GapKind::Gap, expected: 5, got: 8, timestamp: 1 };
+        flusher.encode_event_gap(gap(Some(7))).unwrap();
+        flusher.encode_event_gap(gap(None)).unwrap();
+
+        let rows = std::str::from_utf8(flusher.derived.buffer.as_bytes()).unwrap();
+        let sources: Vec<_> = rows.lines().filter_map(|row| row.split("source=").nth(1)?.split(',').next()).collect();
+        assert_eq!(sources, ["7i", "-1i"]);
+        assert!(rows.contains("missing=3i"));
+    }
+
+    #[test]
+    fn ingest_ts_column_is_written_only_when_asked() {
+        let mut flusher = flusher();
+        flusher.encode_order_log(Ingested { record: order(1, 5).at(1), dequeued_at: 2 }).unwrap();
+        flusher.set_ingest_lag(IngestLagConfig { report_ms: 1_000, ingest_ts_column: true });
+        flusher.encode_order_log(Ingested { record: order(2, 5).at(1), dequeued_at: 2_000 }).unwrap();
+
+        let table = &flusher.tables[Stream::OrderLogs.index()];
+        let rows: Vec<_> = std::str::from_utf8(table.buffer.as_bytes()).unwrap().lines().map(str::to_string).collect();
+        assert!(!rows[0].contains("ingest_ts="));
+        // microseconds on the wire
+        assert!(rows[1].contains("ingest_ts=2t"));
+        assert_eq!(flusher.ingest_lag.as_ref().unwrap().pending[Stream::OrderLogs.index()].len(), 1);
+    }
+
+    #[test]
+    fn failed_flush_leaves_no_lag_behind() {
+        let mut flusher = flusher();
+        flusher.set_ingest_lag(IngestLagConfig::default());
+        flusher.encode_order_log(Ingested { record: order(1, 5).at(1).severity(ERROR_SEVERITY), dequeued_at: 2 }).unwrap();
+        flusher.try_flush();
+
+        let lag = flusher.ingest_lag.as_ref().unwrap();
+        assert!(lag.pending[Stream::OrderLogs.index()].is_empty());
+        assert!(lag.histograms[Stream::OrderLogs.index()].iter().all(|h| h.is_empty()));
+        assert!(flusher.tables[Stream::OrderLogs.index()].buffer.is_empty());
+    }
+
+    fn table(policy: FlushPolicy) -> TableBuffer {
+        TableBuffer::new(Buffer::new(ProtocolVersion::V1), policy)
+    }
+
+    fn add_row(table: &mut TableBuffer) {
+        table.buffer.table("t").unwrap().column_i64("v", 1).unwrap().at(TimestampNanos::new(1)).unwrap();
+        table.row_added();
+    }
+
+    // nothing but the limit under test can make the table due
+    const NEVER: FlushPolicy =
+        FlushPolicy { max_rows: usize::MAX, max_bytes: usize::MAX, max_age_ms: u64::MAX, flush_on_error: true };
+
+    #[test]
+    fn empty_table_is_never_due() {
+        let table = table(FlushPolicy { max_rows: 0, max_bytes: 0, max_age_ms: 0, ..NEVER });
+        assert!(!table.is_due());
+    }
+
+    #[test]
+    fn table_is_due_at_max_rows() {
+        let mut table = table(FlushPolicy { max_rows: 3, ..NEVER });
+        add_row(&mut table);
+        add_row(&mut table);
+        assert!(!table.is_due());
+        add_row(&mut table);
+        assert!(table.is_due());
+    }
+
+    #[test]
+    fn table_is_due_at_max_bytes() {
+        let mut table = table(NEVER);
+        add_row(&mut table);
+        table.policy.max_bytes = table.buffer.len() + 1;
+        assert!(!table.is_due());
+        add_row(&mut table);
+        assert!(table.is_due());
+    }
+
+    #[test]
+    fn table_is_due_at_max_age() {
+        let mut table = table(FlushPolicy { max_age_ms: 50, ..NEVER });
+        add_row(&mut table);
+        assert!(!table.is_due());
+        table.oldest_row = Some(Instant::now() - Duration::from_millis(50));
+        assert!(table.is_due());
+    }
+
+    #[test]
+    fn error_record_flushes_only_its_table() {
+        let mut flusher = flusher();
+        flusher.tables.iter_mut().for_each(|table| table.policy = NEVER);
+        flusher.encode_balance_log(Ingested { record: balance(1, 5).at(1), dequeued_at: 1 }).unwrap();
+        flusher.encode_order_log(Ingested { record: order(1, 5).at(1), dequeued_at: 1 }).unwrap();
+        assert!(!flusher.tables[Stream::OrderLogs.index()].is_due());
+        flusher.encode_order_log(Ingested { record: order(1, 5).at(1).severity(ERROR_SEVERITY), dequeued_at: 1 }).unwrap();
+        assert!(flusher.tables[Stream::OrderLogs.index()].urgent);
+        flusher.try_flush();
+
+        assert!(flusher.tables[Stream::OrderLogs.index()].buffer.is_empty());
+        let balances = &flusher.tables[Stream::BalanceLogs.index()];
+        assert_eq!(balances.buffer.row_count(), 1);
+        assert!(balances.oldest_row.is_some());
+    }
+
+    #[test]
+    fn error_record_waits_when_the_policy_says_so() {
+        let mut flusher = flusher();
+        flusher.tables.iter_mut().for_each(|table| table.policy = FlushPolicy { flush_on_error: false, ..NEVER });
+        flusher.encode_order_log(Ingested { record: order(1, 5).at(1).severity(ERROR_SEVERITY), dequeued_at: 1 }).unwrap();
+        assert!(!flusher.tables[Stream::OrderLogs.index()].is_due());
+    }
+}
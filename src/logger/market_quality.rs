@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::{LogProcessor, NANOS_PER_MILLI};
+use crate::logger::types::{OrderBookSnapShot, Stream};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MarketQualityConfig {
+    pub tick_size: u64,
+    // per symbol tick sizes , keyed by the symbol id as a string
+    pub tick_sizes: HashMap<String, u64>,
+    // depth is summed over levels within this many ticks of the mid
+    pub depth_ticks: u64,
+    pub interval_ms: u64,
+}
+
+impl Default for MarketQualityConfig {
+    fn default() -> Self {
+        Self {
+            tick_size: 1,
+            tick_sizes: HashMap::new(),
+            depth_ticks: 10,
+            interval_ms: 1_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Bbo {
+    pub bid: Option<(u64, u32)>,
+    pub ask: Option<(u64, u32)>,
+}
+
+impl Bbo {
+    // levels are not assumed to be sorted , empty levels are zero filled
+    pub fn from_snapshot(snap: &OrderBookSnapShot) -> Self {
+        let bid = snap.bids.iter().filter(|(_, q)| *q > 0).max_by_key(|(p, _)| *p).copied();
+        let ask = snap.asks.iter().filter(|(_, q)| *q > 0).min_by_key(|(p, _)| *p).copied();
+        Self { bid, ask }
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.bid?.0 as f64 + self.ask?.0 as f64) / 2.0)
+    }
+
+    pub fn spread(&self) -> Option<i64> {
+        Some(self.ask?.0 as i64 - self.bid?.0 as i64)
+    }
+
+    pub fn spread_bps(&self) -> Option<f64> {
+        let (mid, spread) = (self.mid()?, self.spread()?);
+        (mid > 0.0).then(|| spread as f64 / mid * 10_000.0)
+    }
+}
+
+// running metrics of one symbol over the current interval
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QualityWindow {
+    pub start: i64,
+    pub snapshots: u64,
+    pub bid_depth_sum: u64,
+    pub ask_depth_sum: u64,
+    pub imbalance_sum: f64,
+    pub imbalance_count: u64,
+    // spread in bps weighted by how long it was in force
+    pub weighted_spread: f64,
+    pub weighted_time: i64,
+    pub min_spread_ticks: Option<f64>,
+    pub max_spread_ticks: Option<f64>,
+    // spread of the latest snapshot , carried into the next interval
+    pub last_spread_bps: Option<f64>,
+    pub last_at: i64,
+}
+
+impl QualityWindow {
+    fn advance(&mut self, to: i64) {
+        if let Some(spread) = self.last_spread_bps
+            && to > self.last_at
+        {
+            self.weighted_spread += spread * (to - self.last_at) as f64;
+            self.weighted_time += to - self.last_at;
+        }
+        self.last_at = self.last_at.max(to);
+    }
+}
+
+pub struct MarketQuality {
+    pub config: MarketQualityConfig,
+    pub tick_sizes: HashMap<u32, u64>,
+    pub windows: HashMap<u32, QualityWindow>,
+    pub interval: i64,
+}
+
+impl MarketQuality {
+    pub fn new(config: MarketQualityConfig) -> Self {
+        let tick_sizes = config
+            .tick_sizes
+            .iter()
+            .filter_map(|(symbol, tick)| Some((symbol.parse().ok()?, *tick)))
+            .collect();
+        let interval = config.interval_ms.max(1) as i64 * NANOS_PER_MILLI;
+        Self {
+            config,
+            tick_sizes,
+            windows: HashMap::new(),
+            interval,
+        }
+    }
+
+    fn tick_size(&self, symbol: u32) -> u64 {
+        self.tick_sizes.get(&symbol).copied().unwrap_or(self.config.tick_size).max(1)
+    }
+
+    fn emit_bbo(out: &mut Buffer, snap: &OrderBookSnapShot, bbo: &Bbo, tick: u64) -> questdb::Result<()> {
+        out.table("bbo")?.symbol("symbol", snap.symbol.to_string())?;
+        if let Some((price, qty)) = bbo.bid {
+            out.column_i64("bid_price", price as i64)?.column_i64("bid_size", qty as i64)?;
+        }
+        if let Some((price, qty)) = bbo.ask {
+            out.column_i64("ask_price", price as i64)?.column_i64("ask_size", qty as i64)?;
+        }
+        if let (Some(mid), Some(spread), Some(bps)) = (bbo.mid(), bbo.spread(), bbo.spread_bps()) {
+            out.column_f64("mid", mid)?
+                .column_f64("spread_ticks", spread as f64 / tick as f64)?
+                .column_f64("spread_bps", bps)?;
+        }
+        out.column_i64("snapshot_id", snap.event_id as i64)?
+            .at(TimestampNanos::new(snap.timestamp))
+    }
+
+    fn emit_window(out: &mut Buffer, symbol: u32, w: &QualityWindow, interval: i64) -> questdb::Result<()> {
+        out.table("market_quality")?
+            .symbol("symbol", symbol.to_string())?
+            .column_i64("snapshots", w.snapshots as i64)?
+            .column_i64("interval_ms", interval / NANOS_PER_MILLI)?;
+        if w.snapshots > 0 {
+            out.column_f64("avg_bid_depth", w.bid_depth_sum as f64 / w.snapshots as f64)?
+                .column_f64("avg_ask_depth", w.ask_depth_sum as f64 / w.snapshots as f64)?;
+        }
+        if w.imbalance_count > 0 {
+            out.column_f64("avg_imbalance", w.imbalance_sum / w.imbalance_count as f64)?;
+        }
+        if w.weighted_time > 0 {
+            out.column_f64("twa_spread_bps", w.weighted_spread / w.weighted_time as f64)?;
+        }
+        if let (Some(min), Some(max)) = (w.min_spread_ticks, w.max_spread_ticks) {
+            out.column_f64("min_spread_ticks", min)?.column_f64("max_spread_ticks", max)?;
+        }
+        out.at(TimestampNanos::new(w.start))
+    }
+
+    // close the symbol's window if `at` is past its end , the next one inherits the last spread
+    fn roll(&mut self, symbol: u32, at: i64, out: &mut Buffer) -> questdb::Result<()> {
+        let interval = self.interval;
+        let Some(w) = self.windows.get_mut(&symbol) else {
+            return Ok(());
+        };
+        if at < w.start + interval {
+            return Ok(());
+        }
+        let end = w.start + interval;
+        w.advance(end);
+        let done = *w;
+        *w = QualityWindow {
+            start: at - at.rem_euclid(interval),
+            last_spread_bps: done.last_spread_bps,
+            ..Default::default()
+        };
+        w.last_at = w.start;
+        // intervals without a single snapshot are not written
+        if done.snapshots == 0 {
+            return Ok(());
+        }
+        Self::emit_window(out, symbol, &done, interval)
+    }
+}
+
+impl LogProcessor for MarketQuality {
+    fn streams(&self) -> &'static [Stream] {
+        &[Stream::Snapshots]
+    }
+
+    fn on_snapshot(&mut self, snap: &OrderBookSnapShot, out: &mut Buffer) -> questdb::Result<()> {
+        let tick = self.tick_size(snap.symbol);
+        let bbo = Bbo::from_snapshot(snap);
+        Self::emit_bbo(out, snap, &bbo, tick)?;
+
+        self.roll(snap.symbol, snap.timestamp, out)?;
+        let interval = self.interval;
+        let w = self.windows.entry(snap.symbol).or_insert_with(|| {
+            let start = snap.timestamp - snap.timestamp.rem_euclid(interval);
+            QualityWindow { start, last_at: snap.timestamp, ..Default::default() }
+        });
+        w.advance(snap.timestamp);
+        w.last_spread_bps = bbo.spread_bps();
+        w.snapshots += 1;
+
+        if let Some(mid) = bbo.mid() {
+            let reach = (self.config.depth_ticks * tick) as f64;
+            let bid_depth: u64 = snap.bids.iter().filter(|(p, q)| *q > 0 && *p as f64 >= mid - reach).map(|(_, q)| *q as u64).sum();
+            let ask_depth: u64 = snap.asks.iter().filter(|(p, q)| *q > 0 && *p as f64 <= mid + reach).map(|(_, q)| *q as u64).sum();
+            w.bid_depth_sum += bid_depth;
+            w.ask_depth_sum += ask_depth;
+            if bid_depth + ask_depth > 0 {
+                w.imbalance_sum += (bid_depth as f64 - ask_depth as f64) / (bid_depth + ask_depth) as f64;
+                w.imbalance_count += 1;
+            }
+        }
+        if let Some(spread) = bbo.spread() {
+            let ticks = spread as f64 / tick as f64;
+            w.min_spread_ticks = Some(w.min_spread_ticks.map_or(ticks, |m| m.min(ticks)));
+            w.max_spread_ticks = Some(w.max_spread_ticks.map_or(ticks, |m| m.max(ticks)));
+        }
+        Ok(())
+    }
+
+    // quiet symbols still get their window closed once a full interval went by without snapshots
+    fn on_tick(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        let stale: Vec<u32> = self
+            .windows
+            .iter()
+            .filter(|(_, w)| w.snapshots > 0 && now >= w.start + 2 * self.interval)
+            .map(|(symbol, _)| *symbol)
+            .collect();
+        for symbol in stale {
+            let w = self.windows.get(&symbol).copied().unwrap();
+            self.roll(symbol, w.start + self.interval, out)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::snapshot;
+    use questdb::ingress::ProtocolVersion;
+
+    const MS: i64 = NANOS_PER_MILLI;
+
+    fn quality() -> MarketQuality {
+        MarketQuality::new(MarketQualityConfig { depth_ticks: 2, interval_ms: 1_000, ..Default::default() })
+    }
+
+    fn windows(out: &Buffer) -> Vec<String> {
+        std::str::from_utf8(out.as_bytes())
+            .unwrap()
+            .lines()
+            .filter(|row| row.starts_with("market_quality,"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn field(row: &str, name: &str) -> f64 {
+        let value = row.split([' ', ',']).find_map(|kv| kv.strip_prefix(&format!("{}=", name))).unwrap();
+        value.trim_end_matches('i').parse().unwrap()
+    }
+
+    #[test]
+    fn bbo_ignores_level_order_and_empty_levels() {
+        let bbo = Bbo::from_snapshot(&snapshot(&[(98, 5), (99, 1), (0, 0)], &[(103, 2), (101, 4)]));
+        assert_eq!(bbo.bid, Some((99, 1)));
+        assert_eq!(bbo.ask, Some((101, 4)));
+        assert_eq!(bbo.spread(), Some(2));
+        assert_eq!(bbo.spread_bps(), Some(200.0));
+        assert!(Bbo::from_snapshot(&snapshot(&[(99, 1)], &[])).mid().is_none());
+    }
+
+    #[test]
+    fn spread_is_weighted_by_how_long_it_was_in_force() {
+        let mut quality = quality();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        // 200 bps for 250ms , then 400 bps for 750ms
+        quality.on_snapshot(&snapshot(&[(99, 1)], &[(101, 1)]), &mut out).unwrap();
+        quality.on_snapshot(&snapshot(&[(98, 1)], &[(102, 1)]).at(250 * MS), &mut out).unwrap();
+        assert!(windows(&out).is_empty());
+        quality.on_snapshot(&snapshot(&[(98, 1)], &[(102, 1)]).at(1_000 * MS), &mut out).unwrap();
+
+        let rows = windows(&out);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(field(&rows[0], "snapshots"), 2.0);
+        assert_eq!(field(&rows[0], "twa_spread_bps"), 350.0);
+        assert_eq!(field(&rows[0], "min_spread_ticks"), 2.0);
+        assert_eq!(field(&rows[0], "max_spread_ticks"), 4.0);
+        // the new window starts with the spread still in force
+        assert_eq!(quality.windows[&7].last_spread_bps, Some(400.0));
+    }
+
+    #[test]
+    fn depth_and_imbalance_only_count_levels_near_the_mid() {
+        let mut quality = quality();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        quality.on_snapshot(&snapshot(&[(99, 10), (98, 5), (90, 100)], &[(101, 5), (110, 100)]), &mut out).unwrap();
+        quality.on_snapshot(&snapshot(&[(99, 1)], &[(101, 1)]).at(1_000 * MS), &mut out).unwrap();
+
+        let rows = windows(&out);
+        assert_eq!(field(&rows[0], "avg_bid_depth"), 15.0);
+        assert_eq!(field(&rows[0], "avg_ask_depth"), 5.0);
+        assert_eq!(field(&rows[0], "avg_imbalance"), 0.5);
+    }
+
+    #[test]
+    fn tick_closes_the_window_of_a_quiet_symbol() {
+        let mut quality = quality();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        quality.on_snapshot(&snapshot(&[(99, 1)], &[(101, 1)]).at(100 * MS), &mut out).unwrap();
+        quality.on_tick(1_999 * MS, &mut out).unwrap();
+        assert!(windows(&out).is_empty());
+        quality.on_tick(2_000 * MS, &mut out).unwrap();
+
+        let rows = windows(&out);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(field(&rows[0], "twa_spread_bps"), 200.0);
+        assert_eq!(quality.windows[&7].snapshots, 0);
+    }
+}
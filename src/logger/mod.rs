@@ -1,2 +1,23 @@
 pub mod types;
-pub mod log_flusher;
\ No newline at end of file
+pub mod serde_fields;
+pub mod log_flusher;
+pub mod histogram;
+pub mod ingest_lag;
+pub mod sink;
+pub mod parquet_sink;
+pub mod jsonl_sink;
+pub mod fix_sink;
+pub mod audit_journal;
+pub mod processor;
+pub mod candles;
+pub mod order_state;
+pub mod balance_ledger;
+pub mod holdings_recon;
+pub mod correlator;
+pub mod market_quality;
+pub mod conflation;
+pub mod book_deltas;
+pub mod surveillance;
+pub mod user_activity;
+pub mod order_latency;
+pub mod rules;
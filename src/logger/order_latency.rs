@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::histogram::{write_summary, Histogram};
+use crate::logger::processor::{LogProcessor, NANOS_PER_MILLI};
+use crate::logger::types::{OrderLogWrapper, Stream};
+
+const RECEIVED: u8 = 0;
+const MATCHED: u8 = 1;
+const CANCELED: u8 = 2;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OrderLatencyConfig {
+    // histograms are written and reset every report_ms
+    pub report_ms: u64,
+    // a single latency above this is written to order_latency_outliers
+    pub outlier_ms: u64,
+    // orders neither filled nor canceled for this long are forgotten
+    pub order_ttl_ms: u64,
+}
+
+impl Default for OrderLatencyConfig {
+    fn default() -> Self {
+        Self {
+            report_ms: 10_000,
+            outlier_ms: 100,
+            order_ttl_ms: 3_600_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LatencyKind {
+    FirstFill,
+    LastFill,
+    Cancel,
+}
+
+impl LatencyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LatencyKind::FirstFill => "received_to_first_fill",
+            LatencyKind::LastFill => "received_to_last_fill",
+            LatencyKind::Cancel => "received_to_cancel",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OrderTiming {
+    pub user_id: u64,
+    pub symbol: u32,
+    pub qty: u32,
+    pub filled: u32,
+    pub received_at: i64,
+    pub first_fill_at: Option<i64>,
+    pub last_fill_at: i64,
+    pub last_seen: i64,
+}
+
+pub struct OrderLatency {
+    pub config: OrderLatencyConfig,
+    // only orders whose received event was seen , the rest have nothing to measure from
+    pub orders: HashMap<u64, OrderTiming>,
+    pub histograms: HashMap<(u32, LatencyKind), Histogram>,
+    pub now: i64,
+    pub last_report: i64,
+    pub outliers: u64,
+}
+
+impl OrderLatency {
+    pub fn new(config: OrderLatencyConfig) -> Self {
+        Self {
+            config,
+            orders: HashMap::new(),
+            histograms: HashMap::new(),
+            now: 0,
+            last_report: 0,
+            outliers: 0,
+        }
+    }
+
+    fn record(&mut self, out: &mut Buffer, order_id: u64, order: &OrderTiming, kind: LatencyKind, at: i64) -> questdb::Result<()> {
+        let latency = at - order.received_at;
+        self.histograms.entry((order.symbol, kind)).or_default().record_nanos(latency);
+        if latency <= self.config.outlier_ms as i64 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.outliers += 1;
+        out.table("order_latency_outliers")?
+            .symbol("instrument", order.symbol.to_string())?
+            .symbol("latency", kind.as_str())?
+            .column_i64("order_id", order_id as i64)?
+            .column_i64("user_id", order.user_id as i64)?
+            .column_i64("latency_ns", latency)?
+            .column_ts("received_at", TimestampNanos::new(order.received_at))?
+            .at(TimestampNanos::new(at))
+    }
+
+    fn report(&mut self, out: &mut Buffer) -> questdb::Result<()> {
+        for ((symbol, kind), histogram) in self.histograms.iter_mut() {
+            if histogram.is_empty() {
+                continue;
+            }
+            out.table("order_latency")?
+                .symbol("instrument", symbol.to_string())?
+                .symbol("latency", kind.as_str())?;
+            write_summary(out, histogram)?;
+            out.column_i64("interval_ms", self.config.report_ms as i64)?
+                .at(TimestampNanos::new(self.now))?;
+            histogram.reset();
+        }
+        Ok(())
+    }
+}
+
+impl LogProcessor for OrderLatency {
+    fn streams(&self) -> &'static [Stream] {
+        &[Stream::OrderLogs]
+    }
+
+    fn on_order_log(&mut self, log: &OrderLogWrapper, out: &mut Buffer) -> questdb::Result<()> {
+        let delta = &log.order_delta;
+        if delta.order_event_type == RECEIVED {
+            self.orders.insert(delta.order_id, OrderTiming {
+                user_id: delta.user_id,
+                symbol: delta.symbol,
+                qty: delta.shares_qty,
+                filled: 0,
+                received_at: log.timestamp,
+                first_fill_at: None,
+                last_fill_at: 0,
+                last_seen: self.now,
+            });
+            return Ok(());
+        }
+        let Some(order) = self.orders.get_mut(&delta.order_id) else {
+            return Ok(());
+        };
+        order.last_seen = self.now;
+
+        match delta.order_event_type {
+            MATCHED => {
+                let first = order.first_fill_at.is_none();
+                order.first_fill_at.get_or_insert(log.timestamp);
+                order.last_fill_at = log.timestamp;
+                order.filled = order.filled.saturating_add(delta.shares_qty);
+                let done = order.filled >= order.qty;
+                let order = *order;
+                if first {
+                    self.record(out, delta.order_id, &order, LatencyKind::FirstFill, log.timestamp)?;
+                }
+                if done {
+                    self.orders.remove(&delta.order_id);
+                    self.record(out, delta.order_id, &order, LatencyKind::LastFill, log.timestamp)?;
+                }
+            }
+            CANCELED => {
+                let order = *order;
+                self.orders.remove(&delta.order_id);
+                // a partly filled order is done filling once it is canceled
+                if order.first_fill_at.is_some() {
+                    self.record(out, delta.order_id, &order, LatencyKind::LastFill, order.last_fill_at)?;
+                }
+                self.record(out, delta.order_id, &order, LatencyKind::Cancel, log.timestamp)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn on_tick(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        self.now = now;
+        if now - self.last_report < self.config.report_ms as i64 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.last_report = now;
+        let ttl = self.config.order_ttl_ms as i64 * NANOS_PER_MILLI;
+        self.orders.retain(|_, o| o.last_seen + ttl > now);
+        self.report(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::order;
+    use questdb::ingress::ProtocolVersion;
+
+    fn latency() -> OrderLatency {
+        OrderLatency::new(OrderLatencyConfig { report_ms: 10_000, outlier_ms: 100, order_ttl_ms: 3_600_000 })
+    }
+
+    fn feed(latency: &mut OrderLatency, logs: &[OrderLogWrapper]) -> String {
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        for log in logs {
+            latency.on_order_log(log, &mut out).unwrap();
+        }
+        String::from_utf8(out.as_bytes().to_vec()).unwrap()
+    }
+
+    // ( count , max ) of one histogram
+    fn recorded(latency: &OrderLatency, kind: LatencyKind) -> (u64, u64) {
+        latency.histograms.get(&(7, kind)).map_or((0, 0), |h| (h.count, h.max))
+    }
+
+    const MS: i64 = NANOS_PER_MILLI;
+
+    #[test]
+    fn first_and_last_fill_are_measured_from_received() {
+        let mut latency = latency();
+        feed(&mut latency, &[
+            order(1, 5).at(0).qty(10),
+            order(1, 5).at(2 * MS).event(MATCHED).qty(4),
+            order(1, 5).at(5 * MS).event(MATCHED).qty(6),
+        ]);
+        assert_eq!(recorded(&latency, LatencyKind::FirstFill), (1, 2 * MS as u64));
+        assert_eq!(recorded(&latency, LatencyKind::LastFill), (1, 5 * MS as u64));
+        assert!(latency.orders.is_empty());
+    }
+
+    #[test]
+    fn partial_fill_is_not_the_last_one() {
+        let mut latency = latency();
+        feed(&mut latency, &[order(1, 5).at(0).qty(10), order(1, 5).at(2 * MS).event(MATCHED).qty(4)]);
+        assert_eq!(recorded(&latency, LatencyKind::FirstFill).0, 1);
+        assert_eq!(recorded(&latency, LatencyKind::LastFill).0, 0);
+        assert!(latency.orders.contains_key(&1));
+    }
+
+    #[test]
+    fn cancel_after_fills_closes_the_last_fill() {
+        let mut latency = latency();
+        feed(&mut latency, &[
+            order(1, 5).at(0).qty(10),
+            order(1, 5).at(2 * MS).event(MATCHED).qty(4),
+            order(1, 5).at(3 * MS).event(MATCHED).qty(1),
+            order(1, 5).at(9 * MS).event(CANCELED),
+            // canceled without a fill
+            order(2, 5).at(0).qty(10),
+            order(2, 5).at(4 * MS).event(CANCELED),
+        ]);
+        assert_eq!(recorded(&latency, LatencyKind::LastFill), (1, 3 * MS as u64));
+        assert_eq!(recorded(&latency, LatencyKind::Cancel), (2, 9 * MS as u64));
+        assert!(latency.orders.is_empty());
+    }
+
+    #[test]
+    fn slow_orders_get_an_outlier_row() {
+        let mut latency = latency();
+        let rows = feed(&mut latency, &[
+            order(1, 5).at(0).qty(1),
+            order(1, 5).at(50 * MS).event(MATCHED).qty(1),
+            order(2, 5).at(0).qty(1),
+            order(2, 5).at(150 * MS).event(MATCHED).qty(1),
+        ]);
+        // order 2 is over the limit for its first and last fill
+        assert_eq!(latency.outliers, 2);
+        let outliers: Vec<_> = rows.lines().filter(|row| row.starts_with("order_latency_outliers,")).collect();
+        assert_eq!(outliers.len(), 2);
+        assert!(outliers.iter().all(|row| row.contains("order_id=2i") && row.contains("latency_ns=150000000i")));
+        assert!(outliers[0].contains("latency=received_to_first_fill"));
+        assert!(outliers[1].contains("latency=received_to_last_fill"));
+    }
+}
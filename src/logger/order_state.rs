@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::{LogProcessor, NANOS_PER_MILLI};
+use crate::logger::types::{side_str, OrderDelta, OrderLogWrapper, Stream};
+
+const RECEIVED: u8 = 0;
+const MATCHED: u8 = 1;
+const CANCELED: u8 = 2;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OrderStateConfig {
+    // open orders without any event for this long are dropped with a final "evicted" row
+    pub stale_after_ms: u64,
+}
+
+impl Default for OrderStateConfig {
+    fn default() -> Self {
+        Self { stale_after_ms: 24 * 3_600_000 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    Open,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Evicted,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Open => "open",
+            OrderStatus::PartiallyFilled => "partially_filled",
+            OrderStatus::Filled => "filled",
+            OrderStatus::Canceled => "canceled",
+            OrderStatus::Evicted => "evicted",
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, OrderStatus::Filled | OrderStatus::Canceled | OrderStatus::Evicted)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OrderState {
+    pub order_id: u64,
+    pub user_id: u64,
+    pub symbol: u32,
+    pub side: u8,
+    pub price: u64,
+    // 0 until the received event was seen
+    pub original_qty: u32,
+    pub filled_qty: u32,
+    pub status: OrderStatus,
+    pub first_event_at: i64,
+    pub last_event_at: i64,
+    pub fills: u32,
+    // false when the first event we saw for the order was not "received"
+    pub seen_received: bool,
+}
+
+impl OrderState {
+    fn new(delta: &OrderDelta, timestamp: i64) -> Self {
+        Self {
+            order_id: delta.order_id,
+            user_id: delta.user_id,
+            symbol: delta.symbol,
+            side: delta.side,
+            price: delta.price,
+            original_qty: 0,
+            filled_qty: 0,
+            status: OrderStatus::Open,
+            first_event_at: timestamp,
+            last_event_at: timestamp,
+            fills: 0,
+            seen_received: false,
+        }
+    }
+
+    pub fn remaining_qty(&self) -> u32 {
+        self.original_qty.saturating_sub(self.filled_qty)
+    }
+}
+
+pub struct OrderStateTracker {
+    pub config: OrderStateConfig,
+    pub orders: HashMap<u64, OrderState>,
+    pub last_sweep: i64,
+}
+
+impl OrderStateTracker {
+    pub fn new(config: OrderStateConfig) -> Self {
+        Self {
+            config,
+            orders: HashMap::new(),
+            last_sweep: 0,
+        }
+    }
+
+    fn emit(out: &mut Buffer, state: &OrderState, event: &str, at: i64) -> questdb::Result<()> {
+        out.table("order_state")?
+            .symbol("instrument", state.symbol.to_string())?
+            .symbol("side", side_str(state.side))?
+            .symbol("status", state.status.as_str())?
+            .symbol("event_type", event)?
+            .column_i64("order_id", state.order_id as i64)?
+            .column_i64("user_id", state.user_id as i64)?
+            .column_i64("price", state.price as i64)?
+            .column_i64("original_qty", state.original_qty as i64)?
+            .column_i64("filled_qty", state.filled_qty as i64)?
+            .column_i64("remaining_qty", state.remaining_qty() as i64)?
+            .column_i64("fills", state.fills as i64)?
+            .column_bool("seen_received", state.seen_received)?
+            .column_bool("is_final", state.status.is_terminal())?
+            .column_ts("first_event_at", TimestampNanos::new(state.first_event_at))?
+            .column_ts("last_event_at", TimestampNanos::new(state.last_event_at))?
+            .at(TimestampNanos::new(at))
+    }
+}
+
+impl LogProcessor for OrderStateTracker {
+    fn streams(&self) -> &'static [Stream] {
+        &[Stream::OrderLogs]
+    }
+
+    fn on_order_log(&mut self, log: &OrderLogWrapper, out: &mut Buffer) -> questdb::Result<()> {
+        let delta = &log.order_delta;
+        let state = self
+            .orders
+            .entry(delta.order_id)
+            .or_insert_with(|| OrderState::new(delta, log.timestamp));
+        state.last_event_at = state.last_event_at.max(log.timestamp);
+        state.first_event_at = state.first_event_at.min(log.timestamp);
+
+        let event = match delta.order_event_type {
+            RECEIVED => {
+                state.original_qty = delta.shares_qty;
+                state.price = delta.price;
+                state.seen_received = true;
+                "received"
+            }
+            // shares_qty of a matched event is the quantity of that fill
+            MATCHED => {
+                state.filled_qty = state.filled_qty.saturating_add(delta.shares_qty);
+                state.fills += 1;
+                "matched"
+            }
+            CANCELED => {
+                state.status = OrderStatus::Canceled;
+                "canceled"
+            }
+            _ => return Ok(()),
+        };
+
+        if state.status != OrderStatus::Canceled {
+            state.status = if state.filled_qty == 0 {
+                OrderStatus::Open
+            } else if state.seen_received && state.filled_qty >= state.original_qty {
+                OrderStatus::Filled
+            } else {
+                OrderStatus::PartiallyFilled
+            };
+        }
+
+        let state = *state;
+        if state.status.is_terminal() {
+            self.orders.remove(&state.order_id);
+        }
+        Self::emit(out, &state, event, log.timestamp)
+    }
+
+    fn on_tick(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        // once a second is plenty for a timeout measured in hours
+        if now - self.last_sweep < 1_000 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.last_sweep = now;
+
+        let stale_before = now - self.config.stale_after_ms as i64 * NANOS_PER_MILLI;
+        let stale: Vec<u64> = self
+            .orders
+            .values()
+            .filter(|o| o.last_event_at < stale_before)
+            .map(|o| o.order_id)
+            .collect();
+        for order_id in stale {
+            let mut state = self.orders.remove(&order_id).unwrap();
+            state.status = OrderStatus::Evicted;
+            Self::emit(out, &state, "evicted", now)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::order;
+    use questdb::ingress::ProtocolVersion;
+
+    const SEC: i64 = 1_000 * NANOS_PER_MILLI;
+
+    fn tracker() -> OrderStateTracker {
+        OrderStateTracker::new(OrderStateConfig { stale_after_ms: 60_000 })
+    }
+
+    fn statuses(out: &Buffer) -> Vec<String> {
+        std::str::from_utf8(out.as_bytes())
+            .unwrap()
+            .lines()
+            .map(|row| row.split(",status=").nth(1).unwrap().split(',').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn fills_move_the_order_to_filled() {
+        let mut orders = tracker();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        orders.on_order_log(&order(1, 9).at(SEC).qty(10), &mut out).unwrap();
+        orders.on_order_log(&order(1, 9).at(2 * SEC).event(MATCHED).qty(4), &mut out).unwrap();
+        assert_eq!(orders.orders[&1].remaining_qty(), 6);
+        orders.on_order_log(&order(1, 9).at(3 * SEC).event(MATCHED).qty(6), &mut out).unwrap();
+
+        assert_eq!(statuses(&out), ["open", "partially_filled", "filled"]);
+        assert!(std::str::from_utf8(out.as_bytes()).unwrap().lines().last().unwrap().contains("fills=2i"));
+        assert!(orders.orders.is_empty());
+    }
+
+    #[test]
+    fn cancel_is_final_after_a_partial_fill() {
+        let mut orders = tracker();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        orders.on_order_log(&order(1, 9).at(SEC).qty(10), &mut out).unwrap();
+        orders.on_order_log(&order(1, 9).at(2 * SEC).event(MATCHED).qty(3), &mut out).unwrap();
+        orders.on_order_log(&order(1, 9).at(3 * SEC).event(CANCELED).qty(0), &mut out).unwrap();
+
+        assert_eq!(statuses(&out), ["open", "partially_filled", "canceled"]);
+        assert!(orders.orders.is_empty());
+    }
+
+    #[test]
+    fn fill_before_received_is_not_filled() {
+        let mut orders = tracker();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        orders.on_order_log(&order(1, 9).at(2 * SEC).event(MATCHED).qty(10), &mut out).unwrap();
+        let state = orders.orders[&1];
+        assert_eq!(state.status, OrderStatus::PartiallyFilled);
+        assert!(!state.seen_received);
+
+        // the late received event completes it and keeps the earliest timestamp
+        orders.on_order_log(&order(1, 9).at(SEC).qty(10), &mut out).unwrap();
+        assert_eq!(statuses(&out), ["partially_filled", "filled"]);
+        assert!(orders.orders.is_empty());
+    }
+
+    #[test]
+    fn quiet_orders_are_evicted() {
+        let mut orders = tracker();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        orders.on_order_log(&order(1, 9).at(SEC).qty(10), &mut out).unwrap();
+        orders.on_order_log(&order(2, 9).at(30 * SEC).qty(10), &mut out).unwrap();
+        out.clear();
+
+        orders.on_tick(61 * SEC + 1, &mut out).unwrap();
+        assert_eq!(statuses(&out), ["evicted"]);
+        assert!(std::str::from_utf8(out.as_bytes()).unwrap().contains("order_id=1i"));
+        assert!(orders.orders.contains_key(&2) && !orders.orders.contains_key(&1));
+    }
+}
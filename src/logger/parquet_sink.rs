@@ -0,0 +1,652 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow_array::builder::{ListBuilder, StructBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::{
+    ArrayRef, BooleanArray, Int32Array, Int64Array, RecordBatch, StringArray,
+    TimestampNanosecondArray, UInt32Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Deserialize;
+
+use crate::logger::sink::LogSink;
+use crate::logger::types::{
+    order_event_type_str, reason_str, severity_str, side_str, BalanceLogWrapper,
+    HoldingLogWrapper, OrderBookSnapShot, OrderLogWrapper, TradeLogs,
+};
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Partitioning {
+    Hourly,
+    Daily,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ParquetConfig {
+    pub dir: PathBuf,
+    pub partitioning: Partitioning,
+    // a row group is written once this many rows are pending ...
+    pub row_group_rows: usize,
+    // ... or once the oldest pending row is this old
+    pub max_pending_ms: u64,
+    // the open file is closed and published after this many row groups ...
+    pub file_row_groups: usize,
+    // ... or once it has been open this long , so a restart loses at most this much
+    pub max_file_ms: u64,
+}
+
+impl Default for ParquetConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("/tmp/logger/parquet"),
+            partitioning: Partitioning::Hourly,
+            row_group_rows: 65536,
+            max_pending_ms: 60_000,
+            file_row_groups: 16,
+            max_file_ms: 300_000,
+        }
+    }
+}
+
+// one typed row per stream , converted to an arrow batch when a row group is written
+pub trait ParquetRow: Copy {
+    const TABLE: &'static str;
+    fn schema() -> SchemaRef;
+    fn timestamp(&self) -> i64;
+    fn to_batch(rows: &[Self], schema: &SchemaRef) -> parquet::errors::Result<RecordBatch>;
+}
+
+fn ts_field() -> Field {
+    Field::new("timestamp", DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())), false)
+}
+
+fn ts_array<T>(rows: &[T], ts: impl Fn(&T) -> i64) -> ArrayRef {
+    Arc::new(TimestampNanosecondArray::from_iter_values(rows.iter().map(ts)).with_timezone("UTC"))
+}
+
+fn level_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("price", DataType::UInt64, false),
+        Field::new("qty", DataType::UInt32, false),
+    ])
+}
+
+fn level_item_field() -> Arc<Field> {
+    Arc::new(Field::new("item", DataType::Struct(level_fields()), false))
+}
+
+fn levels_array(rows: &[OrderBookSnapShot], side: impl Fn(&OrderBookSnapShot) -> &[(u64, u32); 20]) -> ArrayRef {
+    let mut builder = ListBuilder::new(StructBuilder::from_fields(level_fields(), rows.len() * 20))
+        .with_field(level_item_field());
+    for snap in rows {
+        for &(price, qty) in side(snap) {
+            // unused levels are zero filled by the engine
+            if price == 0 && qty == 0 {
+                continue;
+            }
+            let levels = builder.values();
+            levels.field_builder::<UInt64Builder>(0).unwrap().append_value(price);
+            levels.field_builder::<UInt32Builder>(1).unwrap().append_value(qty);
+            levels.append(true);
+        }
+        builder.append(true);
+    }
+    Arc::new(builder.finish())
+}
+
+impl ParquetRow for TradeLogs {
+    const TABLE: &'static str = "trade_logs";
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            ts_field(),
+            Field::new("symbol", DataType::UInt32, false),
+            Field::new("price", DataType::UInt64, false),
+            Field::new("quantity", DataType::UInt32, false),
+            Field::new("buyer_order_id", DataType::UInt64, false),
+            Field::new("seller_order_id", DataType::UInt64, false),
+            Field::new("is_buyer_maker", DataType::Boolean, false),
+        ]))
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    fn to_batch(rows: &[Self], schema: &SchemaRef) -> parquet::errors::Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            ts_array(rows, |r| r.timestamp),
+            Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.symbol))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.price))),
+            Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.quantity))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.buyer_order_id))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.seller_order_id))),
+            Arc::new(BooleanArray::from(rows.iter().map(|r| r.is_buyer_maker).collect::<Vec<_>>())),
+        ];
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+}
+
+impl ParquetRow for OrderLogWrapper {
+    const TABLE: &'static str = "order_logs";
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            ts_field(),
+            Field::new("event_id", DataType::UInt64, false),
+            Field::new("order_id", DataType::UInt64, false),
+            Field::new("user_id", DataType::UInt64, false),
+            Field::new("instrument", DataType::UInt32, false),
+            Field::new("price", DataType::UInt64, false),
+            Field::new("shares_qty", DataType::UInt32, false),
+            Field::new("side", DataType::Utf8, false),
+            Field::new("event_type", DataType::Utf8, false),
+            Field::new("severity", DataType::Utf8, false),
+        ]))
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    fn to_batch(rows: &[Self], schema: &SchemaRef) -> parquet::errors::Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            ts_array(rows, |r| r.timestamp),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.order_delta.event_id))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.order_delta.order_id))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.order_delta.user_id))),
+            Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.order_delta.symbol))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.order_delta.price))),
+            Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.order_delta.shares_qty))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| side_str(r.order_delta.side)))),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| order_event_type_str(r.order_delta.order_event_type)),
+            )),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| severity_str(r.severity)))),
+        ];
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+}
+
+impl ParquetRow for BalanceLogWrapper {
+    const TABLE: &'static str = "balance_logs";
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            ts_field(),
+            Field::new("event_id", DataType::UInt64, false),
+            Field::new("user_id", DataType::UInt64, false),
+            Field::new("order_id", DataType::UInt64, false),
+            Field::new("delta_available_balance", DataType::Int64, false),
+            Field::new("delta_reserved_balance", DataType::Int64, false),
+            Field::new("reason", DataType::Utf8, false),
+            Field::new("severity", DataType::Utf8, false),
+        ]))
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    fn to_batch(rows: &[Self], schema: &SchemaRef) -> parquet::errors::Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            ts_array(rows, |r| r.timestamp),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.balance_delta.event_id))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.balance_delta.user_id))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.balance_delta.order_id))),
+            Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.balance_delta.delta_available))),
+            Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.balance_delta.delta_reserved))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| reason_str(r.balance_delta.reason)))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| severity_str(r.severity)))),
+        ];
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+}
+
+impl ParquetRow for HoldingLogWrapper {
+    const TABLE: &'static str = "holding_logs";
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            ts_field(),
+            Field::new("event_id", DataType::UInt64, false),
+            Field::new("user_id", DataType::UInt64, false),
+            Field::new("order_id", DataType::UInt64, false),
+            Field::new("instrument", DataType::UInt32, false),
+            Field::new("delta_available_holding", DataType::Int32, false),
+            Field::new("delta_reserved_holding", DataType::Int32, false),
+            Field::new("reason", DataType::Utf8, false),
+            Field::new("severity", DataType::Utf8, false),
+        ]))
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    fn to_batch(rows: &[Self], schema: &SchemaRef) -> parquet::errors::Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            ts_array(rows, |r| r.timestamp),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.holding_delta.event_id))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.holding_delta.user_id))),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.holding_delta.order_id))),
+            Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.holding_delta.symbol))),
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.holding_delta.delta_available))),
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.holding_delta.delta_reserved))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| reason_str(r.holding_delta.reason)))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| severity_str(r.severity)))),
+        ];
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+}
+
+impl ParquetRow for OrderBookSnapShot {
+    const TABLE: &'static str = "orderbook_snapshots";
+
+    fn schema() -> SchemaRef {
+        let levels = DataType::List(level_item_field());
+        Arc::new(Schema::new(vec![
+            ts_field(),
+            Field::new("snapshot_id", DataType::UInt64, false),
+            Field::new("symbol", DataType::UInt32, false),
+            Field::new("bids", levels.clone(), false),
+            Field::new("asks", levels, false),
+        ]))
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    fn to_batch(rows: &[Self], schema: &SchemaRef) -> parquet::errors::Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            ts_array(rows, |r| r.timestamp),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.event_id))),
+            Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.symbol))),
+            levels_array(rows, |r| &r.bids),
+            levels_array(rows, |r| &r.asks),
+        ];
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+}
+
+// one partition of a table , rows wait in pending until a row group is written to the open file
+pub struct OpenPartition<R: ParquetRow> {
+    pub pending: Vec<R>,
+    pub pending_since: Option<Instant>,
+    pub last_row: Instant,
+    pub writer: Option<ArrowWriter<File>>,
+    pub file_path: Option<PathBuf>,
+    pub file_opened: Option<Instant>,
+    pub row_groups: usize,
+    // rows in the row groups of the open file
+    pub file_rows: usize,
+}
+
+impl<R: ParquetRow> OpenPartition<R> {
+    fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            pending_since: None,
+            last_row: Instant::now(),
+            writer: None,
+            file_path: None,
+            file_opened: None,
+            row_groups: 0,
+            file_rows: 0,
+        }
+    }
+}
+
+// writer for a single table , every partition that recently got rows keeps its own open file
+// so rows arriving out of order do not close and reopen files
+pub struct PartitionedTable<R: ParquetRow> {
+    pub config: ParquetConfig,
+    pub schema: SchemaRef,
+    pub partitions: HashMap<String, OpenPartition<R>>,
+    pub files_written: u64,
+    // rows that never made it into a published file , since start
+    pub rows_dropped: u64,
+}
+
+impl<R: ParquetRow> PartitionedTable<R> {
+    pub fn new(config: ParquetConfig) -> Self {
+        Self { config, schema: R::schema(), partitions: HashMap::new(), files_written: 0, rows_dropped: 0 }
+    }
+
+    fn push(&mut self, row: R) {
+        let key = partition_key(row.timestamp(), self.config.partitioning);
+        let partition = self.partitions.entry(key.clone()).or_insert_with(OpenPartition::new);
+        if partition.pending.is_empty() {
+            partition.pending_since = Some(Instant::now());
+        }
+        partition.last_row = Instant::now();
+        partition.pending.push(row);
+        if partition.pending.len() >= self.config.row_group_rows {
+            self.write_pending(&key);
+            if self.partitions[&key].row_groups >= self.config.file_row_groups {
+                self.close(&key);
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        let max_pending = Duration::from_millis(self.config.max_pending_ms);
+        let aged: Vec<String> = self
+            .partitions
+            .iter()
+            .filter(|(_, p)| p.pending_since.is_some_and(|since| since.elapsed() >= max_pending))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in aged {
+            self.write_pending(&key);
+        }
+
+        // an open file has no footer , publish it before it holds more than max_file_ms of rows
+        let max_file = Duration::from_millis(self.config.max_file_ms);
+        let full: Vec<String> = self
+            .partitions
+            .iter()
+            .filter(|(_, p)| {
+                p.row_groups >= self.config.file_row_groups || p.file_opened.is_some_and(|at| at.elapsed() >= max_file)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in full {
+            self.write_pending(&key);
+            self.close(&key);
+        }
+
+        // the wall clock left the partition and late rows stopped coming , finish the file so it becomes readable
+        let current = partition_key(Utc::now().timestamp_nanos_opt().unwrap_or(0), self.config.partitioning);
+        let finished: Vec<String> = self
+            .partitions
+            .iter()
+            .filter(|(key, p)| key.as_str() < current.as_str() && p.last_row.elapsed() >= max_pending)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in finished {
+            self.write_pending(&key);
+            self.close(&key);
+        }
+    }
+
+    fn write_pending(&mut self, key: &str) {
+        let Some(partition) = self.partitions.get(key) else { return };
+        if partition.pending.is_empty() {
+            return;
+        }
+        if let Err(e) = self.try_write_pending(key) {
+            let rows = self.partitions[key].pending.len();
+            self.rows_dropped += rows as u64;
+            eprintln!(
+                "parquet sink: failed to write {} rows to {} , {} dropped since start: {}",
+                rows, R::TABLE, self.rows_dropped, e
+            );
+        }
+        let partition = self.partitions.get_mut(key).unwrap();
+        partition.pending.clear();
+        partition.pending_since = None;
+    }
+
+    fn try_write_pending(&mut self, key: &str) -> parquet::errors::Result<()> {
+        let partition = self.partitions.get_mut(key).unwrap();
+        if partition.writer.is_none() {
+            let dir = self.config.dir.join(R::TABLE).join(key);
+            fs::create_dir_all(&dir)?;
+            let now = Utc::now().timestamp_nanos_opt().unwrap_or(0);
+            let path = dir.join(format!("part-{}-{}.parquet.tmp", now, self.files_written));
+            let props = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .set_max_row_group_row_count(Some(self.config.row_group_rows))
+                .build();
+            partition.writer = Some(ArrowWriter::try_new(File::create(&path)?, self.schema.clone(), Some(props))?);
+            partition.file_path = Some(path);
+            partition.file_opened = Some(Instant::now());
+            self.files_written += 1;
+        }
+
+        let batch = R::to_batch(&partition.pending, &self.schema)?;
+        let writer = partition.writer.as_mut().unwrap();
+        // flush ends the row group so memory is freed , the .tmp file only becomes readable on close ,
+        // which file_row_groups and max_file_ms bound
+        if let Err(e) = writer.write(&batch).and_then(|_| writer.flush()) {
+            self.abandon(key);
+            return Err(e);
+        }
+        partition.row_groups += 1;
+        partition.file_rows += partition.pending.len();
+        Ok(())
+    }
+
+    // a writer that failed halfway through a row group is not trusted with more rows , its file is
+    // left unpublished and the next row group starts a new one
+    fn abandon(&mut self, key: &str) {
+        let partition = self.partitions.get_mut(key).unwrap();
+        let path = partition.file_path.take();
+        partition.writer = None;
+        partition.file_opened = None;
+        partition.row_groups = 0;
+        // without a footer the row groups already in the file are lost as well
+        let lost = std::mem::take(&mut partition.file_rows);
+        self.rows_dropped += lost as u64;
+        if let Some(path) = path {
+            eprintln!("parquet sink: abandoned {} with {} rows written", path.display(), lost);
+        }
+    }
+
+    fn close(&mut self, key: &str) {
+        let Some(partition) = self.partitions.remove(key) else { return };
+        let (Some(writer), Some(path)) = (partition.writer, partition.file_path) else {
+            return;
+        };
+        if let Err(e) = writer.close() {
+            self.rows_dropped += partition.file_rows as u64;
+            eprintln!("parquet sink: failed to close {} , {} rows lost: {}", path.display(), partition.file_rows, e);
+            return;
+        }
+        // readers only ever see complete files
+        if let Err(e) = fs::rename(&path, final_path(&path)) {
+            eprintln!("parquet sink: failed to publish {}: {}", path.display(), e);
+        }
+    }
+}
+
+impl<R: ParquetRow> Drop for PartitionedTable<R> {
+    fn drop(&mut self) {
+        let keys: Vec<String> = self.partitions.keys().cloned().collect();
+        for key in keys {
+            self.write_pending(&key);
+            self.close(&key);
+        }
+    }
+}
+
+fn final_path(tmp: &Path) -> PathBuf {
+    tmp.with_extension("")
+}
+
+// hive style directory names so DuckDB / Spark pick the partition columns up
+pub fn partition_key(timestamp: i64, partitioning: Partitioning) -> String {
+    let at: DateTime<Utc> = DateTime::from_timestamp_nanos(timestamp);
+    match partitioning {
+        Partitioning::Hourly => at.format("date=%Y-%m-%d/hour=%H").to_string(),
+        Partitioning::Daily => at.format("date=%Y-%m-%d").to_string(),
+    }
+}
+
+pub struct ParquetSink {
+    pub trade_logs: PartitionedTable<TradeLogs>,
+    pub order_logs: PartitionedTable<OrderLogWrapper>,
+    pub balance_logs: PartitionedTable<BalanceLogWrapper>,
+    pub holding_logs: PartitionedTable<HoldingLogWrapper>,
+    pub snapshots: PartitionedTable<OrderBookSnapShot>,
+}
+
+impl ParquetSink {
+    pub fn new(config: ParquetConfig) -> Self {
+        Self {
+            trade_logs: PartitionedTable::new(config.clone()),
+            order_logs: PartitionedTable::new(config.clone()),
+            balance_logs: PartitionedTable::new(config.clone()),
+            holding_logs: PartitionedTable::new(config.clone()),
+            snapshots: PartitionedTable::new(config),
+        }
+    }
+}
+
+impl LogSink for ParquetSink {
+    fn write_order_log(&mut self, log: &OrderLogWrapper) {
+        self.order_logs.push(*log);
+    }
+
+    fn write_balance_log(&mut self, log: &BalanceLogWrapper) {
+        self.balance_logs.push(*log);
+    }
+
+    fn write_holding_log(&mut self, log: &HoldingLogWrapper) {
+        self.holding_logs.push(*log);
+    }
+
+    fn write_trade_log(&mut self, log: &TradeLogs) {
+        self.trade_logs.push(*log);
+    }
+
+    fn write_snapshot(&mut self, snap: &OrderBookSnapShot) {
+        self.snapshots.push(*snap);
+    }
+
+    fn tick(&mut self) {
+        self.trade_logs.tick();
+        self.order_logs.tick();
+        self.balance_logs.tick();
+        self.holding_logs.tick();
+        self.snapshots.tick();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::trade;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    // 2024-01-02T03:04:05Z
+    const AT: i64 = 1_704_164_645_000_000_000;
+
+    fn config(name: &str) -> ParquetConfig {
+        let dir = std::env::temp_dir().join(format!("logger-parquet-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        ParquetConfig { dir, partitioning: Partitioning::Hourly, ..ParquetConfig::default() }
+    }
+
+    fn files(config: &ParquetConfig) -> Vec<PathBuf> {
+        let dir = config.dir.join(TradeLogs::TABLE).join(partition_key(AT, config.partitioning));
+        let mut files: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+        files.sort();
+        files
+    }
+
+    fn quantities(path: &Path) -> Vec<u32> {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap().build().unwrap();
+        let mut quantities = Vec::new();
+        for batch in reader {
+            let batch = batch.unwrap();
+            let column = batch.column_by_name("quantity").unwrap().as_any().downcast_ref::<UInt32Array>().unwrap();
+            quantities.extend(column.values().iter().copied());
+        }
+        quantities
+    }
+
+    #[test]
+    fn partition_keys() {
+        assert_eq!(partition_key(AT, Partitioning::Hourly), "date=2024-01-02/hour=03");
+        assert_eq!(partition_key(AT, Partitioning::Daily), "date=2024-01-02");
+    }
+
+    #[test]
+    fn tick_publishes_the_open_file_after_max_file_ms() {
+        let config = ParquetConfig { max_pending_ms: 0, max_file_ms: 0, ..config("age") };
+        let mut table = PartitionedTable::<TradeLogs>::new(config.clone());
+        for quantity in [3, 1, 2] {
+            table.push(trade(1, 2).at(AT + quantity as i64).qty(quantity));
+        }
+        table.tick();
+
+        assert!(table.partitions.is_empty());
+        let files = files(&config);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "parquet");
+        assert_eq!(quantities(&files[0]), [3, 1, 2]);
+    }
+
+    #[test]
+    fn file_is_published_after_file_row_groups() {
+        let config = ParquetConfig { row_group_rows: 2, file_row_groups: 2, ..config("groups") };
+        let mut table = PartitionedTable::<TradeLogs>::new(config.clone());
+        for quantity in 1..=5 {
+            table.push(trade(1, 2).at(AT + quantity as i64).qty(quantity));
+        }
+
+        // two row groups went into the published file , the fifth row is still pending
+        let files = files(&config);
+        assert_eq!(files.len(), 1);
+        assert_eq!(quantities(&files[0]), [1, 2, 3, 4]);
+        assert_eq!(table.partitions.values().next().unwrap().pending.len(), 1);
+    }
+
+    #[test]
+    fn rows_that_can_not_be_written_are_counted() {
+        let config = config("unwritable");
+        // a file where the table directory should be
+        fs::create_dir_all(&config.dir).unwrap();
+        fs::write(config.dir.join(TradeLogs::TABLE), "").unwrap();
+        let mut table = PartitionedTable::<TradeLogs>::new(ParquetConfig { row_group_rows: 2, ..config });
+        for quantity in 1..=3 {
+            table.push(trade(1, 2).at(AT).qty(quantity));
+        }
+
+        assert_eq!(table.rows_dropped, 2);
+        assert_eq!(table.partitions.values().next().unwrap().pending.len(), 1);
+    }
+
+    #[test]
+    fn failed_writer_is_abandoned() {
+        // row groups well past the writer's buffer , so a write goes to the file right away
+        const ROWS: u32 = 4096;
+        let config = ParquetConfig { row_group_rows: ROWS as usize, ..config("abandon") };
+        let mut table = PartitionedTable::<TradeLogs>::new(config.clone());
+        let push = |table: &mut PartitionedTable<TradeLogs>, from: u32| {
+            for n in from..from + ROWS {
+                table.push(trade(n as u64, n as u64 + 1).at(AT + n as i64).qty(n));
+            }
+        };
+        push(&mut table, 0);
+        let key = partition_key(AT, config.partitioning);
+        assert_eq!(table.partitions[&key].file_rows, ROWS as usize);
+
+        // the open file turns read only under the writer
+        let partition = table.partitions.get_mut(&key).unwrap();
+        let path = partition.file_path.clone().unwrap();
+        partition.writer = Some(ArrowWriter::try_new(File::open(&path).unwrap(), table.schema.clone(), None).unwrap());
+        push(&mut table, ROWS);
+
+        let partition = &table.partitions[&key];
+        assert!(partition.writer.is_none() && partition.file_path.is_none());
+        assert_eq!((partition.row_groups, partition.file_rows), (0, 0));
+        assert_eq!(table.rows_dropped, 2 * ROWS as u64);
+
+        // the next row group goes to a new file
+        push(&mut table, 2 * ROWS);
+        assert!(table.partitions[&key].file_path.as_ref().is_some_and(|next| *next != path));
+    }
+}
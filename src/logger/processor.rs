@@ -0,0 +1,49 @@
+use questdb::ingress::Buffer;
+
+use crate::logger::types::{
+    BalanceLogWrapper,
+    HoldingLogWrapper,
+    OrderBookSnapShot,
+    OrderLogWrapper,
+    Stream,
+    TradeLogs,
+};
+
+// derived tables computed in the flusher from the decoded records
+// rows are written into the flusher's derived buffer , which has its own flush policy
+pub trait LogProcessor: Send {
+    // a processor only runs in the flusher that owns every stream listed here
+    fn streams(&self) -> &[Stream];
+
+    fn on_order_log(&mut self, _log: &OrderLogWrapper, _out: &mut Buffer) -> questdb::Result<()> {
+        Ok(())
+    }
+
+    fn on_balance_log(&mut self, _log: &BalanceLogWrapper, _out: &mut Buffer) -> questdb::Result<()> {
+        Ok(())
+    }
+
+    fn on_holding_log(&mut self, _log: &HoldingLogWrapper, _out: &mut Buffer) -> questdb::Result<()> {
+        Ok(())
+    }
+
+    fn on_trade_log(&mut self, _log: &TradeLogs, _out: &mut Buffer) -> questdb::Result<()> {
+        Ok(())
+    }
+
+    fn on_snapshot(&mut self, _snap: &OrderBookSnapShot, _out: &mut Buffer) -> questdb::Result<()> {
+        Ok(())
+    }
+
+    // called on every flusher loop iteration with the wall clock in nanos , for time based output
+    fn on_tick(&mut self, _now: i64, _out: &mut Buffer) -> questdb::Result<()> {
+        Ok(())
+    }
+}
+
+#[inline(always)]
+pub fn now_nanos() -> i64 {
+    chrono::Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX)
+}
+
+pub const NANOS_PER_MILLI: i64 = 1_000_000;
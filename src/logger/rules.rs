@@ -0,0 +1,571 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use chrono::{DateTime, NaiveTime, Utc};
+use crossbeam::channel::{Receiver, Sender};
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::logger::processor::{LogProcessor, NANOS_PER_MILLI};
+use crate::logger::serde_fields;
+use crate::logger::types::{BalanceLogWrapper, HoldingLogWrapper, OrderBookSnapShot, OrderLogWrapper, Stream, TradeLogs};
+
+const NOTIFY_QUEUE: usize = 1024;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RulesConfig {
+    pub rules: Vec<RuleConfig>,
+    pub notifier: Option<NotifierConfig>,
+}
+
+// rules see records as the JSON sinks write them , e.g. "severity": "error" , "balance_delta.user_id"
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleConfig {
+    pub name: String,
+    pub stream: Stream,
+    // every condition has to hold for a record to match
+    #[serde(default, rename = "where")]
+    pub conditions: Vec<Condition>,
+    // field whose value splits the rule into independent groups , e.g. "balance_delta.user_id"
+    pub group_by: Option<String>,
+    #[serde(flatten)]
+    pub kind: RuleKind,
+    // a group raises the same rule at most once per cooldown
+    #[serde(default = "default_cooldown_ms")]
+    pub cooldown_ms: u64,
+}
+
+fn default_cooldown_ms() -> u64 {
+    60_000
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RuleKind {
+    // more than `more_than` matching records within window_ms ( producer time )
+    Count { more_than: u64, window_ms: u64 },
+    // a matching record whose field compares true against value
+    Threshold {
+        field: String,
+        op: Op,
+        value: f64,
+        #[serde(default)]
+        abs: bool,
+    },
+    // no matching record for window_ms , only checked inside the session when one is given
+    Absence { window_ms: u64, session: Option<Session> },
+}
+
+impl RuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleKind::Count { .. } => "count",
+            RuleKind::Threshold { .. } => "threshold",
+            RuleKind::Absence { .. } => "absence",
+        }
+    }
+}
+
+// UTC wall clock times , "09:15" - "15:30" ; an end before the start spans midnight
+#[derive(Debug, Clone, Deserialize)]
+pub struct Session {
+    #[serde(deserialize_with = "clock_time")]
+    pub start: NaiveTime,
+    #[serde(deserialize_with = "clock_time")]
+    pub end: NaiveTime,
+}
+
+fn clock_time<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&raw, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(&raw, "%H:%M:%S"))
+        .map_err(|e| serde::de::Error::custom(format!("invalid time {}: {}", raw, e)))
+}
+
+impl Session {
+    // start of the session `now` falls in , None outside of it
+    fn open_since(&self, now: i64) -> Option<i64> {
+        let now = DateTime::<Utc>::from_timestamp_nanos(now);
+        let time = now.time();
+        let today = now.date_naive();
+        let start_day = if self.start <= self.end {
+            (time >= self.start && time < self.end).then_some(today)?
+        } else if time >= self.start {
+            today
+        } else if time < self.end {
+            today.pred_opt()?
+        } else {
+            return None;
+        };
+        start_day.and_time(self.start).and_utc().timestamp_nanos_opt()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Op {
+    fn holds(&self, ordering: Option<std::cmp::Ordering>) -> bool {
+        use std::cmp::Ordering::*;
+        match (self, ordering) {
+            (_, None) => matches!(self, Op::Ne),
+            (Op::Eq, Some(o)) => o == Equal,
+            (Op::Ne, Some(o)) => o != Equal,
+            (Op::Gt, Some(o)) => o == Greater,
+            (Op::Gte, Some(o)) => o != Less,
+            (Op::Lt, Some(o)) => o == Less,
+            (Op::Lte, Some(o)) => o != Greater,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Condition {
+    pub field: String,
+    pub op: Op,
+    pub value: Value,
+}
+
+impl Condition {
+    fn matches(&self, record: &Value) -> bool {
+        let actual = lookup(record, &self.field);
+        let ordering = match (actual, &self.value) {
+            (Some(Value::Number(a)), Value::Number(b)) => a.as_f64().zip(b.as_f64()).and_then(|(a, b)| a.partial_cmp(&b)),
+            (Some(Value::String(a)), Value::String(b)) => Some(a.cmp(b)),
+            (Some(a), b) => (a == b).then_some(std::cmp::Ordering::Equal),
+            (None, _) => None,
+        };
+        self.op.holds(ordering)
+    }
+}
+
+// dotted path into the record , "holding_delta.symbol"
+pub fn lookup<'a>(record: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(record, |value, key| value.get(key))
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NotifierConfig {
+    // every alert is POSTed as JSON
+    pub webhook_url: Option<String>,
+    // every alert is written as a JSON line , reconnecting when the listener goes away
+    pub unix_socket: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleAlert {
+    pub rule: String,
+    pub kind: &'static str,
+    pub stream: Stream,
+    pub group: Option<String>,
+    // count , field value or silence in ms , depending on the kind
+    pub value: f64,
+    pub message: String,
+    #[serde(with = "serde_fields::timestamp")]
+    pub timestamp: i64,
+}
+
+// runs on its own thread so a slow webhook never holds up a flusher
+fn run_notifier(config: NotifierConfig, alerts: Receiver<RuleAlert>) {
+    let agent: ureq::Agent = ureq::Agent::config_builder().build().into();
+    let mut socket: Option<UnixStream> = None;
+    for alert in alerts {
+        let Ok(line) = serde_json::to_string(&alert) else {
+            continue;
+        };
+        if let Some(url) = &config.webhook_url
+            && let Err(e) = agent.post(url).header("Content-Type", "application/json").send(line.as_str())
+        {
+            eprintln!("rules notifier: webhook {} failed: {}", url, e);
+        }
+        if let Some(path) = &config.unix_socket {
+            if socket.is_none() {
+                socket = UnixStream::connect(path)
+                    .map_err(|e| eprintln!("rules notifier: failed to connect to {}: {}", path.display(), e))
+                    .ok();
+            }
+            if let Some(stream) = socket.as_mut()
+                && let Err(e) = writeln!(stream, "{}", line)
+            {
+                eprintln!("rules notifier: write to {} failed: {}", path.display(), e);
+                socket = None;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GroupState {
+    // producer timestamps of recent matches , count rules only
+    pub matches: VecDeque<i64>,
+    // wall clock of the last match , absence rules only
+    pub last_match: i64,
+    pub last_alert: Option<i64>,
+}
+
+pub struct Rules {
+    pub rules: Vec<RuleConfig>,
+    // streams the rules watch , the flusher owning all of them runs this processor
+    pub streams: Vec<Stream>,
+    // per stream , whether any rule has to look into the record , indexed by Stream::index
+    pub needs_value: [bool; 5],
+    // per rule , keyed by the group value ( "" without group_by )
+    pub groups: Vec<HashMap<String, GroupState>>,
+    pub notifier: Option<Sender<RuleAlert>>,
+    pub now: i64,
+    pub started: i64,
+    pub alerts: u64,
+    pub dropped_notifications: u64,
+}
+
+impl Rules {
+    // rules are independent , so every flusher group gets the rules on the streams it owns
+    // and the notifier thread is shared by all of them
+    pub fn per_group(config: RulesConfig, groups: &[Vec<Stream>]) -> Vec<Self> {
+        let notifier = config.notifier.map(|notifier| {
+            let (tx, rx) = crossbeam::channel::bounded(NOTIFY_QUEUE);
+            std::thread::spawn(move || run_notifier(notifier, rx));
+            tx
+        });
+        groups
+            .iter()
+            .filter_map(|owned| {
+                let rules: Vec<RuleConfig> = config.rules.iter().filter(|r| owned.contains(&r.stream)).cloned().collect();
+                (!rules.is_empty()).then(|| Self::new(rules, notifier.clone()))
+            })
+            .collect()
+    }
+
+    pub fn new(rules: Vec<RuleConfig>, notifier: Option<Sender<RuleAlert>>) -> Self {
+        let mut streams: Vec<Stream> = Vec::new();
+        let mut needs_value = [false; 5];
+        for rule in &rules {
+            if !streams.contains(&rule.stream) {
+                streams.push(rule.stream);
+            }
+            // count and absence rules without conditions only need to know a record arrived
+            needs_value[rule.stream.index()] |= !rule.conditions.is_empty()
+                || rule.group_by.is_some()
+                || matches!(rule.kind, RuleKind::Threshold { .. });
+        }
+        Self {
+            groups: rules.iter().map(|_| HashMap::new()).collect(),
+            rules,
+            streams,
+            needs_value,
+            notifier,
+            now: 0,
+            started: crate::logger::processor::now_nanos(),
+            alerts: 0,
+            dropped_notifications: 0,
+        }
+    }
+
+    fn raise(&mut self, out: &mut Buffer, alert: RuleAlert) -> questdb::Result<()> {
+        self.alerts += 1;
+        eprintln!("rule {} ({}): {}", alert.rule, alert.kind, alert.message);
+        out.table("rule_alerts")?
+            .symbol("rule", &alert.rule)?
+            .symbol("kind", alert.kind)?
+            .symbol("stream", alert.stream.table())?
+            .column_str("group_key", alert.group.as_deref().unwrap_or(""))?
+            .column_f64("value", alert.value)?
+            .column_str("message", &alert.message)?
+            .at(TimestampNanos::new(alert.timestamp))?;
+        if let Some(notifier) = &self.notifier
+            && notifier.try_send(alert).is_err()
+        {
+            self.dropped_notifications += 1;
+        }
+        Ok(())
+    }
+
+    fn evaluate<T: Serialize>(&mut self, stream: Stream, record: &T, timestamp: i64, out: &mut Buffer) -> questdb::Result<()> {
+        if !self.streams.contains(&stream) {
+            return Ok(());
+        }
+        // converting a 40 level snapshot is not free , only done when a rule reads a field
+        let value = if self.needs_value[stream.index()] {
+            match serde_json::to_value(record) {
+                Ok(value) => value,
+                Err(_) => return Ok(()),
+            }
+        } else {
+            Value::Null
+        };
+        let now = self.now;
+        let mut raised = Vec::new();
+        for (rule, groups) in self.rules.iter().zip(self.groups.iter_mut()) {
+            if rule.stream != stream || !rule.conditions.iter().all(|c| c.matches(&value)) {
+                continue;
+            }
+            let group = rule.group_by.as_ref().map(|field| {
+                lookup(&value, field).map(|v| v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string())).unwrap_or_default()
+            });
+            let state = groups.entry(group.clone().unwrap_or_default()).or_default();
+            let cooldown = rule.cooldown_ms as i64 * NANOS_PER_MILLI;
+            let cooled = state.last_alert.is_none_or(|at| now - at >= cooldown);
+
+            let (fire, measured, message) = match &rule.kind {
+                RuleKind::Count { more_than, window_ms } => {
+                    let window = *window_ms as i64 * NANOS_PER_MILLI;
+                    state.matches.push_back(timestamp);
+                    while state.matches.front().is_some_and(|t| timestamp - t > window) {
+                        state.matches.pop_front();
+                    }
+                    let count = state.matches.len() as u64;
+                    (count > *more_than, count as f64, format!("{} matching records in {}ms (limit {})", count, window_ms, more_than))
+                }
+                RuleKind::Threshold { field, op, value: limit, abs } => {
+                    let Some(actual) = lookup(&value, field).and_then(Value::as_f64) else {
+                        continue;
+                    };
+                    let actual = if *abs { actual.abs() } else { actual };
+                    let fire = op.holds(actual.partial_cmp(limit));
+                    (fire, actual, format!("{} = {} ({:?} {})", field, actual, op, limit))
+                }
+                RuleKind::Absence { .. } => {
+                    state.last_match = now;
+                    continue;
+                }
+            };
+            if fire && cooled {
+                state.last_alert = Some(now);
+                raised.push(RuleAlert {
+                    rule: rule.name.clone(),
+                    kind: rule.kind.as_str(),
+                    stream,
+                    group,
+                    value: measured,
+                    message,
+                    timestamp,
+                });
+            }
+        }
+        for alert in raised {
+            self.raise(out, alert)?;
+        }
+        Ok(())
+    }
+}
+
+impl LogProcessor for Rules {
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    fn on_order_log(&mut self, log: &OrderLogWrapper, out: &mut Buffer) -> questdb::Result<()> {
+        self.evaluate(Stream::OrderLogs, log, log.timestamp, out)
+    }
+
+    fn on_balance_log(&mut self, log: &BalanceLogWrapper, out: &mut Buffer) -> questdb::Result<()> {
+        self.evaluate(Stream::BalanceLogs, log, log.timestamp, out)
+    }
+
+    fn on_holding_log(&mut self, log: &HoldingLogWrapper, out: &mut Buffer) -> questdb::Result<()> {
+        self.evaluate(Stream::HoldingLogs, log, log.timestamp, out)
+    }
+
+    fn on_trade_log(&mut self, log: &TradeLogs, out: &mut Buffer) -> questdb::Result<()> {
+        self.evaluate(Stream::TradeLogs, log, log.timestamp, out)
+    }
+
+    fn on_snapshot(&mut self, snap: &OrderBookSnapShot, out: &mut Buffer) -> questdb::Result<()> {
+        self.evaluate(Stream::Snapshots, snap, snap.timestamp, out)
+    }
+
+    // absence rules are wall clock driven
+    fn on_tick(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        self.now = now;
+        let mut raised = Vec::new();
+        for (rule, groups) in self.rules.iter().zip(self.groups.iter_mut()) {
+            let RuleKind::Absence { window_ms, session } = &rule.kind else {
+                continue;
+            };
+            let since = match session {
+                Some(session) => match session.open_since(now) {
+                    Some(open) => open,
+                    None => continue,
+                },
+                None => self.started,
+            };
+            // a rule without group_by watches the stream as a whole , even before anything matched
+            if rule.group_by.is_none() {
+                groups.entry(String::new()).or_default();
+            }
+            let window = *window_ms as i64 * NANOS_PER_MILLI;
+            let cooldown = rule.cooldown_ms as i64 * NANOS_PER_MILLI;
+            for (group, state) in groups.iter_mut() {
+                let silent_since = state.last_match.max(since).max(self.started);
+                if now - silent_since < window || state.last_alert.is_some_and(|at| now - at < cooldown.max(window)) {
+                    continue;
+                }
+                state.last_alert = Some(now);
+                let silence_ms = (now - silent_since) / NANOS_PER_MILLI;
+                raised.push(RuleAlert {
+                    rule: rule.name.clone(),
+                    kind: rule.kind.as_str(),
+                    stream: rule.stream,
+                    group: rule.group_by.as_ref().map(|_| group.clone()),
+                    value: silence_ms as f64,
+                    message: format!("no matching {} for {}ms", rule.stream.table(), silence_ms),
+                    timestamp: now,
+                });
+            }
+        }
+        for alert in raised {
+            self.raise(out, alert)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{balance, trade};
+    use questdb::ingress::ProtocolVersion;
+
+    const MS: i64 = NANOS_PER_MILLI;
+    // 2024-01-02T00:00:00Z
+    const DAY: i64 = 1_704_153_600_000_000_000;
+    const HOUR: i64 = 3_600_000 * MS;
+
+    fn rules(json: &str) -> Rules {
+        let rules: Vec<RuleConfig> = serde_json::from_str(json).unwrap();
+        let mut rules = Rules::new(rules, None);
+        rules.started = 0;
+        rules
+    }
+
+    fn alerts(out: &Buffer) -> Vec<String> {
+        std::str::from_utf8(out.as_bytes())
+            .unwrap()
+            .lines()
+            .map(|row| {
+                let rule = row.strip_prefix("rule_alerts,rule=").unwrap().split(',').next().unwrap();
+                let group = row.split("group_key=\"").nth(1).unwrap().split('"').next().unwrap();
+                format!("{}:{}", rule, group)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn threshold_compares_the_field_after_the_conditions() {
+        let mut rules = rules(
+            r#"[{ "name": "big_debit", "stream": "balance_logs", "kind": "threshold",
+                  "field": "balance_delta.delta_available", "op": "gt", "value": 1000, "abs": true,
+                  "where": [{ "field": "balance_delta.user_id", "op": "ne", "value": 1 }], "cooldown_ms": 0 }]"#,
+        );
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        rules.on_balance_log(&balance(1, 2).deltas(-500, 0).reason(1), &mut out).unwrap();
+        rules.on_balance_log(&balance(1, 1).deltas(-5_000, 0).reason(1), &mut out).unwrap();
+        assert!(alerts(&out).is_empty());
+        rules.on_balance_log(&balance(1, 2).deltas(-5_000, 0).reason(1), &mut out).unwrap();
+        assert_eq!(alerts(&out), ["big_debit:"]);
+        assert!(std::str::from_utf8(out.as_bytes()).unwrap().contains("value=5000"));
+    }
+
+    #[test]
+    fn count_fires_past_the_limit_within_the_window_per_group() {
+        let mut rules = rules(
+            r#"[{ "name": "churn", "stream": "balance_logs", "kind": "count", "more_than": 2, "window_ms": 1000,
+                  "group_by": "balance_delta.user_id", "cooldown_ms": 0 }]"#,
+        );
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        // the first one falls out of the window before the third arrives
+        for at in [0, 600, 1_200] {
+            rules.on_balance_log(&balance(1, 1).at(at * MS).deltas(1, 0).reason(1), &mut out).unwrap();
+        }
+        rules.on_balance_log(&balance(1, 2).at(1_200 * MS).deltas(1, 0).reason(1), &mut out).unwrap();
+        assert!(alerts(&out).is_empty());
+        rules.on_balance_log(&balance(1, 1).at(1_300 * MS).deltas(1, 0).reason(1), &mut out).unwrap();
+        assert_eq!(alerts(&out), ["churn:1"]);
+    }
+
+    #[test]
+    fn cooldown_holds_repeated_alerts_back() {
+        let mut rules = rules(
+            r#"[{ "name": "big_trade", "stream": "trade_logs", "kind": "threshold",
+                  "field": "quantity", "op": "gte", "value": 100, "cooldown_ms": 1000 }]"#,
+        );
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        rules.on_tick(10_000 * MS, &mut out).unwrap();
+        rules.on_trade_log(&trade(1, 2).qty(100), &mut out).unwrap();
+        rules.on_trade_log(&trade(1, 2).qty(200), &mut out).unwrap();
+        rules.on_tick(10_999 * MS, &mut out).unwrap();
+        rules.on_trade_log(&trade(1, 2).qty(200), &mut out).unwrap();
+        assert_eq!(alerts(&out).len(), 1);
+        rules.on_tick(11_000 * MS, &mut out).unwrap();
+        rules.on_trade_log(&trade(1, 2).qty(200), &mut out).unwrap();
+        assert_eq!(alerts(&out).len(), 2);
+    }
+
+    #[test]
+    fn absence_fires_after_the_window_of_silence() {
+        let mut rules = rules(
+            r#"[{ "name": "no_trades", "stream": "trade_logs", "kind": "absence", "window_ms": 1000, "cooldown_ms": 0 }]"#,
+        );
+        // an absence rule without conditions never needs the record as JSON
+        assert!(!rules.needs_value[Stream::TradeLogs.index()]);
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        rules.on_tick(500 * MS, &mut out).unwrap();
+        rules.on_trade_log(&trade(1, 2), &mut out).unwrap();
+        rules.on_tick(1_499 * MS, &mut out).unwrap();
+        assert!(alerts(&out).is_empty());
+        rules.on_tick(1_500 * MS, &mut out).unwrap();
+        assert_eq!(alerts(&out), ["no_trades:"]);
+        // once per window while the silence goes on
+        rules.on_tick(2_000 * MS, &mut out).unwrap();
+        assert_eq!(alerts(&out).len(), 1);
+        rules.on_tick(2_500 * MS, &mut out).unwrap();
+        assert_eq!(alerts(&out).len(), 2);
+    }
+
+    #[test]
+    fn absence_is_only_checked_inside_the_session() {
+        let mut rules = rules(
+            r#"[{ "name": "quiet_open", "stream": "trade_logs", "kind": "absence", "window_ms": 60000,
+                  "session": { "start": "09:00", "end": "17:00" }, "cooldown_ms": 0 }]"#,
+        );
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        // silent all night , nothing is raised before the session
+        rules.on_tick(DAY + 8 * HOUR, &mut out).unwrap();
+        // the silence is counted from the session start
+        rules.on_tick(DAY + 9 * HOUR + 59_000 * MS, &mut out).unwrap();
+        assert!(alerts(&out).is_empty());
+        rules.on_tick(DAY + 9 * HOUR + 60_000 * MS, &mut out).unwrap();
+        assert_eq!(alerts(&out), ["quiet_open:"]);
+        rules.on_tick(DAY + 18 * HOUR, &mut out).unwrap();
+        assert_eq!(alerts(&out).len(), 1);
+    }
+
+    #[test]
+    fn rules_are_split_per_owning_group() {
+        let config: RulesConfig = serde_json::from_str(
+            r#"{ "rules": [
+                { "name": "a", "stream": "trade_logs", "kind": "absence", "window_ms": 1000 },
+                { "name": "b", "stream": "balance_logs", "kind": "absence", "window_ms": 1000 },
+                { "name": "c", "stream": "trade_logs", "kind": "absence", "window_ms": 1000 } ] }"#,
+        )
+        .unwrap();
+        let groups = [vec![Stream::OrderLogs], vec![Stream::TradeLogs, Stream::Snapshots], vec![Stream::BalanceLogs]];
+        let split = Rules::per_group(config, &groups);
+        let names: Vec<Vec<&str>> = split.iter().map(|r| r.rules.iter().map(|r| r.name.as_str()).collect()).collect();
+        assert_eq!(names, [vec!["a", "c"], vec!["b"]]);
+        assert_eq!(split[0].streams(), [Stream::TradeLogs]);
+    }
+}
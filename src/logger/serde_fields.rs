@@ -0,0 +1,144 @@
+// serde adapters used by the log types , the raw u8 enums and nanosecond timestamps
+// are written in their decoded form and parsed back on the way in
+// an enum value without a name is written as its number , so nothing is lost
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::logger::types::{order_event_type_name, reason_name, severity_name, side_name};
+
+pub fn format_timestamp(timestamp: i64) -> String {
+    DateTime::<Utc>::from_timestamp_nanos(timestamp).to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+pub fn parse_timestamp(raw: &str) -> Result<i64, String> {
+    DateTime::parse_from_rfc3339(raw)
+        .map_err(|e| format!("invalid timestamp {}: {}", raw, e))?
+        .timestamp_nanos_opt()
+        .ok_or_else(|| format!("timestamp out of range: {}", raw))
+}
+
+pub mod timestamp {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(timestamp: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_timestamp(*timestamp))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        parse_timestamp(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawEnum {
+    Name(String),
+    Value(u8),
+}
+
+fn encode<S: Serializer>(value: u8, serializer: S, name: fn(u8) -> Option<&'static str>) -> Result<S::Ok, S::Error> {
+    match name(value) {
+        Some(name) => serializer.serialize_str(name),
+        None => serializer.serialize_u8(value),
+    }
+}
+
+fn decode<'de, D: Deserializer<'de>>(deserializer: D, name: fn(u8) -> Option<&'static str>) -> Result<u8, D::Error> {
+    match RawEnum::deserialize(deserializer)? {
+        RawEnum::Value(value) => Ok(value),
+        RawEnum::Name(raw) => (0..=u8::MAX)
+            .find(|value| name(*value) == Some(raw.as_str()))
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown value {}", raw))),
+    }
+}
+
+pub mod side {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(side: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+        encode(*side, serializer, side_name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        decode(deserializer, side_name)
+    }
+}
+
+pub mod order_event_type {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(event_type: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+        encode(*event_type, serializer, order_event_type_name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        decode(deserializer, order_event_type_name)
+    }
+}
+
+pub mod severity {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(severity: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+        encode(*severity, serializer, severity_name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        decode(deserializer, severity_name)
+    }
+}
+
+pub mod reason {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(reason: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+        encode(*reason, serializer, reason_name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        decode(deserializer, reason_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logger::types::test_records::order;
+    use crate::logger::types::{LogRecord, OrderLogWrapper};
+
+    fn order_log(side: u8, order_event_type: u8, severity: u8) -> LogRecord {
+        order(2, 3).at(1_700_000_000_123_456_789).side(side).event(order_event_type).severity(severity).into()
+    }
+
+    fn round_trip(record: LogRecord) -> (String, OrderLogWrapper) {
+        let line = serde_json::to_string(&record).unwrap();
+        match serde_json::from_str::<LogRecord>(&line).unwrap() {
+            LogRecord::OrderLog(log) => (line, log),
+            other => panic!("came back as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn known_values_are_named() {
+        let (line, log) = round_trip(order_log(1, 2, 1));
+        assert!(line.contains(r#""side":"ask""#) && line.contains(r#""order_event_type":"canceled""#));
+        assert!(line.contains(r#""severity":"error""#) && line.contains("2023-11-14T22:13:20.123456789Z"));
+        assert_eq!((log.order_delta.side, log.order_delta.order_event_type, log.severity), (1, 2, 1));
+        assert_eq!(log.timestamp, 1_700_000_000_123_456_789);
+    }
+
+    #[test]
+    fn unknown_values_keep_their_number() {
+        let (line, log) = round_trip(order_log(7, 9, 200));
+        assert!(line.contains(r#""side":7"#) && line.contains(r#""order_event_type":9"#));
+        assert!(line.contains(r#""severity":200"#));
+        assert_eq!((log.order_delta.side, log.order_delta.order_event_type, log.severity), (7, 9, 200));
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        let line = serde_json::to_string(&order_log(0, 0, 0)).unwrap().replace(r#""bid""#, r#""sideways""#);
+        assert!(serde_json::from_str::<LogRecord>(&line).is_err());
+    }
+}
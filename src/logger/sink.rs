@@ -0,0 +1,28 @@
+use crate::logger::types::{
+    BalanceLogWrapper,
+    HoldingLogWrapper,
+    OrderBookSnapShot,
+    OrderLogWrapper,
+    TradeLogs,
+};
+
+// a secondary destination fed by the flusher next to QuestDB (files , exporters ...)
+// sinks own their own error handling , the flusher never stops because a sink failed
+pub trait LogSink: Send {
+    fn write_order_log(&mut self, _log: &OrderLogWrapper) {}
+
+    fn write_balance_log(&mut self, _log: &BalanceLogWrapper) {}
+
+    fn write_holding_log(&mut self, _log: &HoldingLogWrapper) {}
+
+    fn write_trade_log(&mut self, _log: &TradeLogs) {}
+
+    fn write_snapshot(&mut self, _snap: &OrderBookSnapShot) {}
+
+    // true for sinks that must see every snapshot , they are fed at dequeue before conflation
+    // instead of with the snapshots that made it to storage
+    fn every_snapshot(&self) -> bool { false }
+
+    // called on every flusher loop iteration , sinks decide themselves when to hit the disk
+    fn tick(&mut self) {}
+}
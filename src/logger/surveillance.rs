@@ -0,0 +1,472 @@
+use std::collections::{HashMap, VecDeque};
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::{LogProcessor, NANOS_PER_MILLI};
+use crate::logger::types::{side_str, OrderLogWrapper, Stream, TradeLogs};
+
+const RECEIVED: u8 = 0;
+const MATCHED: u8 = 1;
+const CANCELED: u8 = 2;
+
+const BUY: u8 = 0;
+const SELL: u8 = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SurveillanceConfig {
+    // order -> user mapping is kept this long after the order's last event
+    pub order_ttl_ms: u64,
+    // trades whose orders are not known yet wait this long for the order logs
+    pub resolve_ms: u64,
+    pub wash_trades: bool,
+    // an order this large , canceled within spoof_cancel_ms without any fill , is a spoof candidate
+    pub spoof_min_qty: u32,
+    pub spoof_cancel_ms: u64,
+    // this many spoof candidates by one user on one side of a symbol within the window is layering
+    pub layering_min_orders: usize,
+    pub layering_window_ms: u64,
+    // aggressive fills by one user in one direction that move the price by at least momentum_move_bps
+    // within momentum_window_ms , followed by a trade on the other side within momentum_reversal_ms
+    pub momentum_min_trades: usize,
+    pub momentum_window_ms: u64,
+    pub momentum_move_bps: f64,
+    pub momentum_reversal_ms: u64,
+}
+
+impl Default for SurveillanceConfig {
+    fn default() -> Self {
+        Self {
+            order_ttl_ms: 600_000,
+            resolve_ms: 1_000,
+            wash_trades: true,
+            spoof_min_qty: 10_000,
+            spoof_cancel_ms: 2_000,
+            layering_min_orders: 3,
+            layering_window_ms: 5_000,
+            momentum_min_trades: 5,
+            momentum_window_ms: 1_000,
+            momentum_move_bps: 50.0,
+            momentum_reversal_ms: 10_000,
+        }
+    }
+}
+
+// one alert row , the other detectors write the same columns to a table of their own
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub kind: &'static str,
+    // None for alerts about a user's activity across instruments
+    pub symbol: Option<u32>,
+    pub user_id: u64,
+    pub order_ids: Vec<u64>,
+    pub evidence: String,
+    // producer timestamp of the event that completed the pattern
+    pub timestamp: i64,
+}
+
+pub fn write_alert(out: &mut Buffer, table: &str, alert: &Alert) -> questdb::Result<()> {
+    eprintln!(
+        "{}: {} by user {} on {} ({})",
+        table,
+        alert.kind, alert.user_id, alert.symbol.map(|s| s.to_string()).unwrap_or("all instruments".to_string()), alert.evidence
+    );
+    let order_ids = alert.order_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+    out.table(table)?.symbol("alert_type", alert.kind)?;
+    if let Some(symbol) = alert.symbol {
+        out.symbol("instrument", symbol.to_string())?;
+    }
+    out.column_i64("user_id", alert.user_id as i64)?
+        .column_str("order_ids", &order_ids)?
+        .column_str("evidence", &alert.evidence)?
+        .at(TimestampNanos::new(alert.timestamp))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OrderInfo {
+    pub user_id: u64,
+    pub symbol: u32,
+    pub side: u8,
+    pub price: u64,
+    pub qty: u32,
+    pub filled: u32,
+    pub received_at: Option<i64>,
+    pub last_seen: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpoofCandidate {
+    pub order_id: u64,
+    pub qty: u32,
+    pub canceled_at: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fill {
+    pub order_id: u64,
+    pub side: u8,
+    pub price: u64,
+    pub qty: u32,
+    pub timestamp: i64,
+}
+
+// a burst of aggressive fills waiting for the reversal that makes it ignition
+#[derive(Debug, Clone)]
+pub struct Ignition {
+    pub side: u8,
+    pub fills: Vec<Fill>,
+    pub move_bps: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PendingTrade {
+    pub trade: TradeLogs,
+    pub arrived: i64,
+}
+
+pub struct Surveillance {
+    pub config: SurveillanceConfig,
+    pub orders: HashMap<u64, OrderInfo>,
+    pub pending: VecDeque<PendingTrade>,
+    // canceled spoof candidates per (user , symbol , side)
+    pub spoofs: HashMap<(u64, u32, u8), VecDeque<SpoofCandidate>>,
+    // aggressive fills per (user , symbol)
+    pub fills: HashMap<(u64, u32), VecDeque<Fill>>,
+    pub ignitions: HashMap<(u64, u32), Ignition>,
+    pub now: i64,
+    pub last_sweep: i64,
+    pub alerts: u64,
+}
+
+impl Surveillance {
+    pub fn new(config: SurveillanceConfig) -> Self {
+        Self {
+            config,
+            orders: HashMap::new(),
+            pending: VecDeque::new(),
+            spoofs: HashMap::new(),
+            fills: HashMap::new(),
+            ignitions: HashMap::new(),
+            now: 0,
+            last_sweep: 0,
+            alerts: 0,
+        }
+    }
+
+    fn emit(&mut self, out: &mut Buffer, alert: &Alert) -> questdb::Result<()> {
+        self.alerts += 1;
+        write_alert(out, "surveillance_alerts", alert)
+    }
+
+    fn on_cancel(&mut self, order_id: u64, order: &OrderInfo, at: i64, out: &mut Buffer) -> questdb::Result<()> {
+        let Some(received_at) = order.received_at else {
+            return Ok(());
+        };
+        let lived = at - received_at;
+        if order.qty < self.config.spoof_min_qty
+            || order.filled > 0
+            || lived > self.config.spoof_cancel_ms as i64 * NANOS_PER_MILLI
+        {
+            return Ok(());
+        }
+        self.emit(out, &Alert {
+            kind: "spoofing",
+            symbol: Some(order.symbol),
+            user_id: order.user_id,
+            order_ids: vec![order_id],
+            evidence: format!(
+                "{} {}@{} canceled after {:.3}ms without fills",
+                side_str(order.side), order.qty, order.price, lived as f64 / NANOS_PER_MILLI as f64
+            ),
+            timestamp: at,
+        })?;
+
+        let window = self.config.layering_window_ms as i64 * NANOS_PER_MILLI;
+        let key = (order.user_id, order.symbol, order.side);
+        let candidates = self.spoofs.entry(key).or_default();
+        candidates.push_back(SpoofCandidate { order_id, qty: order.qty, canceled_at: at });
+        while candidates.front().is_some_and(|c| at - c.canceled_at > window) {
+            candidates.pop_front();
+        }
+        if candidates.len() < self.config.layering_min_orders {
+            return Ok(());
+        }
+        let layered: Vec<SpoofCandidate> = candidates.drain(..).collect();
+        self.emit(out, &Alert {
+            kind: "layering",
+            symbol: Some(order.symbol),
+            user_id: order.user_id,
+            order_ids: layered.iter().map(|c| c.order_id).collect(),
+            evidence: format!(
+                "{} large {} orders ({} total) canceled within {}ms",
+                layered.len(), side_str(order.side), layered.iter().map(|c| c.qty as u64).sum::<u64>(), self.config.layering_window_ms
+            ),
+            timestamp: at,
+        })
+    }
+
+    // both users known , run the trade based detectors
+    fn check_trade(&mut self, trade: &TradeLogs, buyer: u64, seller: u64, out: &mut Buffer) -> questdb::Result<()> {
+        if self.config.wash_trades && buyer == seller {
+            self.emit(out, &Alert {
+                kind: "wash_trade",
+                symbol: Some(trade.symbol),
+                user_id: buyer,
+                order_ids: vec![trade.buyer_order_id, trade.seller_order_id],
+                evidence: format!("{}@{} between orders of the same user", trade.quantity, trade.price),
+                timestamp: trade.timestamp,
+            })?;
+        }
+
+        // a reversal is any trade of the user on the other side of the burst , soon enough after it
+        let reversal_window = self.config.momentum_reversal_ms as i64 * NANOS_PER_MILLI;
+        for (user, side) in [(buyer, BUY), (seller, SELL)] {
+            let key = (user, trade.symbol);
+            let reversed = self.ignitions.get(&key).is_some_and(|i| {
+                i.side != side && i.fills.last().is_some_and(|f| trade.timestamp - f.timestamp <= reversal_window)
+            });
+            if reversed {
+                let ignition = self.ignitions.remove(&key).unwrap();
+                let reversal = if side == BUY { trade.buyer_order_id } else { trade.seller_order_id };
+                let mut order_ids: Vec<u64> = ignition.fills.iter().map(|f| f.order_id).collect();
+                order_ids.dedup();
+                order_ids.push(reversal);
+                self.emit(out, &Alert {
+                    kind: "momentum_ignition",
+                    symbol: Some(trade.symbol),
+                    user_id: user,
+                    order_ids,
+                    evidence: format!(
+                        "{} aggressive {} fills moved price {:.1}bps ({} -> {}) , then {} {}@{}",
+                        ignition.fills.len(),
+                        side_str(ignition.side),
+                        ignition.move_bps,
+                        ignition.fills.first().map(|f| f.price).unwrap_or(0),
+                        ignition.fills.last().map(|f| f.price).unwrap_or(0),
+                        side_str(side),
+                        trade.quantity,
+                        trade.price
+                    ),
+                    timestamp: trade.timestamp,
+                })?;
+            }
+        }
+
+        // the maker side is the resting order , the other one took liquidity
+        let (taker, side, order_id) = if trade.is_buyer_maker {
+            (seller, SELL, trade.seller_order_id)
+        } else {
+            (buyer, BUY, trade.buyer_order_id)
+        };
+        let window = self.config.momentum_window_ms as i64 * NANOS_PER_MILLI;
+        let fills = self.fills.entry((taker, trade.symbol)).or_default();
+        // a fill in the other direction ends the burst
+        if fills.back().is_some_and(|f| f.side != side) {
+            fills.clear();
+        }
+        fills.push_back(Fill { order_id, side, price: trade.price, qty: trade.quantity, timestamp: trade.timestamp });
+        while fills.front().is_some_and(|f| trade.timestamp - f.timestamp > window) {
+            fills.pop_front();
+        }
+        if fills.len() < self.config.momentum_min_trades.max(1) {
+            return Ok(());
+        }
+        let first = fills.front().unwrap().price as f64;
+        let moved = trade.price as f64 - first;
+        let move_bps = if first > 0.0 { moved / first * 10_000.0 } else { 0.0 };
+        let with_side = if side == BUY { move_bps } else { -move_bps };
+        if with_side >= self.config.momentum_move_bps {
+            let fills: Vec<Fill> = fills.drain(..).collect();
+            self.ignitions.insert((taker, trade.symbol), Ignition { side, fills, move_bps: with_side });
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, trade: &TradeLogs) -> Option<(u64, u64)> {
+        let buyer = self.orders.get(&trade.buyer_order_id)?.user_id;
+        let seller = self.orders.get(&trade.seller_order_id)?.user_id;
+        Some((buyer, seller))
+    }
+}
+
+impl LogProcessor for Surveillance {
+    fn streams(&self) -> &'static [Stream] {
+        &[Stream::OrderLogs, Stream::TradeLogs]
+    }
+
+    fn on_order_log(&mut self, log: &OrderLogWrapper, out: &mut Buffer) -> questdb::Result<()> {
+        let delta = &log.order_delta;
+        let now = self.now;
+        let order = self.orders.entry(delta.order_id).or_insert(OrderInfo {
+            user_id: delta.user_id,
+            symbol: delta.symbol,
+            side: delta.side,
+            price: delta.price,
+            qty: 0,
+            filled: 0,
+            received_at: None,
+            last_seen: now,
+        });
+        order.last_seen = now;
+        match delta.order_event_type {
+            RECEIVED => {
+                order.qty = delta.shares_qty;
+                order.price = delta.price;
+                order.received_at = Some(log.timestamp);
+            }
+            MATCHED => order.filled = order.filled.saturating_add(delta.shares_qty),
+            CANCELED => {
+                let order = *order;
+                self.on_cancel(delta.order_id, &order, log.timestamp, out)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn on_trade_log(&mut self, log: &TradeLogs, out: &mut Buffer) -> questdb::Result<()> {
+        if !self.pending.is_empty() {
+            // keep trades in order , this one waits behind the unresolved ones
+            self.pending.push_back(PendingTrade { trade: *log, arrived: self.now });
+            return Ok(());
+        }
+        match self.resolve(log) {
+            Some((buyer, seller)) => self.check_trade(log, buyer, seller, out),
+            None => {
+                self.pending.push_back(PendingTrade { trade: *log, arrived: self.now });
+                Ok(())
+            }
+        }
+    }
+
+    fn on_tick(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        self.now = now;
+        let resolve = self.config.resolve_ms as i64 * NANOS_PER_MILLI;
+        while let Some(pending) = self.pending.front().copied() {
+            match self.resolve(&pending.trade) {
+                Some((buyer, seller)) => {
+                    self.pending.pop_front();
+                    self.check_trade(&pending.trade, buyer, seller, out)?;
+                }
+                // the orders never showed up , nothing to attribute the trade to
+                None if pending.arrived + resolve <= now => {
+                    self.pending.pop_front();
+                }
+                None => break,
+            }
+        }
+
+        if now - self.last_sweep < 1_000 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.last_sweep = now;
+        let ttl = self.config.order_ttl_ms as i64 * NANOS_PER_MILLI;
+        self.orders.retain(|_, o| o.last_seen + ttl > now);
+        // bursts and ignitions live in producer time , drop the ones older than any window by the wall clock
+        let horizon = (self.config.momentum_reversal_ms.max(self.config.momentum_window_ms) as i64) * NANOS_PER_MILLI;
+        self.ignitions.retain(|_, i| i.fills.last().is_some_and(|f| f.timestamp + horizon > now));
+        self.fills.retain(|_, f| f.back().is_some_and(|f| f.timestamp + horizon > now));
+        let layering = self.config.layering_window_ms as i64 * NANOS_PER_MILLI;
+        self.spoofs.retain(|_, s| s.back().is_some_and(|c| c.canceled_at + layering > now));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{order, trade};
+    use questdb::ingress::ProtocolVersion;
+
+    const MS: i64 = NANOS_PER_MILLI;
+
+    fn alerts(out: &Buffer) -> Vec<String> {
+        std::str::from_utf8(out.as_bytes())
+            .unwrap()
+            .lines()
+            .map(|row| row.split(",alert_type=").nth(1).unwrap().split([',', ' ']).next().unwrap().to_string())
+            .collect()
+    }
+
+    fn surveillance() -> Surveillance {
+        Surveillance::new(SurveillanceConfig { momentum_min_trades: 3, ..SurveillanceConfig::default() })
+    }
+
+    #[test]
+    fn trade_between_orders_of_one_user_is_a_wash_trade() {
+        let mut s = surveillance();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        s.on_order_log(&order(1, 5).side(BUY), &mut out).unwrap();
+        s.on_order_log(&order(2, 5).side(SELL), &mut out).unwrap();
+        s.on_order_log(&order(3, 6).side(SELL), &mut out).unwrap();
+        s.on_trade_log(&trade(1, 3).buyer_maker(true), &mut out).unwrap();
+        assert!(alerts(&out).is_empty());
+        s.on_trade_log(&trade(1, 2).buyer_maker(true), &mut out).unwrap();
+        assert_eq!(alerts(&out), ["wash_trade"]);
+    }
+
+    #[test]
+    fn trade_waits_for_its_orders() {
+        let mut s = surveillance();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        s.on_trade_log(&trade(1, 2).buyer_maker(true), &mut out).unwrap();
+        s.on_order_log(&order(1, 5).side(BUY), &mut out).unwrap();
+        s.on_order_log(&order(2, 5).side(SELL), &mut out).unwrap();
+        assert_eq!(s.pending.len(), 1);
+        s.on_tick(10 * MS, &mut out).unwrap();
+        assert!(s.pending.is_empty());
+        assert_eq!(alerts(&out), ["wash_trade"]);
+
+        // orders that never show up let the trade go after resolve_ms
+        s.on_trade_log(&trade(8, 9).buyer_maker(true), &mut out).unwrap();
+        s.on_tick(1_009 * MS, &mut out).unwrap();
+        assert_eq!(s.pending.len(), 1);
+        s.on_tick(1_010 * MS, &mut out).unwrap();
+        assert!(s.pending.is_empty());
+    }
+
+    #[test]
+    fn large_orders_canceled_quickly_are_spoofs_and_then_layering() {
+        let mut s = surveillance();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        // too slow , too small and filled orders are fine
+        s.on_order_log(&order(1, 5).side(BUY).qty(10_000), &mut out).unwrap();
+        s.on_order_log(&order(1, 5).at(3_000 * MS).side(BUY).event(CANCELED).qty(0), &mut out).unwrap();
+        s.on_order_log(&order(2, 5).side(BUY).qty(9_999), &mut out).unwrap();
+        s.on_order_log(&order(2, 5).at(MS).side(BUY).event(CANCELED).qty(0), &mut out).unwrap();
+        s.on_order_log(&order(3, 5).side(BUY).qty(10_000), &mut out).unwrap();
+        s.on_order_log(&order(3, 5).at(MS).side(BUY).event(MATCHED), &mut out).unwrap();
+        s.on_order_log(&order(3, 5).at(2 * MS).side(BUY).event(CANCELED).qty(0), &mut out).unwrap();
+        assert!(alerts(&out).is_empty());
+
+        for order_id in 10..13 {
+            s.on_order_log(&order(order_id, 5).side(BUY).qty(20_000), &mut out).unwrap();
+            s.on_order_log(&order(order_id, 5).at(500 * MS).side(BUY).event(CANCELED).qty(0), &mut out).unwrap();
+        }
+        assert_eq!(alerts(&out), ["spoofing", "spoofing", "spoofing", "layering"]);
+        assert!(std::str::from_utf8(out.as_bytes()).unwrap().contains("order_ids=\"10,11,12\""));
+    }
+
+    #[test]
+    fn aggressive_burst_followed_by_a_reversal_is_momentum_ignition() {
+        let mut s = surveillance();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        s.on_order_log(&order(1, 5).side(BUY).qty(10), &mut out).unwrap();
+        s.on_order_log(&order(2, 6).side(SELL).qty(10), &mut out).unwrap();
+        s.on_order_log(&order(3, 5).side(SELL).qty(10), &mut out).unwrap();
+        s.on_order_log(&order(4, 6).side(BUY).qty(10), &mut out).unwrap();
+
+        // user 5 lifts the offer three times , moving the price 60bps
+        for (at, price) in [(0, 10_000), (100, 10_030), (200, 10_060)] {
+            s.on_trade_log(&trade(1, 2).at(at * MS).price(price), &mut out).unwrap();
+        }
+        assert!(s.ignitions.contains_key(&(5, 7)));
+        assert!(alerts(&out).is_empty());
+
+        // and then sells into the move
+        s.on_trade_log(&trade(4, 3).at(2_000 * MS).price(10_050).buyer_maker(true), &mut out).unwrap();
+        assert_eq!(alerts(&out), ["momentum_ignition"]);
+        assert!(std::str::from_utf8(out.as_bytes()).unwrap().contains("order_ids=\"1,3\""));
+    }
+}
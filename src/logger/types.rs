@@ -1,4 +1,7 @@
 
+use serde::{Deserialize, Serialize};
+
+use crate::logger::serde_fields;
 
 // order recived 
 // order rejcted 
@@ -8,35 +11,42 @@
 // source 0->shm reader , 1 -> balance mangaer  , 2 -> matching engine
 // severity 0-> info (from components) 1-> error , 2 -> Debug 
 #[repr(C)]
-#[derive( Debug, Clone, Copy )]
+#[derive( Debug, Clone, Copy, Serialize, Deserialize )]
 pub struct OrderLogWrapper{
+    #[serde(with = "serde_fields::timestamp")]
     pub timestamp              : i64 , 
     pub order_delta            : OrderDelta,
+    #[serde(with = "serde_fields::severity")]
     pub severity               : u8 , 
 } 
 
 
 #[repr(C)]
-#[derive( Debug, Clone, Copy)]
+#[derive( Debug, Clone, Copy, Serialize, Deserialize )]
 pub struct BalanceLogWrapper{
     pub balance_delta          : BalanceDelta,
+    #[serde(with = "serde_fields::timestamp")]
     pub timestamp              : i64 , 
+    #[serde(with = "serde_fields::severity")]
     pub severity               : u8 , 
 }
 // 67 bytes 
 
 #[repr(C)]
-#[derive( Debug, Clone, Copy )]
+#[derive( Debug, Clone, Copy, Serialize, Deserialize )]
 pub struct HoldingLogWrapper{
+    #[serde(with = "serde_fields::timestamp")]
     pub timestamp               : i64 ,
     pub holding_delta           : HoldingDelta,
+    #[serde(with = "serde_fields::severity")]
     pub severity                : u8 , 
 }
 
 
 #[repr(C)]
-#[derive( Debug, Clone , Copy)]
+#[derive( Debug, Clone , Copy, Serialize, Deserialize)]
 pub struct OrderBookSnapShot{
+    #[serde(with = "serde_fields::timestamp")]
     pub timestamp              : i64 ,
     pub event_id               : u64 ,
     pub bids                   : [(u64 , u32) ; 20],
@@ -45,18 +55,19 @@ pub struct OrderBookSnapShot{
 }
 
 
-#[derive( Debug, Clone, Copy)]
+#[derive( Debug, Clone, Copy, Serialize, Deserialize )]
 pub struct BalanceDelta{
     pub event_id: u64,
     pub user_id: u64,
     pub delta_available: i64,
     pub delta_reserved: i64,
     pub order_id: u64,     // the taker order id which caused the balance updations 
+    #[serde(with = "serde_fields::reason")]
     pub reason: u8,      // reso for the balance update , either balances locked = 0 , or funds updated =1
    
 }
 
-#[derive(Debug ,Copy, Clone)]
+#[derive(Debug ,Copy, Clone, Serialize, Deserialize)]
 pub struct HoldingDelta {
     pub order_id: u64,
     pub event_id: u64,
@@ -64,12 +75,13 @@ pub struct HoldingDelta {
     pub symbol: u32,
     pub delta_available: i32,
     pub delta_reserved: i32,
+    #[serde(with = "serde_fields::reason")]
     pub reason: u8,
 }
 
 
 
-#[derive( Debug, Clone, Copy )]
+#[derive( Debug, Clone, Copy, Serialize, Deserialize )]
 pub struct OrderDelta{
     pub event_id               : u64 ,
     pub order_id               : u64 ,
@@ -77,7 +89,9 @@ pub struct OrderDelta{
     pub price                  : u64 , 
     pub symbol                 : u32 ,
     pub shares_qty             : u32 ,
+    #[serde(with = "serde_fields::side")]
     pub side                   : u8 ,
+    #[serde(with = "serde_fields::order_event_type")]
     pub order_event_type       : u8 ,   // 0 order recived at SHM reader , 1->order matched , 2 -> order canceled log 
 }
 
@@ -89,8 +103,9 @@ pub enum BaseLogs{
 }
 
 #[repr(C)]
-#[derive(Debug , Clone , Copy)]
+#[derive(Debug , Clone , Copy, Serialize, Deserialize)]
 pub struct TradeLogs{
+    #[serde(with = "serde_fields::timestamp")]
     pub timestamp       : i64 ,
     pub buyer_order_id  : u64 ,
     pub seller_order_id : u64 ,
@@ -101,3 +116,435 @@ pub struct TradeLogs{
 }
 
 
+
+// the five shm streams , named after the QuestDB table each one lands in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Stream{
+    #[serde(rename = "order_logs")]
+    OrderLogs,
+    #[serde(rename = "balance_logs")]
+    BalanceLogs,
+    #[serde(rename = "holding_logs")]
+    HoldingLogs,
+    #[serde(rename = "trade_logs")]
+    TradeLogs,
+    #[serde(rename = "orderbook_snapshots")]
+    Snapshots,
+}
+
+impl Stream{
+    pub const ALL: [Stream; 5] = [
+        Stream::OrderLogs,
+        Stream::BalanceLogs,
+        Stream::HoldingLogs,
+        Stream::TradeLogs,
+        Stream::Snapshots,
+    ];
+
+    // position in Stream::ALL , for per stream arrays
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            Stream::OrderLogs => "order_logs",
+            Stream::BalanceLogs => "balance_logs",
+            Stream::HoldingLogs => "holding_logs",
+            Stream::TradeLogs => "trade_logs",
+            Stream::Snapshots => "orderbook_snapshots",
+        }
+    }
+}
+
+// one record of any stream , tagged with the table it is stored in
+// used for the line oriented sinks ( {"stream":"trade_logs","record":{...}} )
+// kept unboxed so records stay Copy , snapshots dominate the size anyway
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "stream", content = "record")]
+pub enum LogRecord{
+    #[serde(rename = "order_logs")]
+    OrderLog(OrderLogWrapper),
+    #[serde(rename = "balance_logs")]
+    BalanceLog(BalanceLogWrapper),
+    #[serde(rename = "holding_logs")]
+    HoldingLog(HoldingLogWrapper),
+    #[serde(rename = "trade_logs")]
+    TradeLog(TradeLogs),
+    #[serde(rename = "orderbook_snapshots")]
+    Snapshot(OrderBookSnapShot),
+}
+
+impl LogRecord{
+    pub fn stream(&self) -> Stream {
+        match self {
+            LogRecord::OrderLog(_) => Stream::OrderLogs,
+            LogRecord::BalanceLog(_) => Stream::BalanceLogs,
+            LogRecord::HoldingLog(_) => Stream::HoldingLogs,
+            LogRecord::TradeLog(_) => Stream::TradeLogs,
+            LogRecord::Snapshot(_) => Stream::Snapshots,
+        }
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        match self {
+            LogRecord::OrderLog(log) => log.timestamp,
+            LogRecord::BalanceLog(log) => log.timestamp,
+            LogRecord::HoldingLog(log) => log.timestamp,
+            LogRecord::TradeLog(log) => log.timestamp,
+            LogRecord::Snapshot(snap) => snap.timestamp,
+        }
+    }
+
+    // balance deltas are not tied to an instrument
+    pub fn symbol(&self) -> Option<u32> {
+        match self {
+            LogRecord::OrderLog(log) => Some(log.order_delta.symbol),
+            LogRecord::BalanceLog(_) => None,
+            LogRecord::HoldingLog(log) => Some(log.holding_delta.symbol),
+            LogRecord::TradeLog(log) => Some(log.symbol),
+            LogRecord::Snapshot(snap) => Some(snap.symbol),
+        }
+    }
+
+    // trades only carry order ids , snapshots nothing user related
+    pub fn user_id(&self) -> Option<u64> {
+        match self {
+            LogRecord::OrderLog(log) => Some(log.order_delta.user_id),
+            LogRecord::BalanceLog(log) => Some(log.balance_delta.user_id),
+            LogRecord::HoldingLog(log) => Some(log.holding_delta.user_id),
+            LogRecord::TradeLog(_) | LogRecord::Snapshot(_) => None,
+        }
+    }
+
+    // trades touch two orders , the seller's only shows up when it differs from the buyer's
+    pub fn order_ids(&self) -> [Option<u64>; 2] {
+        match self {
+            LogRecord::OrderLog(log) => [Some(log.order_delta.order_id), None],
+            LogRecord::BalanceLog(log) => [Some(log.balance_delta.order_id), None],
+            LogRecord::HoldingLog(log) => [Some(log.holding_delta.order_id), None],
+            LogRecord::TradeLog(log) => [
+                Some(log.buyer_order_id),
+                (log.seller_order_id != log.buyer_order_id).then_some(log.seller_order_id),
+            ],
+            LogRecord::Snapshot(_) => [None, None],
+        }
+    }
+
+    pub fn severity(&self) -> Option<u8> {
+        match self {
+            LogRecord::OrderLog(log) => Some(log.severity),
+            LogRecord::BalanceLog(log) => Some(log.severity),
+            LogRecord::HoldingLog(log) => Some(log.severity),
+            LogRecord::TradeLog(_) | LogRecord::Snapshot(_) => None,
+        }
+    }
+}
+
+// a record as handed from the poller to the flushers , stamped when it left shm
+#[derive(Debug, Clone, Copy)]
+pub struct Ingested<T>{
+    pub record      : T,
+    pub dequeued_at : i64,
+}
+
+impl<T> Ingested<T>{
+    #[inline(always)]
+    pub fn new(record: T) -> Self {
+        Self { record, dequeued_at: crate::logger::processor::now_nanos() }
+    }
+}
+
+// decoded names for the raw u8 enums , shared by QuestDB encoding and the file sinks
+// the *_name ones know only the real values , None lets the file sinks keep the number
+#[inline(always)]
+pub fn side_name(side: u8) -> Option<&'static str> {
+    match side {
+        0 => Some("bid"),
+        1 => Some("ask"),
+        _ => None,
+    }
+}
+
+#[inline(always)]
+pub fn order_event_type_name(order_event_type: u8) -> Option<&'static str> {
+    match order_event_type {
+        0 => Some("received"),
+        1 => Some("matched"),
+        2 => Some("canceled"),
+        _ => None,
+    }
+}
+
+#[inline(always)]
+pub fn severity_name(severity: u8) -> Option<&'static str> {
+    match severity {
+        0 => Some("info"),
+        1 => Some("error"),
+        2 => Some("debug"),
+        _ => None,
+    }
+}
+
+#[inline(always)]
+pub fn reason_name(reason: u8) -> Option<&'static str> {
+    match reason {
+        0 => Some("lock"),
+        1 => Some("update"),
+        _ => None,
+    }
+}
+
+#[inline(always)]
+pub fn side_str(side: u8) -> &'static str {
+    if side == 0 { "bid" } else { "ask" }
+}
+
+#[inline(always)]
+pub fn order_event_type_str(order_event_type: u8) -> &'static str {
+    order_event_type_name(order_event_type).unwrap_or("unknown")
+}
+
+#[inline(always)]
+pub fn severity_str(severity: u8) -> &'static str {
+    severity_name(severity).unwrap_or("unknown")
+}
+
+#[inline(always)]
+pub fn reason_str(reason: u8) -> &'static str {
+    if reason == 0 { "lock" } else { "update" }
+}
+
+// defaulted records for tests , each test sets only the fields it is about
+// order 1 of user 1 , symbol 7 , price 100 , quantity 1 , everything else zero
+#[cfg(test)]
+pub mod test_records {
+    use super::*;
+
+    pub fn order(order_id: u64, user_id: u64) -> OrderLogWrapper {
+        OrderLogWrapper {
+            timestamp: 0,
+            order_delta: OrderDelta {
+                event_id: 0,
+                order_id,
+                user_id,
+                price: 100,
+                symbol: 7,
+                shares_qty: 1,
+                side: 0,
+                order_event_type: 0,
+            },
+            severity: 0,
+        }
+    }
+
+    pub fn balance(order_id: u64, user_id: u64) -> BalanceLogWrapper {
+        BalanceLogWrapper {
+            balance_delta: BalanceDelta {
+                event_id: 0,
+                user_id,
+                delta_available: 0,
+                delta_reserved: 0,
+                order_id,
+                reason: 0,
+            },
+            timestamp: 0,
+            severity: 0,
+        }
+    }
+
+    pub fn holding(order_id: u64, user_id: u64) -> HoldingLogWrapper {
+        HoldingLogWrapper {
+            timestamp: 0,
+            holding_delta: HoldingDelta {
+                order_id,
+                event_id: 0,
+                user_id,
+                symbol: 7,
+                delta_available: 0,
+                delta_reserved: 0,
+                reason: 0,
+            },
+            severity: 0,
+        }
+    }
+
+    pub fn trade(buyer_order_id: u64, seller_order_id: u64) -> TradeLogs {
+        TradeLogs {
+            timestamp: 0,
+            buyer_order_id,
+            seller_order_id,
+            price: 100,
+            symbol: 7,
+            quantity: 1,
+            is_buyer_maker: false,
+        }
+    }
+
+    // levels go to the front of each side as given , the rest stays empty
+    pub fn snapshot(bids: &[(u64, u32)], asks: &[(u64, u32)]) -> OrderBookSnapShot {
+        let mut snap =
+            OrderBookSnapShot { timestamp: 0, event_id: 0, bids: [(0, 0); 20], asks: [(0, 0); 20], symbol: 7 };
+        snap.bids[..bids.len()].copy_from_slice(bids);
+        snap.asks[..asks.len()].copy_from_slice(asks);
+        snap
+    }
+
+    impl OrderLogWrapper {
+        pub fn at(mut self, timestamp: i64) -> Self {
+            self.timestamp = timestamp;
+            self
+        }
+
+        pub fn event(mut self, order_event_type: u8) -> Self {
+            self.order_delta.order_event_type = order_event_type;
+            self
+        }
+
+        pub fn side(mut self, side: u8) -> Self {
+            self.order_delta.side = side;
+            self
+        }
+
+        pub fn qty(mut self, shares_qty: u32) -> Self {
+            self.order_delta.shares_qty = shares_qty;
+            self
+        }
+
+        pub fn price(mut self, price: u64) -> Self {
+            self.order_delta.price = price;
+            self
+        }
+
+        pub fn symbol(mut self, symbol: u32) -> Self {
+            self.order_delta.symbol = symbol;
+            self
+        }
+
+        pub fn severity(mut self, severity: u8) -> Self {
+            self.severity = severity;
+            self
+        }
+    }
+
+    impl BalanceLogWrapper {
+        pub fn at(mut self, timestamp: i64) -> Self {
+            self.timestamp = timestamp;
+            self
+        }
+
+        pub fn deltas(mut self, delta_available: i64, delta_reserved: i64) -> Self {
+            self.balance_delta.delta_available = delta_available;
+            self.balance_delta.delta_reserved = delta_reserved;
+            self
+        }
+
+        pub fn reason(mut self, reason: u8) -> Self {
+            self.balance_delta.reason = reason;
+            self
+        }
+
+        pub fn severity(mut self, severity: u8) -> Self {
+            self.severity = severity;
+            self
+        }
+    }
+
+    impl HoldingLogWrapper {
+        pub fn at(mut self, timestamp: i64) -> Self {
+            self.timestamp = timestamp;
+            self
+        }
+
+        pub fn deltas(mut self, delta_available: i32, delta_reserved: i32) -> Self {
+            self.holding_delta.delta_available = delta_available;
+            self.holding_delta.delta_reserved = delta_reserved;
+            self
+        }
+
+        pub fn reason(mut self, reason: u8) -> Self {
+            self.holding_delta.reason = reason;
+            self
+        }
+
+        pub fn symbol(mut self, symbol: u32) -> Self {
+            self.holding_delta.symbol = symbol;
+            self
+        }
+    }
+
+    impl TradeLogs {
+        pub fn at(mut self, timestamp: i64) -> Self {
+            self.timestamp = timestamp;
+            self
+        }
+
+        pub fn price(mut self, price: u64) -> Self {
+            self.price = price;
+            self
+        }
+
+        pub fn qty(mut self, quantity: u32) -> Self {
+            self.quantity = quantity;
+            self
+        }
+
+        pub fn symbol(mut self, symbol: u32) -> Self {
+            self.symbol = symbol;
+            self
+        }
+
+        pub fn buyer_maker(mut self, is_buyer_maker: bool) -> Self {
+            self.is_buyer_maker = is_buyer_maker;
+            self
+        }
+    }
+
+    impl OrderBookSnapShot {
+        pub fn at(mut self, timestamp: i64) -> Self {
+            self.timestamp = timestamp;
+            self
+        }
+
+        pub fn event_id(mut self, event_id: u64) -> Self {
+            self.event_id = event_id;
+            self
+        }
+
+        pub fn symbol(mut self, symbol: u32) -> Self {
+            self.symbol = symbol;
+            self
+        }
+    }
+
+    impl From<OrderLogWrapper> for LogRecord {
+        fn from(log: OrderLogWrapper) -> Self {
+            LogRecord::OrderLog(log)
+        }
+    }
+
+    impl From<BalanceLogWrapper> for LogRecord {
+        fn from(log: BalanceLogWrapper) -> Self {
+            LogRecord::BalanceLog(log)
+        }
+    }
+
+    impl From<HoldingLogWrapper> for LogRecord {
+        fn from(log: HoldingLogWrapper) -> Self {
+            LogRecord::HoldingLog(log)
+        }
+    }
+
+    impl From<TradeLogs> for LogRecord {
+        fn from(log: TradeLogs) -> Self {
+            LogRecord::TradeLog(log)
+        }
+    }
+
+    impl From<OrderBookSnapShot> for LogRecord {
+        fn from(snap: OrderBookSnapShot) -> Self {
+            LogRecord::Snapshot(snap)
+        }
+    }
+}
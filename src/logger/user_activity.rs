@@ -0,0 +1,413 @@
+use std::collections::{HashMap, VecDeque};
+
+use questdb::ingress::{Buffer, TimestampNanos};
+use serde::Deserialize;
+
+use crate::logger::processor::{LogProcessor, NANOS_PER_MILLI};
+use crate::logger::surveillance::{write_alert, Alert, PendingTrade};
+use crate::logger::types::{OrderLogWrapper, Stream, TradeLogs};
+
+const RECEIVED: u8 = 0;
+const MATCHED: u8 = 1;
+const CANCELED: u8 = 2;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UserActivityConfig {
+    // statistics cover the last window_ms , a user_activity row per active user every report_ms
+    pub window_ms: u64,
+    pub report_ms: u64,
+    pub bucket_ms: u64,
+    // order -> user mapping used to attribute trades
+    pub order_ttl_ms: u64,
+    // trades whose orders are not known yet wait this long for the order logs ,
+    // after that they count for whichever side is known
+    pub resolve_ms: u64,
+    // ratios are not checked for users with fewer orders in the window
+    pub min_orders: u64,
+    pub max_order_to_trade: Option<f64>,
+    pub max_cancel_to_order: Option<f64>,
+    // orders + cancels per second
+    pub max_message_rate: Option<f64>,
+}
+
+impl Default for UserActivityConfig {
+    fn default() -> Self {
+        Self {
+            window_ms: 60_000,
+            report_ms: 10_000,
+            bucket_ms: 1_000,
+            order_ttl_ms: 600_000,
+            resolve_ms: 1_000,
+            min_orders: 100,
+            max_order_to_trade: None,
+            max_cancel_to_order: None,
+            max_message_rate: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ActivityBucket {
+    pub start: i64,
+    pub orders: u64,
+    pub cancels: u64,
+    pub fills: u64,
+    pub trades: u64,
+    pub volume: u64,
+}
+
+impl ActivityBucket {
+    fn add(&mut self, other: &ActivityBucket) {
+        self.orders += other.orders;
+        self.cancels += other.cancels;
+        self.fills += other.fills;
+        self.trades += other.trades;
+        self.volume += other.volume;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    OrderToTrade,
+    CancelToOrder,
+    MessageRate,
+}
+
+impl Limit {
+    pub const ALL: [Limit; 3] = [Limit::OrderToTrade, Limit::CancelToOrder, Limit::MessageRate];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Limit::OrderToTrade => "order_to_trade_limit",
+            Limit::CancelToOrder => "cancel_to_order_limit",
+            Limit::MessageRate => "message_rate_limit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UserWindow {
+    pub buckets: VecDeque<ActivityBucket>,
+    // limits currently exceeded , an alert is raised when one is crossed
+    pub breached: [bool; 3],
+}
+
+// totals of one user's window and the ratios derived from them
+#[derive(Debug, Clone, Copy)]
+pub struct ActivityStats {
+    pub totals: ActivityBucket,
+    pub order_to_trade: Option<f64>,
+    pub cancel_to_order: Option<f64>,
+    pub message_rate: f64,
+}
+
+impl ActivityStats {
+    fn new(totals: ActivityBucket, window_ms: u64) -> Self {
+        Self {
+            totals,
+            order_to_trade: (totals.trades > 0).then(|| totals.orders as f64 / totals.trades as f64),
+            cancel_to_order: (totals.orders > 0).then(|| totals.cancels as f64 / totals.orders as f64),
+            message_rate: (totals.orders + totals.cancels) as f64 * 1_000.0 / window_ms.max(1) as f64,
+        }
+    }
+
+    fn value(&self, limit: Limit) -> Option<f64> {
+        match limit {
+            // without a single trade every order counts against the ratio
+            Limit::OrderToTrade => Some(self.order_to_trade.unwrap_or(self.totals.orders as f64)),
+            Limit::CancelToOrder => self.cancel_to_order,
+            Limit::MessageRate => Some(self.message_rate),
+        }
+    }
+}
+
+pub struct UserActivity {
+    pub config: UserActivityConfig,
+    pub users: HashMap<u64, UserWindow>,
+    // order_id -> (user_id , last seen)
+    pub orders: HashMap<u64, (u64, i64)>,
+    // trades that arrived before the order logs of their orders , oldest first
+    pub pending: VecDeque<PendingTrade>,
+    pub now: i64,
+    pub last_report: i64,
+    pub alerts: u64,
+}
+
+impl UserActivity {
+    pub fn new(config: UserActivityConfig) -> Self {
+        Self {
+            config,
+            users: HashMap::new(),
+            orders: HashMap::new(),
+            pending: VecDeque::new(),
+            now: 0,
+            last_report: 0,
+            alerts: 0,
+        }
+    }
+
+    fn bucket(&mut self, user_id: u64) -> &mut ActivityBucket {
+        let size = self.config.bucket_ms.max(1) as i64 * NANOS_PER_MILLI;
+        let start = self.now - self.now.rem_euclid(size);
+        let buckets = &mut self.users.entry(user_id).or_default().buckets;
+        if buckets.back().is_none_or(|b| b.start != start) {
+            buckets.push_back(ActivityBucket { start, ..Default::default() });
+        }
+        buckets.back_mut().unwrap()
+    }
+
+    fn user(&self, order_id: u64) -> Option<u64> {
+        self.orders.get(&order_id).map(|(user, _)| *user)
+    }
+
+    // the flusher hands over a batch of trades before the order logs of the same loop ,
+    // so a taker's trade usually shows up before the order that caused it
+    fn resolved(&self, trade: &TradeLogs) -> bool {
+        self.user(trade.buyer_order_id).is_some() && self.user(trade.seller_order_id).is_some()
+    }
+
+    fn count_trade(&mut self, trade: &TradeLogs) {
+        // a self trade counts once for its user
+        let buyer = self.user(trade.buyer_order_id);
+        let seller = self.user(trade.seller_order_id);
+        let users = if buyer == seller { [buyer, None] } else { [buyer, seller] };
+        for user_id in users.into_iter().flatten() {
+            let bucket = self.bucket(user_id);
+            bucket.trades += 1;
+            bucket.volume += trade.quantity as u64;
+        }
+    }
+
+    fn report(&mut self, out: &mut Buffer) -> questdb::Result<()> {
+        let now = self.now;
+        let cutoff = now - self.config.window_ms as i64 * NANOS_PER_MILLI;
+        let window_ms = self.config.window_ms;
+        let min_orders = self.config.min_orders;
+        // in Limit::ALL order
+        let limits = [self.config.max_order_to_trade, self.config.max_cancel_to_order, self.config.max_message_rate];
+        let mut alerts = Vec::new();
+
+        for (user_id, window) in self.users.iter_mut() {
+            while window.buckets.front().is_some_and(|b| b.start < cutoff) {
+                window.buckets.pop_front();
+            }
+            if window.buckets.is_empty() {
+                continue;
+            }
+            let mut totals = ActivityBucket::default();
+            for bucket in &window.buckets {
+                totals.add(bucket);
+            }
+            let stats = ActivityStats::new(totals, window_ms);
+
+            out.table("user_activity")?
+                .column_i64("user_id", *user_id as i64)?
+                .column_i64("orders", totals.orders as i64)?
+                .column_i64("cancels", totals.cancels as i64)?
+                .column_i64("fills", totals.fills as i64)?
+                .column_i64("trades", totals.trades as i64)?
+                .column_i64("volume", totals.volume as i64)?
+                .column_f64("message_rate", stats.message_rate)?;
+            if let Some(ratio) = stats.order_to_trade {
+                out.column_f64("order_to_trade", ratio)?;
+            }
+            if let Some(ratio) = stats.cancel_to_order {
+                out.column_f64("cancel_to_order", ratio)?;
+            }
+            out.column_i64("window_ms", window_ms as i64)?.at(TimestampNanos::new(now))?;
+
+            for (idx, limit) in Limit::ALL.iter().enumerate() {
+                let Some(max) = limits[idx] else {
+                    continue;
+                };
+                let ratio = *limit != Limit::MessageRate;
+                let breached = stats.value(*limit).is_some_and(|v| v > max)
+                    && (!ratio || totals.orders >= min_orders);
+                if breached && !window.breached[idx] {
+                    alerts.push(Alert {
+                        kind: limit.as_str(),
+                        symbol: None,
+                        user_id: *user_id,
+                        order_ids: Vec::new(),
+                        evidence: format!(
+                            "{:.2} over the limit of {:.2} ({} orders , {} cancels , {} trades in {}ms)",
+                            stats.value(*limit).unwrap_or(0.0), max, totals.orders, totals.cancels, totals.trades, window_ms
+                        ),
+                        timestamp: now,
+                    });
+                }
+                window.breached[idx] = breached;
+            }
+        }
+        self.users.retain(|_, w| !w.buckets.is_empty());
+
+        for alert in &alerts {
+            self.alerts += 1;
+            write_alert(out, "user_activity_alerts", alert)?;
+        }
+        Ok(())
+    }
+}
+
+impl LogProcessor for UserActivity {
+    fn streams(&self) -> &'static [Stream] {
+        &[Stream::OrderLogs, Stream::TradeLogs]
+    }
+
+    fn on_order_log(&mut self, log: &OrderLogWrapper, _out: &mut Buffer) -> questdb::Result<()> {
+        let delta = &log.order_delta;
+        self.orders.insert(delta.order_id, (delta.user_id, self.now));
+        let bucket = self.bucket(delta.user_id);
+        match delta.order_event_type {
+            RECEIVED => bucket.orders += 1,
+            MATCHED => bucket.fills += 1,
+            CANCELED => bucket.cancels += 1,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn on_trade_log(&mut self, log: &TradeLogs, _out: &mut Buffer) -> questdb::Result<()> {
+        // keep trades in order , this one waits behind the unresolved ones
+        if !self.pending.is_empty() || !self.resolved(log) {
+            self.pending.push_back(PendingTrade { trade: *log, arrived: self.now });
+            return Ok(());
+        }
+        self.count_trade(log);
+        Ok(())
+    }
+
+    fn on_tick(&mut self, now: i64, out: &mut Buffer) -> questdb::Result<()> {
+        self.now = now;
+        let resolve = self.config.resolve_ms as i64 * NANOS_PER_MILLI;
+        while let Some(pending) = self.pending.front().copied() {
+            if !self.resolved(&pending.trade) && pending.arrived + resolve > now {
+                break;
+            }
+            self.pending.pop_front();
+            self.count_trade(&pending.trade);
+        }
+        if now - self.last_report < self.config.report_ms as i64 * NANOS_PER_MILLI {
+            return Ok(());
+        }
+        self.last_report = now;
+        let ttl = self.config.order_ttl_ms as i64 * NANOS_PER_MILLI;
+        self.orders.retain(|_, (_, seen)| *seen + ttl > now);
+        self.report(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{order, trade};
+    use questdb::ingress::ProtocolVersion;
+
+    const SEC: i64 = 1_000 * NANOS_PER_MILLI;
+
+    fn activity() -> UserActivity {
+        UserActivity::new(UserActivityConfig {
+            window_ms: 10_000,
+            report_ms: 1_000,
+            min_orders: 2,
+            max_cancel_to_order: Some(0.5),
+            ..UserActivityConfig::default()
+        })
+    }
+
+    fn totals(activity: &UserActivity, user_id: u64) -> ActivityBucket {
+        let mut totals = ActivityBucket::default();
+        for bucket in &activity.users[&user_id].buckets {
+            totals.add(bucket);
+        }
+        totals
+    }
+
+    fn alerts(out: &Buffer) -> Vec<String> {
+        std::str::from_utf8(out.as_bytes())
+            .unwrap()
+            .lines()
+            .filter_map(|row| row.strip_prefix("user_activity_alerts,alert_type="))
+            .map(|row| row.split([',', ' ']).next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn trade_before_its_orders_is_counted_once_they_arrive() {
+        let mut activity = activity();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        activity.on_tick(SEC, &mut out).unwrap();
+        activity.on_trade_log(&trade(1, 2).qty(5).buyer_maker(true), &mut out).unwrap();
+        activity.on_order_log(&order(1, 10), &mut out).unwrap();
+        activity.on_order_log(&order(2, 20), &mut out).unwrap();
+        // a later trade of known orders still waits behind the first one
+        activity.on_trade_log(&trade(1, 2).qty(3).buyer_maker(true), &mut out).unwrap();
+        assert_eq!(activity.pending.len(), 2);
+
+        activity.on_tick(SEC + 1, &mut out).unwrap();
+        assert!(activity.pending.is_empty());
+        for user in [10, 20] {
+            let totals = totals(&activity, user);
+            assert_eq!((totals.trades, totals.volume), (2, 8));
+        }
+    }
+
+    #[test]
+    fn unresolved_trade_counts_for_the_known_side_after_resolve_ms() {
+        let mut activity = activity();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        activity.on_tick(SEC, &mut out).unwrap();
+        activity.on_order_log(&order(1, 10), &mut out).unwrap();
+        activity.on_trade_log(&trade(1, 2).qty(5).buyer_maker(true), &mut out).unwrap();
+        activity.on_tick(2 * SEC - 1, &mut out).unwrap();
+        assert_eq!(activity.pending.len(), 1);
+        activity.on_tick(2 * SEC, &mut out).unwrap();
+        assert!(activity.pending.is_empty());
+        assert_eq!(totals(&activity, 10).trades, 1);
+        assert_eq!(activity.users.len(), 1);
+    }
+
+    #[test]
+    fn self_trade_counts_once() {
+        let mut activity = activity();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        activity.on_order_log(&order(1, 10), &mut out).unwrap();
+        activity.on_order_log(&order(2, 10), &mut out).unwrap();
+        activity.on_trade_log(&trade(1, 2).qty(5).buyer_maker(true), &mut out).unwrap();
+        assert_eq!(totals(&activity, 10).trades, 1);
+    }
+
+    #[test]
+    fn limit_alerts_once_per_crossing() {
+        let mut activity = activity();
+        let mut out = Buffer::new(ProtocolVersion::V1);
+        activity.on_tick(SEC, &mut out).unwrap();
+        for order_id in 1..=4 {
+            activity.on_order_log(&order(order_id, 10), &mut out).unwrap();
+        }
+        for order_id in 1..=3 {
+            activity.on_order_log(&order(order_id, 10).event(CANCELED), &mut out).unwrap();
+        }
+        activity.on_tick(2 * SEC, &mut out).unwrap();
+        assert_eq!(alerts(&out), ["cancel_to_order_limit"]);
+        let row = std::str::from_utf8(out.as_bytes()).unwrap().lines().next().unwrap().to_string();
+        assert!(row.starts_with("user_activity user_id=10i,orders=4i,cancels=3i,"));
+
+        // still over the limit , no second alert
+        activity.on_tick(3 * SEC, &mut out).unwrap();
+        assert_eq!(alerts(&out).len(), 1);
+
+        // back under it and over again
+        for order_id in 5..=8 {
+            activity.on_order_log(&order(order_id, 10), &mut out).unwrap();
+        }
+        activity.on_tick(4 * SEC, &mut out).unwrap();
+        assert!(!activity.users[&10].breached[1]);
+        for order_id in 5..=8 {
+            activity.on_order_log(&order(order_id, 10).event(CANCELED), &mut out).unwrap();
+        }
+        activity.on_tick(5 * SEC, &mut out).unwrap();
+        assert_eq!(alerts(&out).len(), 2);
+    }
+}
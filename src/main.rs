@@ -1,27 +1,208 @@
-use logger::{logger::{log_flusher::LogFlusher, types::{BalanceLogWrapper, HoldingLogWrapper, OrderBookSnapShot, OrderLogWrapper, TradeLogs}}, shm::{balance_logs::BalanceLogQueue, holdings_logs::HoldingLogQueue, order_logs::OrderLogQueue, poller::LogPoller}};
+use std::path::PathBuf;
+
+use ed25519_dalek::SigningKey;
+use logger::{config::LoggerConfig, logger::{audit_journal::{load_or_create_key, AuditJournal, JournalConfig}, rules::Rules, order_latency::OrderLatency, user_activity::UserActivity, surveillance::Surveillance, market_quality::MarketQuality, correlator::Correlator, fix_sink::FixSink, holdings_recon::HoldingsRecon, balance_ledger::BalanceLedger, order_state::OrderStateTracker, candles::CandleAggregator, jsonl_sink::{JsonlConfig, JsonlSink}, log_flusher::{FlusherGroupConfig, FlusherInputs, LogFlusher}, parquet_sink::ParquetSink, processor::LogProcessor, types::{BalanceLogWrapper, HoldingLogWrapper, Ingested, OrderBookSnapShot, OrderLogWrapper, Stream, TradeLogs}}, server::{history, hub::Hub, tail, ws}, shm::{poller::LogPoller, sequence::{EventGap, SharedCounters}}};
+
+// one journal file per flusher when there are several
+fn journal_config(journal: &JournalConfig, group: &FlusherGroupConfig, groups: usize) -> JournalConfig {
+    let mut journal = journal.clone();
+    if groups > 1 {
+        journal.prefix = format!("{}-{}", journal.prefix, group.name);
+    }
+    journal
+}
+
+// every flusher gets its own sink instances , so no file is shared between threads
+fn add_sinks(flusher: &mut LogFlusher, config: &LoggerConfig, group: &FlusherGroupConfig, groups: usize, journal_key: Option<&SigningKey>){
+    if let Some(parquet) = &config.parquet {
+        flusher.add_sink(Box::new(ParquetSink::new(parquet.clone())));
+    }
+    if let Some(jsonl) = &config.jsonl {
+        let mut jsonl: JsonlConfig = jsonl.clone();
+        if groups > 1 {
+            jsonl.prefix = format!("{}-{}", jsonl.prefix, group.name);
+        }
+        flusher.add_sink(Box::new(JsonlSink::new(jsonl)));
+    }
+    if let (Some(journal), Some(key)) = (&config.journal, journal_key) {
+        match AuditJournal::new(journal_config(journal, group, groups), key.clone()) {
+            Ok(journal) => flusher.add_sink(Box::new(journal)),
+            Err(e) => eprintln!("audit journal disabled for flusher {}: {}", group.name, e),
+        }
+    }
+    // fills need the order sizes , so both streams have to come through this flusher
+    if let Some(fix) = &config.fix {
+        let owns = |stream: Stream| group.streams.contains(&stream);
+        if owns(Stream::OrderLogs) && owns(Stream::TradeLogs) {
+            flusher.add_sink(Box::new(FixSink::new(fix.clone())));
+        } else if owns(Stream::TradeLogs) {
+            eprintln!("fix export disabled , flusher {} owns trade_logs but not order_logs", group.name);
+        }
+    }
+}
+
+fn build_processors(config: &LoggerConfig, groups: &[FlusherGroupConfig]) -> Vec<Box<dyn LogProcessor>>{
+    let mut processors: Vec<Box<dyn LogProcessor>> = Vec::new();
+    if let Some(candles) = &config.candles {
+        processors.push(Box::new(CandleAggregator::new(candles.clone())));
+    }
+    if let Some(order_state) = &config.order_state {
+        processors.push(Box::new(OrderStateTracker::new(order_state.clone())));
+    }
+    if let Some(balance_ledger) = &config.balance_ledger {
+        match BalanceLedger::new(balance_ledger.clone()) {
+            Ok(ledger) => processors.push(Box::new(ledger)),
+            Err(e) => eprintln!("balance ledger: {} , the ledger is disabled", e),
+        }
+    }
+    if let Some(holdings_recon) = &config.holdings_recon {
+        processors.push(Box::new(HoldingsRecon::new(holdings_recon.clone())));
+    }
+    if let Some(correlator) = &config.correlator {
+        processors.push(Box::new(Correlator::new(correlator.clone())));
+    }
+    if let Some(market_quality) = &config.market_quality {
+        processors.push(Box::new(MarketQuality::new(market_quality.clone())));
+    }
+    if let Some(surveillance) = &config.surveillance {
+        processors.push(Box::new(Surveillance::new(surveillance.clone())));
+    }
+    if let Some(user_activity) = &config.user_activity {
+        processors.push(Box::new(UserActivity::new(user_activity.clone())));
+    }
+    if let Some(order_latency) = &config.order_latency {
+        processors.push(Box::new(OrderLatency::new(order_latency.clone())));
+    }
+    if let Some(rules) = &config.rules {
+        let owned: Vec<Vec<Stream>> = groups.iter().map(|g| g.streams.clone()).collect();
+        for rules in Rules::per_group(rules.clone(), &owned) {
+            processors.push(Box::new(rules));
+        }
+    }
+    processors
+}
 
 fn main(){
 
-    let (order_log_sender , order_log_receiver) = crossbeam::channel::bounded::<OrderLogWrapper>(32768);
-    let (balance_log_sender , balance_log_receiver) = crossbeam::channel::bounded::<BalanceLogWrapper>(32768);
-    let (holding_log_sender , holding_log_receiver) = crossbeam::channel::bounded::<HoldingLogWrapper>(32768);
-    let (trade_log_sender , trade_log_receiver) = crossbeam::channel::bounded::<TradeLogs>(32768);
-    let (snapshot_sender , snapshot_receiver)= crossbeam::channel::bounded::<OrderBookSnapShot>(32768);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        if let Err(e) = logger::query::run(command, &args[1..]) {
+            eprintln!("{}: {}", command, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let config = LoggerConfig::load();
+    let groups = config.flusher_groups();
 
+    let (order_log_sender , order_log_receiver) = crossbeam::channel::bounded::<Ingested<OrderLogWrapper>>(32768);
+    let (balance_log_sender , balance_log_receiver) = crossbeam::channel::bounded::<Ingested<BalanceLogWrapper>>(32768);
+    let (holding_log_sender , holding_log_receiver) = crossbeam::channel::bounded::<Ingested<HoldingLogWrapper>>(32768);
+    let (trade_log_sender , trade_log_receiver) = crossbeam::channel::bounded::<Ingested<TradeLogs>>(32768);
+    let (snapshot_sender , snapshot_receiver)= crossbeam::channel::bounded::<Ingested<OrderBookSnapShot>>(32768);
+    let (gap_sender , gap_receiver) = crossbeam::channel::bounded::<EventGap>(4096);
+    let poller_counters = SharedCounters::default();
 
+
+    // records reach the live consumers from the poller , before any flusher sees them
+    let hub = (config.tail.is_some() || config.ws.is_some() || config.history.is_some()).then(Hub::new);
+    if let (Some(tail_config), Some(hub)) = (&config.tail, &hub) {
+        tail::start(tail_config.clone(), hub.clone());
+    }
+    if let (Some(ws_config), Some(hub)) = (&config.ws, &hub) {
+        ws::start(ws_config.clone(), hub.clone());
+    }
+    if let (Some(history_config), Some(hub)) = (&config.history, &hub) {
+        history::start(history_config.clone(), hub.clone());
+    }
+
+    let poller_core = config.poller_core;
+    let sequence_config = config.sequence.clone();
+    let counters = poller_counters.clone();
     let poller_handle = std::thread::spawn(move||{
-        core_affinity::set_for_current(core_affinity::CoreId { id: 1 });
-       let mut poller = LogPoller::new(order_log_sender, balance_log_sender, holding_log_sender , trade_log_sender , snapshot_sender);
+        if let Some(id) = poller_core {
+            core_affinity::set_for_current(core_affinity::CoreId { id });
+        }
+       let mut poller = LogPoller::new(order_log_sender, balance_log_sender, holding_log_sender , trade_log_sender , snapshot_sender, gap_sender, sequence_config);
+       poller.set_counters(counters);
+       if let Some(hub) = hub {
+           poller.set_hub(hub);
+       }
        poller.run_poller();
     });
 
-    let flusher_handle = std::thread::spawn(move ||{
-        core_affinity::set_for_current(core_affinity::CoreId { id: 4 });
-        let mut flusher = LogFlusher::new(order_log_receiver, balance_log_receiver, holding_log_receiver , trade_log_receiver , snapshot_receiver);
-        flusher.run();
+    let mut inputs: Vec<FlusherInputs> = groups.iter().map(|_| FlusherInputs::default()).collect();
+    let owner = |stream: Stream| groups.iter().position(|g| g.streams.contains(&stream)).unwrap();
+    inputs[owner(Stream::OrderLogs)].order_logs = Some(order_log_receiver);
+    inputs[owner(Stream::BalanceLogs)].balance_logs = Some(balance_log_receiver);
+    inputs[owner(Stream::HoldingLogs)].holding_logs = Some(holding_log_receiver);
+    inputs[owner(Stream::TradeLogs)].trade_logs = Some(trade_log_receiver);
+    inputs[owner(Stream::Snapshots)].snapshots = Some(snapshot_receiver);
+    inputs[0].event_gaps = Some(gap_receiver);
+    inputs[0].poller_counters = Some(poller_counters);
+
+    // a processor runs in the first flusher that owns all of its streams
+    let mut processors = build_processors(&config, &groups);
+    let mut group_processors: Vec<Vec<Box<dyn LogProcessor>>> = groups.iter().map(|_| Vec::new()).collect();
+    for processor in processors.drain(..) {
+        match groups.iter().position(|g| processor.streams().iter().all(|s| g.streams.contains(s))) {
+            Some(idx) => group_processors[idx].push(processor),
+            None => eprintln!(
+                "no flusher owns all of {:?} , derived tables for them are disabled",
+                processor.streams().iter().map(|s| s.table()).collect::<Vec<_>>()
+            ),
+        }
+    }
+
+    let group_count = groups.len();
+    // loaded once here , flushers creating it themselves would race and sign with different keys
+    let journal_key = config.journal.as_ref().and_then(|journal| {
+        let paths: Vec<PathBuf> = groups.iter().map(|g| journal_config(journal, g, group_count).path()).collect();
+        match load_or_create_key(&journal.key_path, &paths) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                eprintln!("audit journal disabled: {}", e);
+                None
+            }
+        }
     });
+    let mut flusher_handles = Vec::new();
+    for ((group, inputs), processors) in groups.into_iter().zip(inputs).zip(group_processors) {
+        let config = config.clone();
+        let journal_key = journal_key.clone();
+        flusher_handles.push(std::thread::spawn(move ||{
+            if let Some(id) = group.core {
+                core_affinity::set_for_current(core_affinity::CoreId { id });
+            }
+            let mut flusher = LogFlusher::new(&group.name, inputs, &config.flush_policies);
+            add_sinks(&mut flusher, &config, &group, group_count, journal_key.as_ref());
+            flusher.set_derived_policy(config.derived_flush_policy);
+            if let Some(conflation) = &config.conflation {
+                if group.streams.contains(&Stream::Snapshots) && !group.streams.contains(&Stream::TradeLogs) {
+                    eprintln!(
+                        "conflation: flusher {} owns orderbook_snapshots but not trade_logs , snapshots right after a trade are conflated like any other",
+                        group.name
+                    );
+                }
+                flusher.set_conflation(conflation.clone());
+            }
+            if let Some(book_deltas) = &config.book_deltas {
+                flusher.set_book_deltas(book_deltas.clone());
+            }
+            if let Some(ingest_lag) = &config.ingest_lag {
+                flusher.set_ingest_lag(ingest_lag.clone());
+            }
+            for processor in processors {
+                flusher.add_processor(processor);
+            }
+            flusher.run();
+        }));
+    }
 
     poller_handle.join().expect("poller panicked");
-    flusher_handle.join().expect("flusher panicked");
+    for handle in flusher_handles {
+        handle.join().expect("flusher panicked");
+    }
     println!("System shutdown");
 }
\ No newline at end of file
@@ -0,0 +1,637 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::logger::audit_journal::JournalLine;
+use crate::logger::serde_fields::{self, format_timestamp};
+use crate::logger::types::{order_event_type_name, reason_name, severity_name, side_name, LogRecord, Stream, TradeLogs};
+use crate::query::book::parse_at;
+use crate::query::questdb::{sql_timestamp, QuestDbClient, Row, DEFAULT_URL};
+
+// QuestDB is asked about this many order ids per trade query
+const ORDER_ID_CHUNK: usize = 500;
+
+// decoded name of a raw enum , the number itself when it has none
+fn decoded(value: u8, name: fn(u8) -> Option<&'static str>) -> String {
+    name(value).map(str::to_string).unwrap_or_else(|| value.to_string())
+}
+
+// one line of the report , fields a stream does not have stay empty
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    #[serde(with = "serde_fields::timestamp")]
+    pub timestamp: i64,
+    pub stream: Stream,
+    // received / matched / canceled for orders , lock / update for balances and holdings , trade
+    pub event: String,
+    pub order_id: Option<u64>,
+    // position of this event among the events of its order , 1 based
+    pub order_event_no: u32,
+    pub event_id: Option<u64>,
+    pub symbol: Option<u32>,
+    pub side: Option<String>,
+    pub price: Option<u64>,
+    pub quantity: Option<u64>,
+    pub delta_available: Option<i64>,
+    pub delta_reserved: Option<i64>,
+    // the other order of a trade
+    pub counterparty_order_id: Option<u64>,
+    // maker or taker , trades only
+    pub liquidity: Option<&'static str>,
+    pub severity: Option<String>,
+}
+
+impl AuditEvent {
+    fn new(timestamp: i64, stream: Stream, event: &str) -> Self {
+        Self {
+            timestamp,
+            stream,
+            event: event.to_string(),
+            order_id: None,
+            order_event_no: 0,
+            event_id: None,
+            symbol: None,
+            side: None,
+            price: None,
+            quantity: None,
+            delta_available: None,
+            delta_reserved: None,
+            counterparty_order_id: None,
+            liquidity: None,
+            severity: None,
+        }
+    }
+
+    // one event per order of the user in the trade , a self trade gives two
+    fn from_trade(trade: &TradeLogs, orders: &HashSet<u64>) -> Vec<Self> {
+        let sides = [
+            (trade.buyer_order_id, trade.seller_order_id, "bid", trade.is_buyer_maker),
+            (trade.seller_order_id, trade.buyer_order_id, "ask", !trade.is_buyer_maker),
+        ];
+        sides
+            .iter()
+            .filter(|(own, ..)| orders.contains(own))
+            .map(|(own, other, side, maker)| Self {
+                order_id: Some(*own),
+                symbol: Some(trade.symbol),
+                side: Some(side.to_string()),
+                price: Some(trade.price),
+                quantity: Some(trade.quantity as u64),
+                counterparty_order_id: Some(*other),
+                liquidity: Some(if *maker { "maker" } else { "taker" }),
+                ..Self::new(trade.timestamp, Stream::TradeLogs, "trade")
+            })
+            .collect()
+    }
+
+    fn from_record(record: &LogRecord) -> Option<Self> {
+        let event = match record {
+            LogRecord::OrderLog(log) => {
+                let d = &log.order_delta;
+                Self {
+                    order_id: Some(d.order_id),
+                    event_id: Some(d.event_id),
+                    symbol: Some(d.symbol),
+                    side: Some(decoded(d.side, side_name)),
+                    price: Some(d.price),
+                    quantity: Some(d.shares_qty as u64),
+                    severity: Some(decoded(log.severity, severity_name)),
+                    ..Self::new(log.timestamp, Stream::OrderLogs, &decoded(d.order_event_type, order_event_type_name))
+                }
+            }
+            LogRecord::BalanceLog(log) => {
+                let d = &log.balance_delta;
+                Self {
+                    order_id: Some(d.order_id),
+                    event_id: Some(d.event_id),
+                    delta_available: Some(d.delta_available),
+                    delta_reserved: Some(d.delta_reserved),
+                    severity: Some(decoded(log.severity, severity_name)),
+                    ..Self::new(log.timestamp, Stream::BalanceLogs, &decoded(d.reason, reason_name))
+                }
+            }
+            LogRecord::HoldingLog(log) => {
+                let d = &log.holding_delta;
+                Self {
+                    order_id: Some(d.order_id),
+                    event_id: Some(d.event_id),
+                    symbol: Some(d.symbol),
+                    delta_available: Some(d.delta_available as i64),
+                    delta_reserved: Some(d.delta_reserved as i64),
+                    severity: Some(decoded(log.severity, severity_name)),
+                    ..Self::new(log.timestamp, Stream::HoldingLogs, &decoded(d.reason, reason_name))
+                }
+            }
+            LogRecord::TradeLog(_) | LogRecord::Snapshot(_) => return None,
+        };
+        Some(event)
+    }
+}
+
+// per order roll up , in order of first appearance
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderSummary {
+    pub order_id: u64,
+    pub symbol: Option<u32>,
+    pub side: Option<String>,
+    #[serde(with = "serde_fields::timestamp")]
+    pub first_seen: i64,
+    #[serde(with = "serde_fields::timestamp")]
+    pub last_seen: i64,
+    pub events: u32,
+    pub trades: u32,
+    pub traded_qty: u64,
+    // last order log event in the range
+    pub last_state: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditReport {
+    pub user_id: u64,
+    #[serde(with = "serde_fields::timestamp")]
+    pub from: i64,
+    #[serde(with = "serde_fields::timestamp")]
+    pub to: i64,
+    pub source: String,
+    pub orders: Vec<OrderSummary>,
+    pub events: Vec<AuditEvent>,
+}
+
+impl AuditReport {
+    // time ordered , ties keep the stream order so an order log comes before the balance it caused
+    fn build(user_id: u64, from: i64, to: i64, source: &str, mut events: Vec<AuditEvent>) -> Self {
+        events.sort_by_key(|e| (e.timestamp, e.stream.index()));
+        let mut orders: Vec<OrderSummary> = Vec::new();
+        let mut positions: HashMap<u64, usize> = HashMap::new();
+        for event in &mut events {
+            let Some(order_id) = event.order_id else { continue };
+            let idx = *positions.entry(order_id).or_insert_with(|| {
+                orders.push(OrderSummary {
+                    order_id,
+                    symbol: None,
+                    side: None,
+                    first_seen: event.timestamp,
+                    last_seen: event.timestamp,
+                    events: 0,
+                    trades: 0,
+                    traded_qty: 0,
+                    last_state: None,
+                });
+                orders.len() - 1
+            });
+            let summary = &mut orders[idx];
+            summary.events += 1;
+            summary.last_seen = event.timestamp;
+            summary.symbol = summary.symbol.or(event.symbol);
+            if summary.side.is_none() {
+                summary.side = event.side.clone();
+            }
+            match event.stream {
+                Stream::TradeLogs => {
+                    summary.trades += 1;
+                    summary.traded_qty += event.quantity.unwrap_or(0);
+                }
+                Stream::OrderLogs => summary.last_state = Some(event.event.clone()),
+                _ => {}
+            }
+            event.order_event_no = summary.events;
+        }
+        Self { user_id, from, to, source: source.to_string(), orders, events }
+    }
+
+    pub fn write_json(&self, out: &mut dyn Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, self)?;
+        writeln!(out)
+    }
+
+    // the events only , one row each , order summaries are a JSON thing
+    pub fn write_csv(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "timestamp,stream,event,order_id,order_event_no,event_id,symbol,side,price,quantity,\
+             delta_available,delta_reserved,counterparty_order_id,liquidity,severity"
+        )?;
+        fn cell<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(T::to_string).unwrap_or_default()
+        }
+        for e in &self.events {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                format_timestamp(e.timestamp),
+                e.stream.table(),
+                e.event,
+                cell(&e.order_id),
+                e.order_event_no,
+                cell(&e.event_id),
+                cell(&e.symbol),
+                cell(&e.side),
+                cell(&e.price),
+                cell(&e.quantity),
+                cell(&e.delta_available),
+                cell(&e.delta_reserved),
+                cell(&e.counterparty_order_id),
+                cell(&e.liquidity),
+                cell(&e.severity),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// order ids the user's own events mention , trades are matched against these
+fn user_orders(events: &[AuditEvent]) -> HashSet<u64> {
+    events.iter().filter_map(|e| e.order_id).collect()
+}
+
+fn u64_col(row: &Row, name: &str) -> Result<u64, String> {
+    Ok(row.i64(name)? as u64)
+}
+
+fn symbol_col(row: &Row, name: &str) -> Result<u32, String> {
+    row.str(name)?.parse().map_err(|e| format!("column {} is not a symbol id: {}", name, e))
+}
+
+// the first trade query whose order ids take in this trade , identical fills still come back once per query
+fn first_chunk(chunk_of: &HashMap<u64, usize>, trade: &TradeLogs) -> Option<usize> {
+    [trade.buyer_order_id, trade.seller_order_id].iter().filter_map(|id| chunk_of.get(id)).min().copied()
+}
+
+pub fn from_questdb(client: &QuestDbClient, user_id: u64, from: i64, to: i64) -> Result<Vec<AuditEvent>, String> {
+    let range = format!("timestamp >= {} AND timestamp <= {}", sql_timestamp(from), sql_timestamp(to));
+    let mut events = Vec::new();
+
+    let sql = format!(
+        "SELECT timestamp, instrument, side, event_type, severity, event_id, order_id, price, shares_qty \
+         FROM order_logs WHERE user_id = {} AND {}",
+        user_id, range
+    );
+    for row in client.exec_optional(&sql, "order_logs")?.iter().flat_map(|r| r.rows()) {
+        events.push(AuditEvent {
+            order_id: Some(u64_col(&row, "order_id")?),
+            event_id: Some(u64_col(&row, "event_id")?),
+            symbol: Some(symbol_col(&row, "instrument")?),
+            side: Some(row.str("side")?.to_string()),
+            price: Some(u64_col(&row, "price")?),
+            quantity: Some(u64_col(&row, "shares_qty")?),
+            severity: Some(row.str("severity")?.to_string()),
+            ..AuditEvent::new(row.timestamp("timestamp")?, Stream::OrderLogs, row.str("event_type")?)
+        });
+    }
+
+    let sql = format!(
+        "SELECT timestamp, reason, severity, event_id, order_id, delta_available_balance, delta_reserved_balance \
+         FROM balance_logs WHERE user_id = {} AND {}",
+        user_id, range
+    );
+    for row in client.exec_optional(&sql, "balance_logs")?.iter().flat_map(|r| r.rows()) {
+        events.push(AuditEvent {
+            order_id: Some(u64_col(&row, "order_id")?),
+            event_id: Some(u64_col(&row, "event_id")?),
+            delta_available: Some(row.i64("delta_available_balance")?),
+            delta_reserved: Some(row.i64("delta_reserved_balance")?),
+            severity: Some(row.str("severity")?.to_string()),
+            ..AuditEvent::new(row.timestamp("timestamp")?, Stream::BalanceLogs, row.str("reason")?)
+        });
+    }
+
+    let sql = format!(
+        "SELECT timestamp, instrument, reason, severity, event_id, order_id, delta_available_holding, delta_reserved_holding \
+         FROM holding_logs WHERE user_id = {} AND {}",
+        user_id, range
+    );
+    for row in client.exec_optional(&sql, "holding_logs")?.iter().flat_map(|r| r.rows()) {
+        events.push(AuditEvent {
+            order_id: Some(u64_col(&row, "order_id")?),
+            event_id: Some(u64_col(&row, "event_id")?),
+            symbol: Some(symbol_col(&row, "instrument")?),
+            delta_available: Some(row.i64("delta_available_holding")?),
+            delta_reserved: Some(row.i64("delta_reserved_holding")?),
+            severity: Some(row.str("severity")?.to_string()),
+            ..AuditEvent::new(row.timestamp("timestamp")?, Stream::HoldingLogs, row.str("reason")?)
+        });
+    }
+
+    // trades carry no user , they are found through the user's orders
+    let orders = user_orders(&events);
+    let ids: Vec<u64> = orders.iter().copied().collect();
+    let chunk_of: HashMap<u64, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i / ORDER_ID_CHUNK)).collect();
+    for (chunk_no, chunk) in ids.chunks(ORDER_ID_CHUNK).enumerate() {
+        let list = chunk.iter().map(u64::to_string).collect::<Vec<_>>().join(",");
+        let sql = format!(
+            "SELECT timestamp, symbol, price, quantity, buyer_order_id, seller_order_id, is_buyer_maker \
+             FROM trade_logs WHERE (buyer_order_id IN ({list}) OR seller_order_id IN ({list})) AND {range}"
+        );
+        for row in client.exec_optional(&sql, "trade_logs")?.iter().flat_map(|r| r.rows()) {
+            let trade = TradeLogs {
+                timestamp: row.timestamp("timestamp")?,
+                buyer_order_id: u64_col(&row, "buyer_order_id")?,
+                seller_order_id: u64_col(&row, "seller_order_id")?,
+                price: u64_col(&row, "price")?,
+                symbol: symbol_col(&row, "symbol")?,
+                quantity: row.i64("quantity")? as u32,
+                is_buyer_maker: row.bool("is_buyer_maker")?,
+            };
+            // a trade between two of the user's orders in different chunks is kept by the earlier one
+            if first_chunk(&chunk_of, &trade) == Some(chunk_no) {
+                events.extend(AuditEvent::from_trade(&trade, &orders));
+            }
+        }
+    }
+    Ok(events)
+}
+
+fn jsonl_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("cannot read {}: {}", dir.display(), e))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// records of the user and every trade seen in the window , the trades are narrowed to the user's orders at the end
+struct Collector {
+    user_id: u64,
+    from: i64,
+    to: i64,
+    events: Vec<AuditEvent>,
+    trades: Vec<TradeLogs>,
+    bad_lines: u64,
+}
+
+impl Collector {
+    fn new(user_id: u64, from: i64, to: i64) -> Self {
+        Self { user_id, from, to, events: Vec::new(), trades: Vec::new(), bad_lines: 0 }
+    }
+
+    fn add(&mut self, record: LogRecord) {
+        if record.timestamp() < self.from || record.timestamp() > self.to {
+            return;
+        }
+        match record {
+            LogRecord::TradeLog(trade) => self.trades.push(trade),
+            _ if record.user_id() == Some(self.user_id) => self.events.extend(AuditEvent::from_record(&record)),
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> Vec<AuditEvent> {
+        if self.bad_lines > 0 {
+            eprintln!("audit-export: skipped {} lines that are not log records", self.bad_lines);
+        }
+        let orders = user_orders(&self.events);
+        for trade in &self.trades {
+            self.events.extend(AuditEvent::from_trade(trade, &orders));
+        }
+        self.events
+    }
+}
+
+// every jsonl sink file in the directory , lines that do not parse are counted and skipped
+pub fn from_jsonl(dir: &Path, user_id: u64, from: i64, to: i64) -> Result<Vec<AuditEvent>, String> {
+    let mut collector = Collector::new(user_id, from, to);
+    for path in jsonl_files(dir)? {
+        let file = File::open(&path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+            match serde_json::from_str::<LogRecord>(&line) {
+                Ok(record) => collector.add(record),
+                Err(_) => collector.bad_lines += 1,
+            }
+        }
+    }
+    Ok(collector.finish())
+}
+
+// the record of one journal line , None for a checkpoint
+fn journal_record(line: &JournalLine) -> Result<Option<LogRecord>, serde_json::Error> {
+    let payload = line.payload;
+    let record = match line.kind {
+        kind if kind == Stream::OrderLogs.table() => LogRecord::OrderLog(serde_json::from_str(payload)?),
+        kind if kind == Stream::BalanceLogs.table() => LogRecord::BalanceLog(serde_json::from_str(payload)?),
+        kind if kind == Stream::HoldingLogs.table() => LogRecord::HoldingLog(serde_json::from_str(payload)?),
+        kind if kind == Stream::TradeLogs.table() => LogRecord::TradeLog(serde_json::from_str(payload)?),
+        kind if kind == Stream::Snapshots.table() => LogRecord::Snapshot(serde_json::from_str(payload)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(record))
+}
+
+// one audit journal file , the hash chain is not checked here , `logger verify` does that
+pub fn from_journal(path: &Path, user_id: u64, from: i64, to: i64) -> Result<Vec<AuditEvent>, String> {
+    let mut collector = Collector::new(user_id, from, to);
+    let file = File::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        match JournalLine::parse(&line).map(|line| journal_record(&line)) {
+            Ok(Ok(Some(record))) => collector.add(record),
+            Ok(Ok(None)) => {}
+            _ => collector.bad_lines += 1,
+        }
+    }
+    Ok(collector.finish())
+}
+
+// logger audit-export --user 9 --from 2024-05-01T00:00:00Z --to 2024-05-02T00:00:00Z
+//     [--format csv|json] [--source questdb|jsonl:<dir>|journal:<file>] [--questdb http://localhost:9000]
+//     [--out report.csv]
+pub fn run(args: &HashMap<String, String>) -> Result<(), String> {
+    let user_id: u64 = args
+        .get("user")
+        .ok_or("--user is required")?
+        .parse()
+        .map_err(|e| format!("invalid --user: {}", e))?;
+    let from = parse_at(args.get("from").ok_or("--from is required")?)?;
+    let to = parse_at(args.get("to").ok_or("--to is required")?)?;
+    if to < from {
+        return Err("--to is before --from".to_string());
+    }
+    let format = args.get("format").map(String::as_str).unwrap_or("csv");
+    if format != "csv" && format != "json" {
+        return Err(format!("unknown --format {} , expected csv or json", format));
+    }
+    let source = args.get("source").map(String::as_str).unwrap_or("questdb");
+
+    let events = if let Some(dir) = source.strip_prefix("jsonl:") {
+        from_jsonl(Path::new(dir), user_id, from, to)?
+    } else if let Some(file) = source.strip_prefix("journal:") {
+        from_journal(Path::new(file), user_id, from, to)?
+    } else if source == "questdb" {
+        let client = QuestDbClient::new(args.get("questdb").map(String::as_str).unwrap_or(DEFAULT_URL));
+        from_questdb(&client, user_id, from, to)?
+    } else {
+        return Err(format!("unknown --source {} , expected questdb , jsonl:<dir> or journal:<file>", source));
+    };
+    let report = AuditReport::build(user_id, from, to, source, events);
+
+    let mut out: BufWriter<Box<dyn Write>> = BufWriter::new(match args.get("out") {
+        Some(path) => Box::new(File::create(path).map_err(|e| format!("cannot create {}: {}", path, e))?),
+        None => Box::new(io::stdout()),
+    });
+    let written = match format {
+        "json" => report.write_json(&mut out),
+        _ => report.write_csv(&mut out),
+    };
+    written.and_then(|_| out.flush()).map_err(|e| format!("write failed: {}", e))?;
+
+    let per_stream: BTreeMap<&str, usize> = report.events.iter().fold(BTreeMap::new(), |mut acc, e| {
+        *acc.entry(e.stream.table()).or_default() += 1;
+        acc
+    });
+    eprintln!(
+        "audit-export: user {} , {} events over {} orders {:?}",
+        user_id, report.events.len(), report.orders.len(), per_stream
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::audit_journal::{AuditJournal, JournalConfig};
+    use crate::logger::sink::LogSink;
+    use crate::logger::types::test_records::{balance, order, snapshot, trade};
+    use ed25519_dalek::SigningKey;
+
+    fn event(record: LogRecord) -> AuditEvent {
+        AuditEvent::from_record(&record).unwrap()
+    }
+
+    fn shape(events: &[AuditEvent]) -> Vec<(i64, Stream, Option<u64>, u32)> {
+        events.iter().map(|e| (e.timestamp, e.stream, e.order_id, e.order_event_no)).collect()
+    }
+
+    #[test]
+    fn trade_gives_one_event_per_own_order() {
+        let orders: HashSet<u64> = [1, 4].into();
+        let events = AuditEvent::from_trade(&trade(1, 2).qty(5), &orders);
+        assert_eq!(events.len(), 1);
+        let own = &events[0];
+        assert_eq!(own.side.as_deref(), Some("bid"));
+        assert_eq!((own.counterparty_order_id, own.liquidity), (Some(2), Some("taker")));
+
+        // a self trade shows up under both orders
+        let events = AuditEvent::from_trade(&trade(1, 4).qty(5), &orders);
+        let sides: Vec<_> = events.iter().map(|e| (e.order_id, e.side.as_deref(), e.liquidity)).collect();
+        assert_eq!(sides, [(Some(1), Some("bid"), Some("taker")), (Some(4), Some("ask"), Some("maker"))]);
+    }
+
+    #[test]
+    fn build_orders_events_and_numbers_them_per_order() {
+        let orders: HashSet<u64> = [1, 3].into();
+        let mut events = AuditEvent::from_trade(&trade(1, 2).at(20).qty(5), &orders);
+        events.extend([
+            event(balance(1, 9).at(20).reason(1).into()),
+            event(order(1, 9).at(20).event(1).into()),
+            event(order(3, 9).at(15).symbol(8).side(1).into()),
+            event(balance(1, 9).at(10).into()),
+            event(order(1, 9).at(10).into()),
+            event(order(3, 9).at(30).symbol(8).side(1).event(2).into()),
+        ]);
+        let report = AuditReport::build(9, 0, 100, "test", events);
+
+        // same timestamp keeps the stream order , order log then balance then trade
+        assert_eq!(
+            shape(&report.events),
+            [
+                (10, Stream::OrderLogs, Some(1), 1),
+                (10, Stream::BalanceLogs, Some(1), 2),
+                (15, Stream::OrderLogs, Some(3), 1),
+                (20, Stream::OrderLogs, Some(1), 3),
+                (20, Stream::BalanceLogs, Some(1), 4),
+                (20, Stream::TradeLogs, Some(1), 5),
+                (30, Stream::OrderLogs, Some(3), 2),
+            ]
+        );
+
+        let [first, second] = report.orders.as_slice() else { panic!("{:?}", report.orders) };
+        assert_eq!((first.order_id, first.symbol, first.side.as_deref()), (1, Some(7), Some("bid")));
+        assert_eq!((first.first_seen, first.last_seen, first.events), (10, 20, 5));
+        assert_eq!((first.trades, first.traded_qty, first.last_state.as_deref()), (1, 5, Some("matched")));
+        assert_eq!((second.order_id, second.events, second.trades), (3, 2, 0));
+        assert_eq!((second.side.as_deref(), second.last_state.as_deref()), (Some("ask"), Some("canceled")));
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 1 + 7);
+    }
+
+    #[test]
+    fn trade_rows_are_kept_by_the_first_chunk_they_match() {
+        let chunk_of: HashMap<u64, usize> = [(1, 0), (2, 1)].into();
+        assert_eq!(first_chunk(&chunk_of, &trade(1, 2).qty(5)), Some(0));
+        assert_eq!(first_chunk(&chunk_of, &trade(3, 2).qty(5)), Some(1));
+        assert_eq!(first_chunk(&chunk_of, &trade(3, 4).qty(5)), None);
+    }
+
+    #[test]
+    fn jsonl_source_picks_the_user_and_their_trades() {
+        let dir = std::env::temp_dir().join(format!("logger-audit-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let lines = |records: &[LogRecord]| {
+            records.iter().map(|r| serde_json::to_string(r).unwrap() + "\n").collect::<String>()
+        };
+        let first = lines(&[order(1, 9).at(10).into(), balance(1, 9).at(10).into(), order(2, 8).at(11).side(1).into()]);
+        fs::write(dir.join("logs-a.jsonl"), first + "not a record\n").unwrap();
+        fs::write(
+            dir.join("logs-b.jsonl"),
+            lines(&[
+                trade(1, 2).at(20).qty(5).into(),
+                // identical fills are two events
+                trade(1, 2).at(20).qty(5).into(),
+                trade(5, 6).at(20).qty(5).into(),
+                order(1, 9).at(500).event(2).into(),
+            ]),
+        )
+        .unwrap();
+        fs::write(dir.join("ignored.txt"), "not a record\n").unwrap();
+
+        let report = AuditReport::build(9, 0, 100, "jsonl", from_jsonl(&dir, 9, 0, 100).unwrap());
+        assert_eq!(
+            shape(&report.events),
+            [
+                (10, Stream::OrderLogs, Some(1), 1),
+                (10, Stream::BalanceLogs, Some(1), 2),
+                (20, Stream::TradeLogs, Some(1), 3),
+                (20, Stream::TradeLogs, Some(1), 4),
+            ]
+        );
+        assert_eq!(report.orders[0].traded_qty, 10);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn journal_source_reads_what_the_journal_wrote() {
+        let dir = std::env::temp_dir().join(format!("logger-audit-{}-journal", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = JournalConfig { dir: dir.clone(), ..JournalConfig::default() };
+        let path = config.path();
+        let mut journal = AuditJournal::new(config, SigningKey::from_bytes(&[3u8; 32])).unwrap();
+        journal.write_order_log(&order(1, 9).at(10));
+        journal.write_balance_log(&balance(1, 9).at(10));
+        journal.write_order_log(&order(2, 8).at(11).side(1));
+        journal.write_trade_log(&trade(1, 2).at(20).qty(5));
+        journal.write_trade_log(&trade(5, 6).at(20).qty(5));
+        journal.write_snapshot(&snapshot(&[(100, 1)], &[]).at(20));
+        // closes with a checkpoint line
+        drop(journal);
+
+        let events = from_journal(&path, 9, 0, 100).unwrap();
+        let report = AuditReport::build(9, 0, 100, "journal", events);
+        assert_eq!(
+            shape(&report.events),
+            [
+                (10, Stream::OrderLogs, Some(1), 1),
+                (10, Stream::BalanceLogs, Some(1), 2),
+                (20, Stream::TradeLogs, Some(1), 3),
+            ]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::logger::book_deltas::{ASK, BID};
+use crate::logger::processor::NANOS_PER_MILLI;
+use crate::logger::serde_fields::{format_timestamp, parse_timestamp};
+use crate::logger::types::{side_str, TradeLogs};
+use crate::query::questdb::{sql_timestamp, ExecResponse, QuestDbClient, DEFAULT_URL};
+
+// the book of one symbol as of a point in time
+#[derive(Debug, Clone, Default)]
+pub struct BookAt {
+    pub symbol: u32,
+    pub at: i64,
+    pub keyframe_at: i64,
+    pub keyframe_id: u64,
+    // producer time of the last change applied , the keyframe when there were none
+    pub as_of: i64,
+    pub deltas_applied: usize,
+    pub bids: BTreeMap<u64, u32>,
+    pub asks: BTreeMap<u64, u32>,
+}
+
+impl BookAt {
+    fn apply(&mut self, side: u8, price: u64, qty: u32) {
+        let levels = if side == BID { &mut self.bids } else { &mut self.asks };
+        if qty == 0 {
+            levels.remove(&price);
+        } else {
+            levels.insert(price, qty);
+        }
+    }
+}
+
+fn parse_levels(raw: &str) -> Result<Vec<(u64, u32)>, String> {
+    serde_json::from_str(raw).map_err(|e| format!("invalid levels {}: {}", raw, e))
+}
+
+// nearest keyframe at or before `at` , then every delta stored after it up to `at`
+pub fn book_at(client: &QuestDbClient, symbol: u32, at: i64) -> Result<BookAt, String> {
+    let keyframe = client.exec(&format!(
+        "SELECT timestamp, snapshot_id, bids, asks FROM orderbook_snapshots \
+         WHERE symbol = '{}' AND timestamp <= {} ORDER BY timestamp DESC LIMIT 1",
+        symbol, sql_timestamp(at)
+    ))?;
+    let mut book = keyframe_book(symbol, at, &keyframe)?;
+
+    // deltas are optional , without them the keyframe is every stored snapshot .
+    // sql_timestamp drops the nanos , so deltas in the keyframe's microsecond are
+    // fetched too and told apart by snapshot id
+    let sql = format!(
+        "SELECT timestamp, snapshot_id, side, price, new_qty FROM orderbook_deltas \
+         WHERE symbol = '{}' AND timestamp >= {} AND timestamp <= {} AND snapshot_id > {} \
+         ORDER BY timestamp, snapshot_id",
+        symbol, sql_timestamp(book.keyframe_at), sql_timestamp(at), book.keyframe_id
+    );
+    if let Some(deltas) = client.exec_optional(&sql, "orderbook_deltas")? {
+        apply_deltas(&mut book, &deltas)?;
+    }
+    Ok(book)
+}
+
+// the book as stored in the keyframe row , nothing applied yet
+pub fn keyframe_book(symbol: u32, at: i64, keyframe: &ExecResponse) -> Result<BookAt, String> {
+    let row = keyframe
+        .rows()
+        .next()
+        .ok_or_else(|| format!("no snapshot of symbol {} at or before {}", symbol, format_timestamp(at)))?;
+
+    let mut book = BookAt {
+        symbol,
+        at,
+        keyframe_at: row.timestamp("timestamp")?,
+        keyframe_id: row.i64("snapshot_id")? as u64,
+        ..Default::default()
+    };
+    book.as_of = book.keyframe_at;
+    for (price, qty) in parse_levels(row.str("bids")?)? {
+        book.apply(BID, price, qty);
+    }
+    for (price, qty) in parse_levels(row.str("asks")?)? {
+        book.apply(ASK, price, qty);
+    }
+    Ok(book)
+}
+
+// level changes in (timestamp , snapshot id) order , only those after the keyframe and up to book.at
+pub fn apply_deltas(book: &mut BookAt, deltas: &ExecResponse) -> Result<(), String> {
+    let mut changes = Vec::with_capacity(deltas.dataset.len());
+    for row in deltas.rows() {
+        let side = if row.str("side")? == side_str(BID) { BID } else { ASK };
+        let (price, qty) = (row.i64("price")? as u64, row.i64("new_qty")? as u32);
+        changes.push((row.timestamp("timestamp")?, row.i64("snapshot_id")? as u64, side, price, qty));
+    }
+    changes.sort_by_key(|&(timestamp, snapshot_id, ..)| (timestamp, snapshot_id));
+    for (timestamp, snapshot_id, side, price, qty) in changes {
+        if snapshot_id <= book.keyframe_id || timestamp > book.at {
+            continue;
+        }
+        book.apply(side, price, qty);
+        book.as_of = timestamp;
+        book.deltas_applied += 1;
+    }
+    Ok(())
+}
+
+pub fn trades_between(client: &QuestDbClient, symbol: u32, from: i64, to: i64) -> Result<Vec<TradeLogs>, String> {
+    let result = client.exec(&format!(
+        "SELECT timestamp, buyer_order_id, seller_order_id, price, quantity, is_buyer_maker FROM trade_logs \
+         WHERE symbol = '{}' AND timestamp >= {} AND timestamp <= {} ORDER BY timestamp",
+        symbol, sql_timestamp(from), sql_timestamp(to)
+    ))?;
+    result
+        .rows()
+        .map(|row| {
+            Ok(TradeLogs {
+                timestamp: row.timestamp("timestamp")?,
+                buyer_order_id: row.i64("buyer_order_id")? as u64,
+                seller_order_id: row.i64("seller_order_id")? as u64,
+                price: row.i64("price")? as u64,
+                symbol,
+                quantity: row.i64("quantity")? as u32,
+                is_buyer_maker: row.bool("is_buyer_maker")?,
+            })
+        })
+        .collect()
+}
+
+pub fn print_book(book: &BookAt, trades: &[TradeLogs]) {
+    println!("symbol {} at {}", book.symbol, format_timestamp(book.at));
+    println!(
+        "keyframe {} (snapshot {}) + {} deltas , book as of {}",
+        format_timestamp(book.keyframe_at), book.keyframe_id, book.deltas_applied, format_timestamp(book.as_of)
+    );
+    println!("{:>12} {:>14} | {:<14} {:<12}", "bid qty", "bid", "ask", "ask qty");
+    let mut bids = book.bids.iter().rev();
+    let mut asks = book.asks.iter();
+    loop {
+        let (bid, ask) = (bids.next(), asks.next());
+        if bid.is_none() && ask.is_none() {
+            break;
+        }
+        let cell = |level: Option<(&u64, &u32)>, price: bool| {
+            level.map(|(p, q)| if price { p.to_string() } else { q.to_string() }).unwrap_or_default()
+        };
+        println!("{:>12} {:>14} | {:<14} {:<12}", cell(bid, false), cell(bid, true), cell(ask, true), cell(ask, false));
+    }
+
+    println!("trades:");
+    let mut marked = false;
+    for trade in trades {
+        if !marked && trade.timestamp > book.at {
+            println!("  ---- {} ----", format_timestamp(book.at));
+            marked = true;
+        }
+        println!(
+            "  {} {:>+10.3}ms {} x {} buyer {} seller {}{}",
+            format_timestamp(trade.timestamp),
+            (trade.timestamp - book.at) as f64 / NANOS_PER_MILLI as f64,
+            trade.price,
+            trade.quantity,
+            trade.buyer_order_id,
+            trade.seller_order_id,
+            if trade.is_buyer_maker { " (buyer maker)" } else { "" }
+        );
+    }
+    if !marked {
+        println!("  ---- {} ----", format_timestamp(book.at));
+    }
+}
+
+// accepts RFC3339 or raw nanoseconds since the epoch
+pub fn parse_at(raw: &str) -> Result<i64, String> {
+    raw.parse::<i64>().or_else(|_| parse_timestamp(raw))
+}
+
+// logger book --symbol 17 --at 2024-05-01T14:03:12.345Z [--window-ms 1000] [--questdb http://localhost:9000]
+pub fn run(args: &HashMap<String, String>) -> Result<(), String> {
+    let symbol: u32 = args
+        .get("symbol")
+        .ok_or("--symbol is required")?
+        .parse()
+        .map_err(|e| format!("invalid --symbol: {}", e))?;
+    let at = parse_at(args.get("at").ok_or("--at is required")?)?;
+    let window_ms: i64 = match args.get("window-ms") {
+        Some(raw) => raw.parse().map_err(|e| format!("invalid --window-ms: {}", e))?,
+        None => 1_000,
+    };
+    let client = QuestDbClient::new(args.get("questdb").map(String::as_str).unwrap_or(DEFAULT_URL));
+
+    let book = book_at(&client, symbol, at)?;
+    let window = window_ms * NANOS_PER_MILLI;
+    let trades = trades_between(&client, symbol, at - window, at + window)?;
+    print_book(&book, &trades);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MS: i64 = NANOS_PER_MILLI;
+    // 2024-05-01T00:00:00Z
+    const T0: i64 = 1_714_521_600_000_000_000;
+
+    fn ts(nanos: i64) -> String {
+        format_timestamp(nanos)
+    }
+
+    fn response(columns: &[&str], dataset: serde_json::Value) -> ExecResponse {
+        let columns: Vec<_> = columns.iter().map(|name| json!({ "name": name, "type": "" })).collect();
+        serde_json::from_value(json!({ "columns": columns, "dataset": dataset })).unwrap()
+    }
+
+    fn keyframe() -> ExecResponse {
+        response(&["timestamp", "snapshot_id", "bids", "asks"], json!([[ts(T0), 10, "[[100,5],[99,3]]", "[[101,4]]"]]))
+    }
+
+    fn deltas(rows: serde_json::Value) -> ExecResponse {
+        response(&["timestamp", "snapshot_id", "side", "price", "new_qty"], rows)
+    }
+
+    #[test]
+    fn deltas_up_to_the_requested_time_are_applied() {
+        let mut book = keyframe_book(17, T0 + 5 * MS, &keyframe()).unwrap();
+        apply_deltas(&mut book, &deltas(json!([
+            [ts(T0 + MS), 11, "bid", 100, 0],
+            [ts(T0 + 2 * MS), 12, "ask", 102, 7],
+            [ts(T0 + 9 * MS), 13, "bid", 98, 1],
+        ])))
+        .unwrap();
+
+        assert_eq!(book.bids, BTreeMap::from([(99, 3)]));
+        assert_eq!(book.asks, BTreeMap::from([(101, 4), (102, 7)]));
+        assert_eq!((book.deltas_applied, book.as_of, book.keyframe_id), (2, T0 + 2 * MS, 10));
+    }
+
+    #[test]
+    fn deltas_in_one_microsecond_go_by_snapshot_id() {
+        let mut book = keyframe_book(17, T0 + MS, &keyframe()).unwrap();
+        // same microsecond as the keyframe , snapshot 9 is older than it and 12 is the newest
+        apply_deltas(&mut book, &deltas(json!([
+            [ts(T0), 12, "ask", 101, 9],
+            [ts(T0), 9, "ask", 101, 1],
+            [ts(T0), 11, "ask", 101, 6],
+        ])))
+        .unwrap();
+
+        assert_eq!(book.asks, BTreeMap::from([(101, 9)]));
+        assert_eq!(book.deltas_applied, 2);
+    }
+
+    #[test]
+    fn time_before_the_first_keyframe_is_an_error() {
+        let empty = response(&["timestamp", "snapshot_id", "bids", "asks"], json!([]));
+        let err = keyframe_book(17, T0, &empty).unwrap_err();
+        assert!(err.starts_with("no snapshot of symbol 17 at or before 2024-05-01T00:00:00"), "{}", err);
+    }
+
+    #[test]
+    fn at_is_nanos_or_rfc3339() {
+        assert_eq!(parse_at("1714521600000000000"), Ok(T0));
+        assert_eq!(parse_at("2024-05-01T00:00:00.000000001Z"), Ok(T0 + 1));
+        assert_eq!(parse_at("2024-05-01T02:00:00+02:00"), Ok(T0));
+        assert!(parse_at("yesterday").is_err());
+    }
+}
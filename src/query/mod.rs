@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+pub mod questdb;
+pub mod book;
+pub mod audit;
+pub mod verify;
+
+// --name value pairs , a flag without a value is stored as "true"
+pub fn parse_args(args: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut parsed = HashMap::new();
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        let name = arg.strip_prefix("--").ok_or_else(|| format!("unexpected argument {}", arg))?;
+        let value = match iter.peek() {
+            Some(next) if !next.starts_with("--") => iter.next().unwrap().clone(),
+            _ => "true".to_string(),
+        };
+        parsed.insert(name.to_string(), value);
+    }
+    Ok(parsed)
+}
+
+// offline commands , the logger itself runs when no command is given
+pub fn run(command: &str, args: &[String]) -> Result<(), String> {
+    let args = parse_args(args)?;
+    match command {
+        "book" => book::run(&args),
+        "audit-export" => audit::run(&args),
+        "verify" => verify::run(&args),
+        _ => Err(format!("unknown command {} , expected one of: book , audit-export , verify", command)),
+    }
+}
@@ -0,0 +1,115 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use ureq::Agent;
+
+use crate::logger::serde_fields::parse_timestamp;
+
+pub const DEFAULT_URL: &str = "http://localhost:9000";
+
+#[derive(Debug, Deserialize)]
+pub struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+// body of QuestDB's /exec endpoint , error is set instead of the dataset when the query failed
+#[derive(Debug, Deserialize)]
+pub struct ExecResponse {
+    #[serde(default)]
+    pub columns: Vec<Column>,
+    #[serde(default)]
+    pub dataset: Vec<Vec<Value>>,
+    pub error: Option<String>,
+    // offset into the query of what the error is about
+    pub position: Option<usize>,
+}
+
+pub struct Row<'a> {
+    pub columns: &'a [Column],
+    pub values: &'a [Value],
+}
+
+impl Row<'_> {
+    pub fn get(&self, name: &str) -> Result<&Value, String> {
+        self.columns
+            .iter()
+            .position(|c| c.name == name)
+            .and_then(|idx| self.values.get(idx))
+            .ok_or_else(|| format!("column {} missing from result", name))
+    }
+
+    pub fn i64(&self, name: &str) -> Result<i64, String> {
+        self.get(name)?.as_i64().ok_or_else(|| format!("column {} is not an integer", name))
+    }
+
+    pub fn str(&self, name: &str) -> Result<&str, String> {
+        self.get(name)?.as_str().ok_or_else(|| format!("column {} is not a string", name))
+    }
+
+    pub fn bool(&self, name: &str) -> Result<bool, String> {
+        self.get(name)?.as_bool().ok_or_else(|| format!("column {} is not a boolean", name))
+    }
+
+    // QuestDB hands timestamps back as ISO strings
+    pub fn timestamp(&self, name: &str) -> Result<i64, String> {
+        parse_timestamp(self.str(name)?)
+    }
+}
+
+impl ExecResponse {
+    pub fn rows(&self) -> impl Iterator<Item = Row<'_>> {
+        self.dataset.iter().map(|values| Row { columns: &self.columns, values })
+    }
+}
+
+pub struct QuestDbClient {
+    pub url: String,
+    pub agent: Agent,
+}
+
+impl QuestDbClient {
+    pub fn new(url: &str) -> Self {
+        // QuestDB reports query errors as 400 with a JSON body worth showing
+        let agent = Agent::config_builder().http_status_as_error(false).build().into();
+        Self { url: url.trim_end_matches('/').to_string(), agent }
+    }
+
+    pub fn exec(&self, sql: &str) -> Result<ExecResponse, String> {
+        let body = self.send(sql)?;
+        match body.error {
+            Some(error) => Err(format!("query failed: {} ({})", error, sql)),
+            None => Ok(body),
+        }
+    }
+
+    // None when the query failed on `table` itself , which is how QuestDB reports a table
+    // that was never created : the error position points at the table name
+    pub fn exec_optional(&self, sql: &str, table: &str) -> Result<Option<ExecResponse>, String> {
+        let body = self.send(sql)?;
+        match &body.error {
+            None => Ok(Some(body)),
+            Some(_) if body.position.and_then(|at| sql.get(at..)).is_some_and(|rest| rest.starts_with(table)) => Ok(None),
+            Some(error) => Err(format!("query failed: {} ({})", error, sql)),
+        }
+    }
+
+    fn send(&self, sql: &str) -> Result<ExecResponse, String> {
+        let mut response = self
+            .agent
+            .get(format!("{}/exec", self.url))
+            .query("query", sql)
+            .call()
+            .map_err(|e| format!("request to {} failed: {}", self.url, e))?;
+        response
+            .body_mut()
+            .read_json()
+            .map_err(|e| format!("unexpected response from {}: {}", self.url, e))
+    }
+}
+
+// QuestDB timestamps are microseconds , anything finer is cut off
+pub fn sql_timestamp(timestamp: i64) -> String {
+    format!("'{}'", DateTime::<Utc>::from_timestamp_nanos(timestamp).format("%Y-%m-%dT%H:%M:%S%.6fZ"))
+}
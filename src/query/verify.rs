@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use ed25519_dalek::VerifyingKey;
+
+use crate::logger::audit_journal::{
+    chain_hash, from_hex, to_hex, verify_checkpoint, Checkpoint, JournalLine, CHECKPOINT, GENESIS,
+};
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub lines: u64,
+    pub checkpoints: u64,
+    // seq of the last line covered by a valid signature
+    pub signed_through: Option<u64>,
+    pub last_seq: Option<u64>,
+}
+
+// where the chain first breaks , everything before it verified
+#[derive(Debug)]
+pub struct Tampered {
+    pub line_no: u64,
+    pub seq: Option<u64>,
+    pub reason: String,
+}
+
+// accepts the hex key itself or a file holding it ( the .pub written next to the signing key )
+fn load_public_key(raw: &str) -> Result<VerifyingKey, String> {
+    let hex = match std::fs::read_to_string(raw) {
+        Ok(contents) => contents,
+        Err(_) => raw.to_string(),
+    };
+    VerifyingKey::from_bytes(&from_hex::<32>(&hex)?).map_err(|e| format!("invalid public key: {}", e))
+}
+
+// without a trusted key the checkpoints are checked against the key they carry ,
+// which proves nothing against someone who rewrote the whole file
+pub fn verify_journal(path: &Path, key: Option<&VerifyingKey>) -> Result<Result<VerifyReport, Tampered>, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    let mut reader = BufReader::new(file);
+    let mut report = VerifyReport::default();
+    let mut prev_hash = GENESIS;
+    let mut prev_hex = to_hex(&GENESIS);
+    let mut embedded_key: Option<String> = None;
+    let mut raw = String::new();
+    let mut line_no = 0;
+
+    loop {
+        raw.clear();
+        let read = reader.read_line(&mut raw).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        line_no += 1;
+        let tampered = |seq: Option<u64>, reason: String| Ok(Err(Tampered { line_no, seq, reason }));
+        let Some(text) = raw.strip_suffix('\n') else {
+            return tampered(None, "last line is incomplete".to_string());
+        };
+        let line = match JournalLine::parse(text) {
+            Ok(line) => line,
+            Err(e) => return tampered(None, e),
+        };
+        let expected_seq = report.last_seq.map_or(0, |s| s + 1);
+        if line.seq != expected_seq {
+            return tampered(Some(line.seq), format!("expected seq {} , records were removed or inserted", expected_seq));
+        }
+        let hash = chain_hash(&prev_hash, line.seq, line.kind, line.payload.as_bytes());
+        let hash_hex = to_hex(&hash);
+        if hash_hex != line.hash {
+            return tampered(Some(line.seq), "hash does not match the record and the chain before it".to_string());
+        }
+
+        if line.kind == CHECKPOINT {
+            let checkpoint: Checkpoint = match serde_json::from_str(line.payload) {
+                Ok(checkpoint) => checkpoint,
+                Err(e) => return tampered(Some(line.seq), format!("invalid checkpoint: {}", e)),
+            };
+            if Some(checkpoint.signed_seq) != report.last_seq || checkpoint.signed_hash != prev_hex {
+                return tampered(Some(line.seq), "checkpoint does not sign the line before it".to_string());
+            }
+            let own_key;
+            let key = match key {
+                Some(key) => key,
+                None => {
+                    if embedded_key.as_ref().is_some_and(|k| *k != checkpoint.public_key) {
+                        return tampered(Some(line.seq), "checkpoint signed with a different key".to_string());
+                    }
+                    embedded_key = Some(checkpoint.public_key.clone());
+                    own_key = match from_hex::<32>(&checkpoint.public_key).map(|k| VerifyingKey::from_bytes(&k)) {
+                        Ok(Ok(key)) => key,
+                        _ => return tampered(Some(line.seq), "checkpoint carries an invalid key".to_string()),
+                    };
+                    &own_key
+                }
+            };
+            if let Err(e) = verify_checkpoint(&checkpoint, key) {
+                return tampered(Some(line.seq), format!("checkpoint {}", e));
+            }
+            report.checkpoints += 1;
+            report.signed_through = Some(checkpoint.signed_seq);
+        }
+
+        report.lines += 1;
+        report.last_seq = Some(line.seq);
+        prev_hash = hash;
+        prev_hex = hash_hex;
+    }
+    Ok(Ok(report))
+}
+
+// logger verify --journal /tmp/logger/journal/journal.log [--public-key /tmp/logger/journal.pub]
+pub fn run(args: &HashMap<String, String>) -> Result<(), String> {
+    let path = Path::new(args.get("journal").ok_or("--journal is required")?);
+    let key = args.get("public-key").map(|raw| load_public_key(raw)).transpose()?;
+    if key.is_none() {
+        eprintln!("verify: no --public-key , checkpoints are only checked against the key they carry");
+    }
+
+    match verify_journal(path, key.as_ref())? {
+        Ok(report) => {
+            println!("{} lines , {} checkpoints , chain intact", report.lines, report.checkpoints);
+            match (report.signed_through, report.last_seq) {
+                (Some(signed), Some(last)) if signed + 1 < last => {
+                    println!("lines after seq {} are not covered by a checkpoint yet", signed + 1)
+                }
+                (None, Some(_)) => println!("no checkpoint yet , nothing is signed"),
+                _ => {}
+            }
+            Ok(())
+        }
+        Err(tampered) => Err(format!(
+            "first tampered record at line {}{} : {}",
+            tampered.line_no,
+            tampered.seq.map(|s| format!(" (seq {})", s)).unwrap_or_default(),
+            tampered.reason
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use ed25519_dalek::SigningKey;
+
+    use super::*;
+    use crate::logger::audit_journal::{load_or_create_key, AuditJournal, JournalConfig};
+    use crate::logger::sink::LogSink;
+    use crate::logger::types::test_records::trade;
+
+    // a journal of five trades and the checkpoint written on drop , in a directory of its own
+    fn journal(name: &str) -> (PathBuf, VerifyingKey) {
+        let dir = std::env::temp_dir().join(format!("logger-verify-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        let config = JournalConfig { dir: dir.clone(), key_path: dir.join("journal.key"), ..JournalConfig::default() };
+        let signing = load_or_create_key(&config.key_path, &[config.path()]).unwrap();
+        let mut sink = AuditJournal::new(config, signing).unwrap();
+        let key = sink.key.verifying_key();
+        for i in 0..5 {
+            sink.write_trade_log(&trade(10 + i as u64, 20 + i as u64).at(1_700_000_000_000_000_000 + i).qty(5));
+        }
+        drop(sink);
+        (dir.join("journal.log"), key)
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        fs::read_to_string(path).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn rewrite(path: &Path, lines: &[String]) {
+        fs::write(path, lines.iter().map(|l| format!("{}\n", l)).collect::<String>()).unwrap();
+    }
+
+    fn tampered(path: &Path, key: &VerifyingKey) -> Tampered {
+        verify_journal(path, Some(key)).unwrap().expect_err("tampering went unnoticed")
+    }
+
+    #[test]
+    fn clean_chain_verifies() {
+        let (path, key) = journal("clean");
+        let report = verify_journal(&path, Some(&key)).unwrap().unwrap();
+        assert_eq!((report.lines, report.checkpoints, report.signed_through, report.last_seq), (6, 1, Some(4), Some(5)));
+
+        // each line chains from the one before it
+        let mut prev = GENESIS;
+        for raw in lines(&path) {
+            let line = JournalLine::parse(&raw).unwrap();
+            prev = chain_hash(&prev, line.seq, line.kind, line.payload.as_bytes());
+            assert_eq!(to_hex(&prev), line.hash);
+        }
+    }
+
+    #[test]
+    fn edited_payload_breaks_the_chain() {
+        let (path, key) = journal("edited");
+        let mut written = lines(&path);
+        written[2] = written[2].replace(r#""quantity":5"#, r#""quantity":500"#);
+        rewrite(&path, &written);
+        let found = tampered(&path, &key);
+        assert_eq!((found.line_no, found.seq), (3, Some(2)));
+        assert!(found.reason.contains("hash does not match"));
+    }
+
+    #[test]
+    fn deleted_line_is_found() {
+        let (path, key) = journal("deleted");
+        let mut written = lines(&path);
+        written.remove(3);
+        rewrite(&path, &written);
+        let found = tampered(&path, &key);
+        assert_eq!((found.line_no, found.seq), (4, Some(4)));
+        assert!(found.reason.contains("expected seq 3"));
+    }
+
+    #[test]
+    fn reordered_lines_are_found() {
+        let (path, key) = journal("reordered");
+        let mut written = lines(&path);
+        written.swap(1, 2);
+        rewrite(&path, &written);
+        let found = tampered(&path, &key);
+        assert_eq!((found.line_no, found.seq), (2, Some(2)));
+    }
+
+    #[test]
+    fn bad_signature_is_found() {
+        let (path, key) = journal("signature");
+        let mut written = lines(&path);
+
+        // a forged signature with the chain hash recomputed , so only the signature gives it away
+        let line = JournalLine::parse(&written[5]).unwrap();
+        let mut checkpoint: Checkpoint = serde_json::from_str(line.payload).unwrap();
+        checkpoint.signature = to_hex(&[7u8; 64]);
+        let payload = serde_json::to_string(&checkpoint).unwrap();
+        let prev = from_hex::<32>(JournalLine::parse(&written[4]).unwrap().hash).unwrap();
+        let hash = chain_hash(&prev, 5, CHECKPOINT, payload.as_bytes());
+        written[5] = format!("5\t{}\t{}\t{}", CHECKPOINT, to_hex(&hash), payload);
+        rewrite(&path, &written);
+        let found = tampered(&path, &key);
+        assert_eq!(found.line_no, 6);
+        assert!(found.reason.contains("signature does not verify"));
+
+        // a genuine journal checked against somebody else's key
+        let (path, _) = journal("other-key");
+        let other = SigningKey::from_bytes(&[9u8; 32]).verifying_key();
+        assert!(tampered(&path, &other).reason.contains("signature does not verify"));
+    }
+
+    #[test]
+    fn partial_last_line_is_reported_and_cut_on_resume() {
+        let (path, key) = journal("partial");
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut file, b"6\ttrade_logs\tabcd").unwrap();
+        drop(file);
+        let found = tampered(&path, &key);
+        assert_eq!((found.line_no, found.seq), (7, None));
+        assert!(found.reason.contains("incomplete"));
+
+        let dir = path.parent().unwrap().to_path_buf();
+        let config = JournalConfig { dir: dir.clone(), key_path: dir.join("journal.key"), ..JournalConfig::default() };
+        let signing = load_or_create_key(&config.key_path, &[config.path()]).unwrap();
+        drop(AuditJournal::new(config, signing).unwrap());
+        let report = verify_journal(&path, Some(&key)).unwrap().unwrap();
+        assert_eq!(report.last_seq, Some(5));
+    }
+}
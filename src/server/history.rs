@@ -0,0 +1,377 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crossbeam::channel::RecvTimeoutError;
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::logger::processor::{now_nanos, NANOS_PER_MILLI};
+use crate::logger::serde_fields;
+use crate::logger::types::{LogRecord, Stream};
+use crate::server::hub::{Filter, Hub, WhenFull};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    // keep this on loopback , there is no auth
+    pub addr: String,
+    // records older than this ( by the time the poller saw them ) are dropped
+    pub retention_ms: u64,
+    // empty -> every stream , snapshots are large so leaving them out saves most of the memory
+    pub streams: Vec<Stream>,
+    pub queue: usize,
+    // answers are cut at this many records
+    pub max_limit: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:9012".to_string(),
+            retention_ms: 10 * 60 * 1000,
+            streams: Vec::new(),
+            queue: 65536,
+            max_limit: 10_000,
+        }
+    }
+}
+
+// sequence numbers of the records carrying a key , oldest first
+type Index<K> = HashMap<K, VecDeque<u64>>;
+
+fn index_push<K: Hash + Eq>(index: &mut Index<K>, key: K, seq: u64) {
+    index.entry(key).or_default().push_back(seq);
+}
+
+// records leave in insertion order , so an evicted record is always at the front of its keys
+fn index_pop<K: Hash + Eq>(index: &mut Index<K>, key: K) {
+    if let Some(seqs) = index.get_mut(&key) {
+        seqs.pop_front();
+        if seqs.is_empty() {
+            index.remove(&key);
+        }
+    }
+}
+
+// every record the poller saw in the retention window , in arrival order
+pub struct HistoryStore {
+    pub retention: i64,
+    // ( received at , record ) , records[i] has sequence number first_seq + i
+    pub records: VecDeque<(i64, LogRecord)>,
+    pub first_seq: u64,
+    pub by_user: Index<u64>,
+    pub by_order: Index<u64>,
+    pub by_symbol: Index<u32>,
+    // records the hub skipped because the store fell behind
+    pub skipped: u64,
+}
+
+impl HistoryStore {
+    pub fn new(retention_ms: u64) -> Self {
+        Self {
+            retention: retention_ms as i64 * NANOS_PER_MILLI,
+            records: VecDeque::new(),
+            first_seq: 0,
+            by_user: HashMap::new(),
+            by_order: HashMap::new(),
+            by_symbol: HashMap::new(),
+            skipped: 0,
+        }
+    }
+
+    pub fn insert(&mut self, record: LogRecord, now: i64) {
+        let seq = self.first_seq + self.records.len() as u64;
+        if let Some(user_id) = record.user_id() {
+            index_push(&mut self.by_user, user_id, seq);
+        }
+        for order_id in record.order_ids().into_iter().flatten() {
+            index_push(&mut self.by_order, order_id, seq);
+        }
+        if let Some(symbol) = record.symbol() {
+            index_push(&mut self.by_symbol, symbol, seq);
+        }
+        self.records.push_back((now, record));
+    }
+
+    pub fn evict(&mut self, now: i64) {
+        while let Some((received, record)) = self.records.front().copied() {
+            if now - received < self.retention {
+                break;
+            }
+            if let Some(user_id) = record.user_id() {
+                index_pop(&mut self.by_user, user_id);
+            }
+            for order_id in record.order_ids().into_iter().flatten() {
+                index_pop(&mut self.by_order, order_id);
+            }
+            if let Some(symbol) = record.symbol() {
+                index_pop(&mut self.by_symbol, symbol);
+            }
+            self.records.pop_front();
+            self.first_seq += 1;
+        }
+    }
+
+    fn get(&self, seq: u64) -> Option<&LogRecord> {
+        self.records.get(seq.checked_sub(self.first_seq)? as usize).map(|(_, r)| r)
+    }
+
+    // the newest `limit` matches , returned oldest first
+    fn latest<'a>(
+        &'a self,
+        records: impl DoubleEndedIterator<Item = &'a LogRecord>,
+        stream: Option<Stream>,
+        limit: usize,
+    ) -> Vec<LogRecord> {
+        let mut found: Vec<LogRecord> = records
+            .rev()
+            .filter(|r| stream.is_none_or(|s| r.stream() == s))
+            .take(limit)
+            .copied()
+            .collect();
+        found.reverse();
+        found
+    }
+
+    fn lookup(&self, seqs: Option<&VecDeque<u64>>, stream: Option<Stream>, limit: usize) -> Vec<LogRecord> {
+        match seqs {
+            Some(seqs) => self.latest(seqs.iter().filter_map(|seq| self.get(*seq)), stream, limit),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn by_user(&self, user_id: u64, stream: Option<Stream>, limit: usize) -> Vec<LogRecord> {
+        self.lookup(self.by_user.get(&user_id), stream, limit)
+    }
+
+    pub fn by_order(&self, order_id: u64, stream: Option<Stream>, limit: usize) -> Vec<LogRecord> {
+        self.lookup(self.by_order.get(&order_id), stream, limit)
+    }
+
+    pub fn by_symbol(&self, symbol: u32, stream: Option<Stream>, limit: usize) -> Vec<LogRecord> {
+        self.lookup(self.by_symbol.get(&symbol), stream, limit)
+    }
+
+    pub fn recent(&self, stream: Option<Stream>, limit: usize) -> Vec<LogRecord> {
+        self.latest(self.records.iter().map(|(_, r)| r), stream, limit)
+    }
+
+    pub fn stats(&self) -> serde_json::Value {
+        let mut per_stream: HashMap<&'static str, u64> = HashMap::new();
+        for (_, record) in &self.records {
+            *per_stream.entry(record.stream().table()).or_default() += 1;
+        }
+        serde_json::json!({
+            "records": self.records.len(),
+            "per_stream": per_stream,
+            "oldest": self.records.front().map(|(at, _)| serde_fields::format_timestamp(*at)),
+            "users": self.by_user.len(),
+            "orders": self.by_order.len(),
+            "symbols": self.by_symbol.len(),
+            "skipped": self.skipped,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Answer<'a> {
+    count: usize,
+    records: &'a [LogRecord],
+}
+
+pub type SharedHistory = Arc<RwLock<HistoryStore>>;
+
+fn json_response(status: u16, body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn parse_stream(raw: &str) -> Result<Stream, String> {
+    Stream::ALL
+        .iter()
+        .find(|s| s.table() == raw)
+        .copied()
+        .ok_or_else(|| format!("unknown stream {}", raw))
+}
+
+// GET /orders/<id> , /users/<id> , /symbols/<id> , /recent , /stats
+// all but /stats take ?stream=<table>&limit=<n>
+fn answer(store: &HistoryStore, url: &str, max_limit: usize) -> Result<String, (u16, String)> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut stream = None;
+    let mut limit = 100;
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        match name {
+            "stream" => stream = Some(parse_stream(value).map_err(|e| (400, e))?),
+            "limit" => limit = value.parse().map_err(|_| (400, format!("bad limit {}", value)))?,
+            _ => return Err((400, format!("unknown parameter {}", name))),
+        }
+    }
+    let limit = limit.min(max_limit);
+
+    let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+    // symbols are u32 , anything wider is a bad id rather than a different symbol
+    fn id<T: std::str::FromStr>(raw: &str) -> Result<T, (u16, String)> {
+        raw.parse().map_err(|_| (400, format!("bad id {}", raw)))
+    }
+    let records = match parts.as_slice() {
+        ["stats"] => return Ok(store.stats().to_string()),
+        ["recent"] => store.recent(stream, limit),
+        ["orders", order_id] => store.by_order(id(order_id)?, stream, limit),
+        ["users", user_id] => store.by_user(id(user_id)?, stream, limit),
+        ["symbols", symbol] => store.by_symbol(id(symbol)?, stream, limit),
+        _ => return Err((404, format!("no route {}", path))),
+    };
+    serde_json::to_string(&Answer { count: records.len(), records: &records }).map_err(|e| (500, e.to_string()))
+}
+
+fn respond(history: &SharedHistory, request: Request, max_limit: usize) {
+    let response = if *request.method() != Method::Get {
+        json_response(405, serde_json::json!({ "error": "only GET is served" }).to_string())
+    } else {
+        let store = history.read().unwrap();
+        match answer(&store, request.url(), max_limit) {
+            Ok(body) => json_response(200, body),
+            Err((status, error)) => json_response(status, serde_json::json!({ "error": error }).to_string()),
+        }
+    };
+    let _ = request.respond(response);
+}
+
+pub fn start(config: HistoryConfig, hub: Hub) {
+    let server = match Server::http(&config.addr) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("history: cannot bind {}: {}", config.addr, e);
+            return;
+        }
+    };
+
+    let history: SharedHistory = Arc::new(RwLock::new(HistoryStore::new(config.retention_ms)));
+    let filter = Filter { streams: config.streams.clone(), ..Filter::default() };
+    // skip rather than disconnect , a slow query must not cost us the store
+    let subscription = hub.subscribe(filter, config.queue, WhenFull::Skip);
+    let store = history.clone();
+    std::thread::spawn(move || {
+        let mut batch = Vec::new();
+        loop {
+            // wake up now and then so a quiet store still ages out
+            match subscription.rx.recv_timeout(Duration::from_secs(1)) {
+                Ok(record) => batch.push(record),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            batch.extend(subscription.rx.try_iter());
+            let now = now_nanos();
+            let mut store = store.write().unwrap();
+            for record in batch.drain(..) {
+                store.insert(record, now);
+            }
+            store.evict(now);
+            store.skipped = subscription.skipped.load(Ordering::Relaxed);
+        }
+    });
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            respond(&history, request, config.max_limit);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{balance, order, trade};
+
+    const MS: i64 = NANOS_PER_MILLI;
+
+    fn seqs<K: Hash + Eq>(index: &Index<K>, key: K) -> Vec<u64> {
+        index.get(&key).map(|s| s.iter().copied().collect()).unwrap_or_default()
+    }
+
+    // records have no PartialEq , stream and order ids tell them apart here
+    fn keys(records: Vec<LogRecord>) -> Vec<(Stream, [Option<u64>; 2])> {
+        records.iter().map(|r| (r.stream(), r.order_ids())).collect()
+    }
+
+    #[test]
+    fn insert_indexes_every_key() {
+        let mut store = HistoryStore::new(1000);
+        store.insert(order(1, 9).into(), 0);
+        store.insert(balance(1, 9).into(), 0);
+        store.insert(trade(1, 2).into(), 0);
+
+        assert_eq!(seqs(&store.by_order, 1), [0, 1, 2]);
+        assert_eq!(seqs(&store.by_order, 2), [2]);
+        assert_eq!(seqs(&store.by_user, 9), [0, 1]);
+        assert_eq!(seqs(&store.by_symbol, 7), [0, 2]);
+        assert_eq!(keys(store.by_order(1, Some(Stream::TradeLogs), 10)), keys(vec![trade(1, 2).into()]));
+        assert_eq!(keys(store.by_user(9, None, 1)), keys(vec![balance(1, 9).into()]));
+    }
+
+    #[test]
+    fn evict_keeps_the_index_in_step() {
+        let mut store = HistoryStore::new(100);
+        store.insert(order(1, 9).into(), 0);
+        store.insert(order(2, 8).into(), 10 * MS);
+        store.insert(trade(1, 2).into(), 20 * MS);
+        store.insert(order(1, 9).into(), 150 * MS);
+
+        store.evict(115 * MS);
+        assert_eq!(store.first_seq, 2);
+        assert_eq!(store.records.len(), 2);
+        // what is left points at the surviving records
+        assert_eq!(seqs(&store.by_order, 1), [2, 3]);
+        assert_eq!(seqs(&store.by_order, 2), [2]);
+        assert_eq!(seqs(&store.by_symbol, 7), [2, 3]);
+        assert_eq!(seqs(&store.by_user, 9), [3]);
+        assert!(!store.by_user.contains_key(&8));
+        assert_eq!(keys(store.by_order(1, None, 10)), keys(vec![trade(1, 2).into(), order(1, 9).into()]));
+
+        store.evict(250 * MS);
+        assert!(store.records.is_empty());
+        assert!(store.by_order.is_empty() && store.by_user.is_empty() && store.by_symbol.is_empty());
+        assert_eq!(store.first_seq, 4);
+    }
+
+    #[test]
+    fn self_trade_is_indexed_once_under_its_order() {
+        let mut store = HistoryStore::new(100);
+        store.insert(trade(5, 5).into(), 0);
+        store.insert(order(5, 9).into(), 50 * MS);
+        assert_eq!(seqs(&store.by_order, 5), [0, 1]);
+
+        // popping the trade must not take the order's entry with it
+        store.evict(100 * MS);
+        assert_eq!(seqs(&store.by_order, 5), [1]);
+        assert_eq!(keys(store.by_order(5, None, 10)), keys(vec![order(5, 9).into()]));
+    }
+
+    #[test]
+    fn answer_routes_and_rejects() {
+        let mut store = HistoryStore::new(1000);
+        store.insert(order(1, 9).into(), 0);
+        store.insert(trade(1, 2).into(), 0);
+
+        let count = |url: &str, max_limit: usize| {
+            let body: serde_json::Value = serde_json::from_str(&answer(&store, url, max_limit).unwrap()).unwrap();
+            body["count"].as_u64().unwrap()
+        };
+        assert_eq!(count("/symbols/7?stream=trade_logs", 10), 1);
+        assert_eq!(count("/symbols/7", 10), 2);
+        // limit is capped at max_limit
+        assert_eq!(count("/recent?limit=50", 1), 1);
+
+        // 2^32 + 7 must not wrap around to symbol 7
+        assert_eq!(answer(&store, "/symbols/4294967303", 10).unwrap_err().0, 400);
+        assert_eq!(answer(&store, "/users/x", 10).unwrap_err().0, 400);
+        assert_eq!(answer(&store, "/recent?stream=nope", 10).unwrap_err().0, 400);
+        assert_eq!(answer(&store, "/nope", 10).unwrap_err().0, 404);
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crossbeam::channel::{Receiver, Sender, TrySendError};
+use serde::Deserialize;
+
+use crate::logger::serde_fields;
+use crate::logger::types::{severity_str, LogRecord, Stream};
+
+// which records a subscriber wants , an empty list lets everything through
+// a filter on a field the record does not have ( user_id on a trade ) drops the record
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    pub streams: Vec<Stream>,
+    pub symbols: Vec<u32>,
+    pub user_ids: Vec<u64>,
+    // "info" , "error" , "debug"
+    pub severities: Vec<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        (self.streams.is_empty() || self.streams.contains(&record.stream()))
+            && (self.symbols.is_empty() || record.symbol().is_some_and(|s| self.symbols.contains(&s)))
+            && (self.user_ids.is_empty() || record.user_id().is_some_and(|u| self.user_ids.contains(&u)))
+            && (self.severities.is_empty()
+                || record.severity().is_some_and(|s| self.severities.iter().any(|name| name == severity_str(s))))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhenFull {
+    // the subscriber is dropped , its receiver sees the channel disconnect
+    Disconnect,
+    // the record is skipped for this subscriber only
+    Skip,
+}
+
+#[derive(Clone)]
+struct Subscriber {
+    id: u64,
+    filter: Filter,
+    tx: Sender<LogRecord>,
+    when_full: WhenFull,
+    skipped: Arc<AtomicU64>,
+}
+
+pub struct Subscription {
+    pub id: u64,
+    pub rx: Receiver<LogRecord>,
+    // records left out because the queue was full , WhenFull::Skip only
+    pub skipped: Arc<AtomicU64>,
+}
+
+// fan out of every record the poller dequeues , to the live consumers ( tail , websocket , history )
+#[derive(Clone, Default)]
+pub struct Hub {
+    // copied on every change , publish only clones the Arc so the poller never waits on a client
+    subscribers: Arc<RwLock<Arc<Vec<Subscriber>>>>,
+    // lets the poller skip the lock while nobody listens
+    active: Arc<AtomicUsize>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn change(&self, f: impl FnOnce(&mut Vec<Subscriber>)) {
+        let mut current = self.subscribers.write().unwrap();
+        let mut subscribers = Vec::clone(&current);
+        f(&mut subscribers);
+        self.active.store(subscribers.len(), Ordering::Release);
+        *current = Arc::new(subscribers);
+    }
+
+    pub fn subscribe(&self, filter: Filter, capacity: usize, when_full: WhenFull) -> Subscription {
+        let (tx, rx) = crossbeam::channel::bounded(capacity.max(1));
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let skipped = Arc::new(AtomicU64::new(0));
+        let subscriber = Subscriber { id, filter, tx, when_full, skipped: skipped.clone() };
+        self.change(|subscribers| subscribers.push(subscriber));
+        Subscription { id, rx, skipped }
+    }
+
+    // records already queued for the subscriber stay queued
+    pub fn update_filter(&self, id: u64, filter: Filter) {
+        self.change(|subscribers| {
+            if let Some(s) = subscribers.iter_mut().find(|s| s.id == id) {
+                s.filter = filter;
+            }
+        });
+    }
+
+    pub fn unsubscribe(&self, id: u64) {
+        self.change(|subscribers| subscribers.retain(|s| s.id != id));
+    }
+
+    #[inline(always)]
+    pub fn publish(&self, record: LogRecord) {
+        if self.active.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        let subscribers = self.subscribers.read().unwrap().clone();
+        let mut gone = Vec::new();
+        for s in subscribers.iter() {
+            if !s.filter.matches(&record) {
+                continue;
+            }
+            match s.tx.try_send(record) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) if s.when_full == WhenFull::Skip => {
+                    s.skipped.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Full(_)) => {
+                    eprintln!(
+                        "hub: subscriber {} fell behind at {} , disconnecting",
+                        s.id, serde_fields::format_timestamp(record.timestamp())
+                    );
+                    gone.push(s.id);
+                }
+                // the consumer went away
+                Err(TrySendError::Disconnected(_)) => gone.push(s.id),
+            }
+        }
+        // our copy holds the senders too , drop it so the receivers see the disconnect
+        drop(subscribers);
+        if !gone.is_empty() {
+            self.change(|subscribers| subscribers.retain(|s| !gone.contains(&s.id)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{balance, trade};
+
+    #[test]
+    fn empty_filter_lets_everything_through() {
+        let filter = Filter::default();
+        assert!(filter.matches(&trade(1, 2).into()) && filter.matches(&balance(1, 9).into()));
+    }
+
+    #[test]
+    fn filter_on_a_missing_field_drops_the_record() {
+        let by_user = Filter { user_ids: vec![9], ..Filter::default() };
+        assert!(by_user.matches(&balance(1, 9).into()));
+        assert!(!by_user.matches(&balance(1, 8).into()));
+        // trades carry no user id
+        assert!(!by_user.matches(&trade(1, 2).into()));
+
+        // balance deltas carry no symbol
+        let by_symbol = Filter { symbols: vec![7], ..Filter::default() };
+        assert!(by_symbol.matches(&trade(1, 2).into()) && !by_symbol.matches(&balance(1, 9).into()));
+
+        let errors = Filter { severities: vec!["error".to_string()], ..Filter::default() };
+        assert!(errors.matches(&balance(1, 9).severity(1).into()));
+        assert!(!errors.matches(&balance(1, 9).into()) && !errors.matches(&trade(1, 2).into()));
+    }
+
+    #[test]
+    fn every_list_has_to_match() {
+        let filter = Filter { streams: vec![Stream::BalanceLogs], user_ids: vec![9], ..Filter::default() };
+        assert!(filter.matches(&balance(1, 9).into()));
+        assert!(!filter.matches(&trade(1, 2).into()));
+    }
+
+    #[test]
+    fn full_queue_skips_or_disconnects() {
+        let hub = Hub::new();
+        let skip = hub.subscribe(Filter::default(), 1, WhenFull::Skip);
+        let disconnect = hub.subscribe(Filter::default(), 1, WhenFull::Disconnect);
+        hub.publish(trade(1, 2).symbol(1).into());
+        hub.publish(trade(1, 2).symbol(2).into());
+        hub.publish(trade(1, 2).symbol(3).into());
+
+        // the skipping subscriber keeps the first record and stays subscribed
+        assert_eq!(skip.skipped.load(Ordering::Relaxed), 2);
+        assert!(matches!(skip.rx.try_recv(), Ok(LogRecord::TradeLog(t)) if t.symbol == 1));
+        hub.publish(trade(1, 2).symbol(4).into());
+        assert!(matches!(skip.rx.try_recv(), Ok(LogRecord::TradeLog(t)) if t.symbol == 4));
+
+        // the other one drains what it had and then sees the disconnect
+        assert!(matches!(disconnect.rx.try_recv(), Ok(LogRecord::TradeLog(t)) if t.symbol == 1));
+        assert!(disconnect.rx.try_recv().unwrap_err().is_disconnected());
+        assert_eq!(hub.active.load(Ordering::Acquire), 1);
+    }
+
+    #[test]
+    fn filtered_out_records_never_fill_the_queue() {
+        let hub = Hub::new();
+        let subscription = hub.subscribe(Filter { symbols: vec![7], ..Filter::default() }, 1, WhenFull::Disconnect);
+        for _ in 0..10 {
+            hub.publish(trade(1, 2).symbol(1).into());
+        }
+        hub.publish(trade(1, 2).symbol(7).into());
+        assert!(matches!(subscription.rx.try_recv(), Ok(LogRecord::TradeLog(t)) if t.symbol == 7));
+
+        hub.unsubscribe(subscription.id);
+        assert!(subscription.rx.try_recv().unwrap_err().is_disconnected());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod history;
+pub mod hub;
+pub mod tail;
+pub mod ws;
@@ -0,0 +1,317 @@
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crossbeam::channel::RecvTimeoutError;
+use serde::Deserialize;
+
+use crate::logger::types::LogRecord;
+use crate::server::hub::{Filter, Hub, WhenFull};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TailConfig {
+    pub unix_socket: Option<PathBuf>,
+    // keep this on loopback , there is no auth
+    pub tcp: Option<String>,
+    // records a client may fall behind by before it is disconnected
+    pub queue: usize,
+}
+
+impl Default for TailConfig {
+    fn default() -> Self {
+        Self { unix_socket: Some(PathBuf::from("/tmp/logger-tail.sock")), tcp: None, queue: 65536 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TailFormat {
+    // one {"stream":..,"record":{..}} object per line , same as the jsonl sink
+    #[default]
+    Json,
+    // [u32 LE frame length][u8 stream index][fields LE] , see encode_binary
+    Binary,
+}
+
+// first line a client sends , e.g. {"streams":["order_logs"],"user_ids":[9],"format":"binary"}
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TailRequest {
+    #[serde(flatten)]
+    pub filter: Filter,
+    pub format: TailFormat,
+}
+
+// fields in declaration order of the wrappers , integers little endian , bools as one byte
+pub fn encode_binary(record: &LogRecord, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    out.push(record.stream().index() as u8);
+    match record {
+        LogRecord::OrderLog(log) => {
+            let d = &log.order_delta;
+            out.extend_from_slice(&log.timestamp.to_le_bytes());
+            out.extend_from_slice(&d.event_id.to_le_bytes());
+            out.extend_from_slice(&d.order_id.to_le_bytes());
+            out.extend_from_slice(&d.user_id.to_le_bytes());
+            out.extend_from_slice(&d.price.to_le_bytes());
+            out.extend_from_slice(&d.symbol.to_le_bytes());
+            out.extend_from_slice(&d.shares_qty.to_le_bytes());
+            out.extend_from_slice(&[d.side, d.order_event_type, log.severity]);
+        }
+        LogRecord::BalanceLog(log) => {
+            let d = &log.balance_delta;
+            out.extend_from_slice(&log.timestamp.to_le_bytes());
+            out.extend_from_slice(&d.event_id.to_le_bytes());
+            out.extend_from_slice(&d.user_id.to_le_bytes());
+            out.extend_from_slice(&d.delta_available.to_le_bytes());
+            out.extend_from_slice(&d.delta_reserved.to_le_bytes());
+            out.extend_from_slice(&d.order_id.to_le_bytes());
+            out.extend_from_slice(&[d.reason, log.severity]);
+        }
+        LogRecord::HoldingLog(log) => {
+            let d = &log.holding_delta;
+            out.extend_from_slice(&log.timestamp.to_le_bytes());
+            out.extend_from_slice(&d.order_id.to_le_bytes());
+            out.extend_from_slice(&d.event_id.to_le_bytes());
+            out.extend_from_slice(&d.user_id.to_le_bytes());
+            out.extend_from_slice(&d.symbol.to_le_bytes());
+            out.extend_from_slice(&d.delta_available.to_le_bytes());
+            out.extend_from_slice(&d.delta_reserved.to_le_bytes());
+            out.extend_from_slice(&[d.reason, log.severity]);
+        }
+        LogRecord::TradeLog(trade) => {
+            out.extend_from_slice(&trade.timestamp.to_le_bytes());
+            out.extend_from_slice(&trade.buyer_order_id.to_le_bytes());
+            out.extend_from_slice(&trade.seller_order_id.to_le_bytes());
+            out.extend_from_slice(&trade.price.to_le_bytes());
+            out.extend_from_slice(&trade.symbol.to_le_bytes());
+            out.extend_from_slice(&trade.quantity.to_le_bytes());
+            out.push(trade.is_buyer_maker as u8);
+        }
+        LogRecord::Snapshot(snap) => {
+            out.extend_from_slice(&snap.timestamp.to_le_bytes());
+            out.extend_from_slice(&snap.event_id.to_le_bytes());
+            out.extend_from_slice(&snap.symbol.to_le_bytes());
+            // all 20 levels per side , empty ones are zero
+            for (price, qty) in snap.bids.iter().chain(snap.asks.iter()) {
+                out.extend_from_slice(&price.to_le_bytes());
+                out.extend_from_slice(&qty.to_le_bytes());
+            }
+        }
+    }
+    // the length covers everything after itself
+    let len = (out.len() - start - 4) as u32;
+    out[start..start + 4].copy_from_slice(&len.to_le_bytes());
+}
+
+// a client whose filter matches nothing gets no writes , so nothing would notice it left
+const PROBE_EVERY: Duration = Duration::from_secs(1);
+
+// the sockets a client connects on
+pub trait Conn: Read + Write {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Conn for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Conn for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+// clients send nothing after the subscription , a read of 0 bytes means the client hung up
+// and a timed out read that it is still there
+fn hung_up<S: Conn>(conn: &mut S) -> bool {
+    let mut buf = [0u8; 256];
+    match conn.read(&mut buf) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(e) => !matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted),
+    }
+}
+
+// serves one client until it hangs up or falls behind
+fn serve<S: Conn>(hub: &Hub, stream: S, queue: usize, peer: &str) {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line).is_err() {
+        return;
+    }
+    let mut writer = BufWriter::new(reader.into_inner());
+    let request: TailRequest = match serde_json::from_str(line.trim()) {
+        Ok(request) => request,
+        Err(e) => {
+            let _ = writeln!(writer, "{}", serde_json::json!({ "error": format!("bad subscription: {}", e) }));
+            return;
+        }
+    };
+
+    if writer.get_ref().set_read_timeout(Some(Duration::from_millis(1))).is_err() {
+        return;
+    }
+
+    let subscription = hub.subscribe(request.filter, queue, WhenFull::Disconnect);
+    let mut frame = Vec::new();
+    loop {
+        let record = match subscription.rx.recv_timeout(PROBE_EVERY) {
+            Ok(record) => record,
+            Err(RecvTimeoutError::Timeout) if hung_up(writer.get_mut()) => break,
+            Err(RecvTimeoutError::Timeout) => continue,
+            // the hub drops our sender when we fall behind
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        frame.clear();
+        match request.format {
+            TailFormat::Json => {
+                if serde_json::to_writer(&mut frame, &record).is_err() {
+                    continue;
+                }
+                frame.push(b'\n');
+            }
+            TailFormat::Binary => encode_binary(&record, &mut frame),
+        }
+        if writer.write_all(&frame).is_err() {
+            break;
+        }
+        // batch writes while records keep coming , flush once the queue drains
+        if subscription.rx.is_empty() && writer.flush().is_err() {
+            break;
+        }
+    }
+    hub.unsubscribe(subscription.id);
+    eprintln!("tail: {} disconnected", peer);
+}
+
+// accept loops on their own threads , one thread per client
+pub fn start(config: TailConfig, hub: Hub) {
+    if let Some(path) = config.unix_socket.clone() {
+        let _ = std::fs::remove_file(&path);
+        match UnixListener::bind(&path) {
+            Ok(listener) => {
+                let hub = hub.clone();
+                let queue = config.queue;
+                std::thread::spawn(move || {
+                    for (n, conn) in listener.incoming().enumerate() {
+                        let Ok(conn) = conn else { continue };
+                        let hub = hub.clone();
+                        let peer = format!("{}#{}", path.display(), n);
+                        std::thread::spawn(move || serve(&hub, conn, queue, &peer));
+                    }
+                });
+            }
+            Err(e) => eprintln!("tail: cannot bind {}: {}", path.display(), e),
+        }
+    }
+    if let Some(addr) = config.tcp.clone() {
+        match TcpListener::bind(&addr) {
+            Ok(listener) => {
+                let queue = config.queue;
+                std::thread::spawn(move || {
+                    for conn in listener.incoming() {
+                        let Ok(conn) = conn else { continue };
+                        let _ = conn.set_nodelay(true);
+                        let hub = hub.clone();
+                        let peer = conn.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                        std::thread::spawn(move || serve(&hub, conn, queue, &peer));
+                    }
+                });
+            }
+            Err(e) => eprintln!("tail: cannot bind {}: {}", addr, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::{balance, holding, order, snapshot};
+    use crate::logger::types::{Stream, TradeLogs};
+
+    fn frame(record: &LogRecord) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_binary(record, &mut out);
+        out
+    }
+
+    fn length(frame: &[u8]) -> usize {
+        u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn trade_frame_layout() {
+        let trade = TradeLogs {
+            timestamp: -2,
+            buyer_order_id: 3,
+            seller_order_id: 4,
+            price: 5,
+            symbol: 6,
+            quantity: 7,
+            is_buyer_maker: true,
+        };
+        let frame = frame(&LogRecord::TradeLog(trade));
+        let mut expected = 42u32.to_le_bytes().to_vec();
+        expected.push(Stream::TradeLogs.index() as u8);
+        expected.extend_from_slice(&(-2i64).to_le_bytes());
+        for field in [3u64, 4, 5] {
+            expected.extend_from_slice(&field.to_le_bytes());
+        }
+        expected.extend_from_slice(&6u32.to_le_bytes());
+        expected.extend_from_slice(&7u32.to_le_bytes());
+        expected.push(1);
+        assert_eq!(frame, expected);
+    }
+
+    #[test]
+    fn frame_lengths_per_stream() {
+        let order = order(3, 4).side(1).event(2).severity(1);
+        let balance = balance(6, 3).deltas(-4, 5).reason(1);
+        let holding = holding(2, 4).deltas(-6, 7);
+        let snap = snapshot(&[(3, 4); 20], &[(5, 6); 20]);
+
+        for (record, len) in [
+            (LogRecord::OrderLog(order), 52),
+            (LogRecord::BalanceLog(balance), 51),
+            (LogRecord::HoldingLog(holding), 47),
+            (LogRecord::Snapshot(snap), 501),
+        ] {
+            let frame = frame(&record);
+            assert_eq!(length(&frame), len, "{:?}", record.stream());
+            assert_eq!(frame.len(), len + 4);
+            assert_eq!(frame[4], record.stream().index() as u8);
+        }
+
+        // the trailing bytes of an order frame are side , event type and severity
+        let frame = frame(&LogRecord::OrderLog(order));
+        assert_eq!(frame[frame.len() - 3..], [1, 2, 1]);
+    }
+
+    #[test]
+    fn frames_are_appended() {
+        let snap = snapshot(&[], &[]);
+        let mut out = vec![0xff];
+        encode_binary(&LogRecord::Snapshot(snap), &mut out);
+        encode_binary(&LogRecord::Snapshot(snap), &mut out);
+        assert_eq!(out.len(), 1 + 2 * 505);
+        assert_eq!(length(&out[1..]), 501);
+        assert_eq!(length(&out[506..]), 501);
+    }
+
+    #[test]
+    fn request_line_carries_filter_and_format() {
+        let line = r#"{"streams":["order_logs"],"user_ids":[9],"format":"binary"}"#;
+        let request: TailRequest = serde_json::from_str(line).unwrap();
+        assert_eq!(request.format, TailFormat::Binary);
+        assert_eq!(request.filter.streams, [Stream::OrderLogs]);
+        assert_eq!(request.filter.user_ids, [9]);
+        assert_eq!(serde_json::from_str::<TailRequest>("{}").unwrap().format, TailFormat::Json);
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tungstenite::{Message, WebSocket};
+
+use crate::logger::processor::{now_nanos, NANOS_PER_MILLI};
+use crate::logger::serde_fields;
+use crate::logger::types::{LogRecord, OrderBookSnapShot, Stream, TradeLogs};
+use crate::server::hub::{Filter, Hub, Subscription, WhenFull};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WsConfig {
+    // keep this on loopback , there is no auth
+    pub addr: String,
+    // at most one book update per symbol per client in this interval , the latest wins
+    pub throttle_ms: u64,
+    // records a client may fall behind by before it is disconnected
+    pub queue: usize,
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self { addr: "127.0.0.1:9011".to_string(), throttle_ms: 100, queue: 16384 }
+    }
+}
+
+// what clients send , {"op":"subscribe","symbols":[1,2]}
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum ClientRequest {
+    Subscribe { symbols: Vec<u32> },
+    Unsubscribe { symbols: Vec<u32> },
+}
+
+// what clients receive , levels are [price, qty] best first with the empty ones dropped
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FeedMessage<'a> {
+    Book {
+        symbol: u32,
+        #[serde(with = "serde_fields::timestamp")]
+        timestamp: i64,
+        event_id: u64,
+        bids: Vec<(u64, u32)>,
+        asks: Vec<(u64, u32)>,
+        // the book as it was when the subscription came in
+        initial: bool,
+    },
+    Trade(&'a TradeLogs),
+    Error { message: String },
+}
+
+fn decoded_levels(side: &[(u64, u32); 20], descending: bool) -> Vec<(u64, u32)> {
+    let mut levels: Vec<(u64, u32)> = side.iter().filter(|(_, q)| *q > 0).copied().collect();
+    if descending {
+        levels.sort_by_key(|l| std::cmp::Reverse(l.0));
+    } else {
+        levels.sort_by_key(|l| l.0);
+    }
+    levels
+}
+
+pub fn book_message(snap: &OrderBookSnapShot, initial: bool) -> FeedMessage<'static> {
+    FeedMessage::Book {
+        symbol: snap.symbol,
+        timestamp: snap.timestamp,
+        event_id: snap.event_id,
+        bids: decoded_levels(&snap.bids, true),
+        asks: decoded_levels(&snap.asks, false),
+        initial,
+    }
+}
+
+// latest snapshot per symbol , so a new subscriber gets the book right away
+pub type BookCache = Arc<Mutex<HashMap<u32, OrderBookSnapShot>>>;
+
+// per client subscription and throttle , kept apart from the socket
+struct FeedState {
+    symbols: Vec<u32>,
+    subscription: Option<Subscription>,
+    throttle: i64,
+    last_sent: HashMap<u32, i64>,
+    // books held back by the throttle
+    held: HashMap<u32, OrderBookSnapShot>,
+}
+
+impl FeedState {
+    fn new(throttle: i64) -> Self {
+        Self { symbols: Vec::new(), subscription: None, throttle, last_sent: HashMap::new(), held: HashMap::new() }
+    }
+
+    fn update_subscription(&mut self, hub: &Hub, queue: usize) {
+        self.held.retain(|symbol, _| self.symbols.contains(symbol));
+        // an empty symbol list would let every symbol through
+        if self.symbols.is_empty() {
+            if let Some(old) = self.subscription.take() {
+                hub.unsubscribe(old.id);
+            }
+            return;
+        }
+        let filter = Filter {
+            streams: vec![Stream::Snapshots, Stream::TradeLogs],
+            symbols: self.symbols.clone(),
+            ..Filter::default()
+        };
+        match &self.subscription {
+            Some(subscription) => hub.update_filter(subscription.id, filter),
+            None => self.subscription = Some(hub.subscribe(filter, queue, WhenFull::Disconnect)),
+        }
+    }
+
+    // the cached books of the newly added symbols , sent right away as initial
+    fn subscribe(
+        &mut self,
+        symbols: Vec<u32>,
+        hub: &Hub,
+        cache: &BookCache,
+        queue: usize,
+        now: i64,
+    ) -> Vec<OrderBookSnapShot> {
+        let added: Vec<u32> = symbols.into_iter().filter(|s| !self.symbols.contains(s)).collect();
+        self.symbols.extend(&added);
+        self.update_subscription(hub, queue);
+        let initial: Vec<OrderBookSnapShot> = {
+            let cache = cache.lock().unwrap();
+            added.iter().filter_map(|s| cache.get(s).copied()).collect()
+        };
+        for snap in &initial {
+            self.last_sent.insert(snap.symbol, now);
+        }
+        initial
+    }
+
+    fn unsubscribe(&mut self, symbols: &[u32], hub: &Hub, queue: usize) {
+        self.symbols.retain(|s| !symbols.contains(s));
+        self.update_subscription(hub, queue);
+    }
+
+    fn due(&self, symbol: u32, now: i64) -> bool {
+        self.last_sent.get(&symbol).is_none_or(|last| now - last >= self.throttle)
+    }
+
+    // the book to send now , None when the throttle holds it back
+    fn on_book(&mut self, snap: OrderBookSnapShot, now: i64) -> Option<OrderBookSnapShot> {
+        if !self.due(snap.symbol, now) {
+            self.held.insert(snap.symbol, snap);
+            return None;
+        }
+        self.held.remove(&snap.symbol);
+        self.last_sent.insert(snap.symbol, now);
+        Some(snap)
+    }
+
+    // held books whose throttle window has passed
+    fn release_held(&mut self, now: i64) -> Vec<OrderBookSnapShot> {
+        let due: Vec<u32> = self.held.keys().filter(|s| self.due(**s, now)).copied().collect();
+        due.into_iter()
+            .filter_map(|symbol| {
+                let snap = self.held.remove(&symbol).unwrap();
+                self.on_book(snap, now)
+            })
+            .collect()
+    }
+}
+
+struct Client {
+    ws: WebSocket<TcpStream>,
+    feed: FeedState,
+}
+
+impl Client {
+    fn send(&mut self, message: &FeedMessage) -> bool {
+        let Ok(text) = serde_json::to_string(message) else { return true };
+        self.ws.write(Message::text(text)).is_ok()
+    }
+
+    fn send_books(&mut self, books: Vec<OrderBookSnapShot>, initial: bool) -> bool {
+        books.iter().all(|snap| self.send(&book_message(snap, initial)))
+    }
+
+    fn handle_request(&mut self, request: ClientRequest, hub: &Hub, cache: &BookCache, queue: usize) -> bool {
+        match request {
+            ClientRequest::Subscribe { symbols } => {
+                let initial = self.feed.subscribe(symbols, hub, cache, queue, now_nanos());
+                self.send_books(initial, true)
+            }
+            ClientRequest::Unsubscribe { symbols } => {
+                self.feed.unsubscribe(&symbols, hub, queue);
+                true
+            }
+        }
+    }
+}
+
+fn serve(hub: Hub, cache: BookCache, stream: TcpStream, config: &WsConfig) {
+    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+    let ws = match tungstenite::accept(stream) {
+        Ok(ws) => ws,
+        Err(e) => {
+            eprintln!("ws: handshake with {} failed: {}", peer, e);
+            return;
+        }
+    };
+    // reads wake up often enough to keep the feed moving
+    let _ = ws.get_ref().set_read_timeout(Some(Duration::from_millis(5)));
+    let mut client = Client { ws, feed: FeedState::new(config.throttle_ms as i64 * NANOS_PER_MILLI) };
+
+    'serve: loop {
+        match client.ws.read() {
+            Ok(Message::Text(text)) => {
+                let ok = match serde_json::from_str::<ClientRequest>(text.as_str()) {
+                    Ok(request) => client.handle_request(request, &hub, &cache, config.queue),
+                    Err(e) => client.send(&FeedMessage::Error { message: format!("bad request: {}", e) }),
+                };
+                if !ok {
+                    break;
+                }
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+
+        let now = now_nanos();
+        if let Some(subscription) = &client.feed.subscription {
+            let rx = subscription.rx.clone();
+            loop {
+                // records queued before an unsubscribe are still in the channel
+                let ok = match rx.try_recv() {
+                    Ok(LogRecord::Snapshot(snap)) if client.feed.symbols.contains(&snap.symbol) => {
+                        match client.feed.on_book(snap, now) {
+                            Some(snap) => client.send(&book_message(&snap, false)),
+                            None => true,
+                        }
+                    }
+                    Ok(LogRecord::TradeLog(trade)) if client.feed.symbols.contains(&trade.symbol) => {
+                        client.send(&FeedMessage::Trade(&trade))
+                    }
+                    Ok(_) => true,
+                    Err(crossbeam::channel::TryRecvError::Empty) => break,
+                    // the hub dropped us , we fell behind
+                    Err(crossbeam::channel::TryRecvError::Disconnected) => {
+                        let _ = client.send(&FeedMessage::Error { message: "slow consumer".to_string() });
+                        let _ = client.ws.close(None);
+                        let _ = client.ws.flush();
+                        client.feed.subscription = None;
+                        break 'serve;
+                    }
+                };
+                if !ok {
+                    break 'serve;
+                }
+            }
+        }
+        let released = client.feed.release_held(now);
+        if !client.send_books(released, false) {
+            break;
+        }
+        match client.ws.flush() {
+            Ok(()) => {}
+            Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => break,
+        }
+    }
+    if let Some(subscription) = client.feed.subscription.take() {
+        hub.unsubscribe(subscription.id);
+    }
+    eprintln!("ws: {} disconnected", peer);
+}
+
+pub fn start(config: WsConfig, hub: Hub) {
+    let listener = match TcpListener::bind(&config.addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("ws: cannot bind {}: {}", config.addr, e);
+            return;
+        }
+    };
+
+    let cache: BookCache = Arc::default();
+    let books = hub.subscribe(
+        Filter { streams: vec![Stream::Snapshots], ..Filter::default() },
+        config.queue,
+        WhenFull::Skip,
+    );
+    let feed = cache.clone();
+    std::thread::spawn(move || {
+        while let Ok(record) = books.rx.recv() {
+            if let LogRecord::Snapshot(snap) = record {
+                feed.lock().unwrap().insert(snap.symbol, snap);
+            }
+        }
+    });
+
+    std::thread::spawn(move || {
+        for conn in listener.incoming() {
+            let Ok(conn) = conn else { continue };
+            let _ = conn.set_nodelay(true);
+            let (hub, cache, config) = (hub.clone(), cache.clone(), config.clone());
+            std::thread::spawn(move || serve(hub, cache, conn, &config));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::types::test_records::snapshot;
+
+    const MS: i64 = NANOS_PER_MILLI;
+
+    fn snap(symbol: u32, event_id: u64) -> OrderBookSnapShot {
+        snapshot(&[(100, 1), (101, 2)], &[]).symbol(symbol).event_id(event_id)
+    }
+
+    fn feed(hub: &Hub, cache: &BookCache, symbols: Vec<u32>) -> FeedState {
+        let mut feed = FeedState::new(100 * MS);
+        feed.subscribe(symbols, hub, cache, 16, 0);
+        feed
+    }
+
+    #[test]
+    fn subscribe_returns_the_cached_books_of_new_symbols_only() {
+        let hub = Hub::new();
+        let cache: BookCache = Arc::default();
+        cache.lock().unwrap().insert(1, snap(1, 10));
+        cache.lock().unwrap().insert(2, snap(2, 20));
+
+        let mut feed = FeedState::new(100 * MS);
+        let initial = feed.subscribe(vec![1, 3], &hub, &cache, 16, 0);
+        assert_eq!(initial.iter().map(|s| s.event_id).collect::<Vec<_>>(), [10]);
+        // already subscribed to 1 , only 2 is new
+        let initial = feed.subscribe(vec![1, 2], &hub, &cache, 16, 0);
+        assert_eq!(initial.iter().map(|s| s.event_id).collect::<Vec<_>>(), [20]);
+        assert_eq!(feed.symbols, [1, 3, 2]);
+
+        // the initial book starts the throttle window
+        assert!(feed.on_book(snap(1, 11), 50 * MS).is_none());
+    }
+
+    #[test]
+    fn throttle_holds_books_and_the_latest_wins() {
+        let hub = Hub::new();
+        let cache: BookCache = Arc::default();
+        let mut feed = feed(&hub, &cache, vec![1, 2]);
+
+        assert_eq!(feed.on_book(snap(1, 1), 0).map(|s| s.event_id), Some(1));
+        assert!(feed.on_book(snap(1, 2), 10 * MS).is_none());
+        assert!(feed.on_book(snap(1, 3), 20 * MS).is_none());
+        // other symbols have their own window
+        assert_eq!(feed.on_book(snap(2, 4), 20 * MS).map(|s| s.event_id), Some(4));
+
+        assert!(feed.release_held(99 * MS).is_empty());
+        let released = feed.release_held(100 * MS);
+        assert_eq!(released.iter().map(|s| s.event_id).collect::<Vec<_>>(), [3]);
+        assert!(feed.held.is_empty());
+        assert!(feed.release_held(300 * MS).is_empty());
+    }
+
+    #[test]
+    fn a_due_book_replaces_the_held_one() {
+        let hub = Hub::new();
+        let cache: BookCache = Arc::default();
+        let mut feed = feed(&hub, &cache, vec![1]);
+
+        feed.on_book(snap(1, 1), 0);
+        feed.on_book(snap(1, 2), 10 * MS);
+        assert_eq!(feed.on_book(snap(1, 3), 100 * MS).map(|s| s.event_id), Some(3));
+        assert!(feed.release_held(250 * MS).is_empty());
+    }
+
+    #[test]
+    fn unsubscribe_drops_held_books_and_the_subscription() {
+        let hub = Hub::new();
+        let cache: BookCache = Arc::default();
+        let mut feed = feed(&hub, &cache, vec![1, 2]);
+        feed.on_book(snap(1, 1), 0);
+        feed.on_book(snap(1, 2), 10 * MS);
+
+        feed.unsubscribe(&[1], &hub, 16);
+        assert!(feed.held.is_empty() && feed.subscription.is_some());
+        feed.unsubscribe(&[2], &hub, 16);
+        assert!(feed.subscription.is_none());
+    }
+
+    #[test]
+    fn book_message_drops_empty_levels_best_first() {
+        let FeedMessage::Book { bids, asks, initial, .. } = book_message(&snap(1, 1), true) else { unreachable!() };
+        assert_eq!(bids, [(101, 2), (100, 1)]);
+        assert!(asks.is_empty() && initial);
+    }
+}
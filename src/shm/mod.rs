@@ -2,5 +2,6 @@ pub mod balance_logs;
 pub mod holdings_logs;
 pub mod order_logs;
 pub mod poller;
+pub mod sequence;
 pub mod trade_logs;
 pub mod snapshot;
\ No newline at end of file
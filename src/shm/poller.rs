@@ -1,25 +1,39 @@
-use crate::{logger::types::{BalanceLogWrapper, HoldingLogWrapper, OrderBookSnapShot, OrderLogWrapper, TradeLogs}, shm::{balance_logs::BalanceLogQueue, holdings_logs::HoldingLogQueue, order_logs::OrderLogQueue, snapshot::OrderBookSnapShotQueue, trade_logs::TradeLogQueue}};
+use crate::{logger::types::{BalanceLogWrapper, HoldingLogWrapper, Ingested, LogRecord, OrderBookSnapShot, OrderLogWrapper, Stream, TradeLogs}, shm::{balance_logs::BalanceLogQueue, holdings_logs::HoldingLogQueue, order_logs::OrderLogQueue, sequence::{bump, EventGap, SequenceConfig, SequenceTracker, SharedCounters, StreamCounters}, snapshot::OrderBookSnapShotQueue, trade_logs::TradeLogQueue}, server::hub::Hub};
 use crossbeam::channel::Sender;
+
+// a full channel loses the record , the flusher reports the count in poller_counters
+#[inline(always)]
+fn forward<T>(sender: &Sender<Ingested<T>>, counters: &StreamCounters, record: T){
+    if sender.try_send(Ingested::new(record)).is_err(){
+        bump(&counters.dropped, 1);
+    }
+}
+
 pub struct LogPoller{
     pub order_log_queue   : OrderLogQueue,
-    pub order_log_sender  : Sender<OrderLogWrapper>,
+    pub order_log_sender  : Sender<Ingested<OrderLogWrapper>>,
     pub balance_log_queue : BalanceLogQueue,
-    pub balance_log_sender : Sender<BalanceLogWrapper>,
+    pub balance_log_sender : Sender<Ingested<BalanceLogWrapper>>,
     pub holding_log_queue : HoldingLogQueue,
-    pub holding_log_sender : Sender<HoldingLogWrapper>,
+    pub holding_log_sender : Sender<Ingested<HoldingLogWrapper>>,
     pub trade_log_queue    : TradeLogQueue,
-    pub trade_log_sender   : Sender<TradeLogs>,
+    pub trade_log_sender   : Sender<Ingested<TradeLogs>>,
     pub snapshot_queue      : OrderBookSnapShotQueue ,
-    pub snapshot_sender     : Sender<OrderBookSnapShot>,
-
+    pub snapshot_sender     : Sender<Ingested<OrderBookSnapShot>>,
+    pub sequence            : SequenceTracker,
+    pub gap_sender          : Sender<EventGap>,
+    // live consumers , only set when something subscribes ( tail server ... )
+    pub hub                 : Option<Hub>,
 }
 
 impl LogPoller{
-    pub fn new(order_log_sender  : Sender<OrderLogWrapper> , 
-        balance_log_sender : Sender<BalanceLogWrapper>,
-        holding_log_sender : Sender<HoldingLogWrapper>,
-        trade_log_sender   : Sender<TradeLogs>,
-        snapshot_sender     : Sender<OrderBookSnapShot>,
+    pub fn new(order_log_sender  : Sender<Ingested<OrderLogWrapper>> , 
+        balance_log_sender : Sender<Ingested<BalanceLogWrapper>>,
+        holding_log_sender : Sender<Ingested<HoldingLogWrapper>>,
+        trade_log_sender   : Sender<Ingested<TradeLogs>>,
+        snapshot_sender     : Sender<Ingested<OrderBookSnapShot>>,
+        gap_sender          : Sender<EventGap>,
+        sequence_config     : SequenceConfig,
     )->Self{
         let order_log_shm_queue = OrderLogQueue::open("/tmp/OrderLogs");
         let balance_log_shm_queue = BalanceLogQueue::open("/tmp/BalanceLogs");
@@ -51,26 +65,66 @@ impl LogPoller{
             trade_log_queue : trade_log_queue.unwrap(), 
             trade_log_sender , 
             snapshot_queue : snapshot_queue.unwrap() ,
-            snapshot_sender
+            snapshot_sender,
+            sequence : SequenceTracker::new(sequence_config, SharedCounters::default()),
+            gap_sender,
+            hub : None,
+        }
+    }
+
+    pub fn set_hub(&mut self, hub: Hub){
+        self.hub = Some(hub);
+    }
+
+    // the flusher reporting the counters holds the other end
+    pub fn set_counters(&mut self, counters: SharedCounters){
+        self.sequence.counters = counters;
+    }
+
+    #[inline(always)]
+    fn publish(&self, record: LogRecord){
+        if let Some(hub) = &self.hub {
+            hub.publish(record);
+        }
+    }
+
+    #[inline(always)]
+    fn check_sequence(&mut self, stream: Stream, source: u64, event_id: u64, timestamp: i64){
+        if let Some(gap) = self.sequence.observe(stream, source, event_id, timestamp)
+            && self.gap_sender.try_send(gap).is_err(){
+            bump(&self.sequence.counters[stream.index()].gap_rows_dropped, 1);
         }
     }
 
     pub fn run_poller(&mut self){
         loop {
             if let Ok(Some(balance_log))=self.balance_log_queue.dequeue(){
-                let _ = self.balance_log_sender.try_send(balance_log);
+                let delta = &balance_log.balance_delta;
+                self.check_sequence(Stream::BalanceLogs, delta.reason as u64, delta.event_id, balance_log.timestamp);
+                self.publish(LogRecord::BalanceLog(balance_log));
+                forward(&self.balance_log_sender, &self.sequence.counters[Stream::BalanceLogs.index()], balance_log);
             }
             if let Ok(Some(holding_log))=self.holding_log_queue.dequeue(){
-                let _ = self.holding_log_sender.try_send(holding_log);
+                let delta = &holding_log.holding_delta;
+                self.check_sequence(Stream::HoldingLogs, delta.reason as u64, delta.event_id, holding_log.timestamp);
+                self.publish(LogRecord::HoldingLog(holding_log));
+                forward(&self.holding_log_sender, &self.sequence.counters[Stream::HoldingLogs.index()], holding_log);
             }
             if let Ok(Some(order_log))=self.order_log_queue.dequeue(){
-                let _ = self.order_log_sender.try_send(order_log);
+                let delta = &order_log.order_delta;
+                self.check_sequence(Stream::OrderLogs, delta.order_event_type as u64, delta.event_id, order_log.timestamp);
+                self.publish(LogRecord::OrderLog(order_log));
+                forward(&self.order_log_sender, &self.sequence.counters[Stream::OrderLogs.index()], order_log);
             }
+            // trade logs carry no event id , nothing to check there
             if let Ok(Some(trade_log)) =self.trade_log_queue.dequeue(){
-                let _ = self.trade_log_sender.try_send(trade_log);
+                self.publish(LogRecord::TradeLog(trade_log));
+                forward(&self.trade_log_sender, &self.sequence.counters[Stream::TradeLogs.index()], trade_log);
             }
             if let Ok(Some(snapshot))= self.snapshot_queue.dequeue(){
-                let _ =self.snapshot_sender.try_send(snapshot);
+                self.check_sequence(Stream::Snapshots, snapshot.symbol as u64, snapshot.event_id, snapshot.timestamp);
+                self.publish(LogRecord::Snapshot(snapshot));
+                forward(&self.snapshot_sender, &self.sequence.counters[Stream::Snapshots.index()], snapshot);
             }
         }
     }
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::logger::types::Stream;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SequenceConfig {
+    pub enabled: bool,
+    // track event ids per source instead of per stream : order event type for order logs ,
+    // reason for balance / holding logs
+    pub per_source: bool,
+}
+
+impl Default for SequenceConfig {
+    fn default() -> Self {
+        Self { enabled: true, per_source: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapKind {
+    // ids expected .. got - 1 never showed up
+    Gap,
+    // got is older than the last id seen
+    OutOfOrder,
+    Duplicate,
+}
+
+impl GapKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GapKind::Gap => "gap",
+            GapKind::OutOfOrder => "out_of_order",
+            GapKind::Duplicate => "duplicate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EventGap {
+    pub stream: Stream,
+    pub source: Option<u64>,
+    pub kind: GapKind,
+    pub expected: u64,
+    pub got: u64,
+    // producer timestamp of the record that exposed the gap
+    pub timestamp: i64,
+}
+
+impl EventGap {
+    pub fn missing(&self) -> u64 {
+        match self.kind {
+            GapKind::Gap => self.got - self.expected,
+            _ => 0,
+        }
+    }
+}
+
+// totals since the poller started , the poller only adds and a flusher reports them
+#[derive(Debug, Default)]
+pub struct StreamCounters {
+    pub gaps: AtomicU64,
+    pub missing: AtomicU64,
+    pub out_of_order: AtomicU64,
+    pub duplicates: AtomicU64,
+    // records lost because the stream's flusher channel was full
+    pub dropped: AtomicU64,
+    // event_gaps rows lost because the gap channel was full
+    pub gap_rows_dropped: AtomicU64,
+}
+
+// indexed by Stream::index
+pub type SharedCounters = Arc<[StreamCounters; 5]>;
+
+#[inline(always)]
+pub fn bump(counter: &AtomicU64, by: u64) {
+    counter.fetch_add(by, Ordering::Relaxed);
+}
+
+pub struct SequenceTracker {
+    pub config: SequenceConfig,
+    // last event id per (stream , source)
+    pub last: HashMap<(Stream, Option<u64>), u64>,
+    pub counters: SharedCounters,
+}
+
+impl SequenceTracker {
+    pub fn new(config: SequenceConfig, counters: SharedCounters) -> Self {
+        Self {
+            config,
+            last: HashMap::new(),
+            counters,
+        }
+    }
+
+    #[inline(always)]
+    pub fn observe(&mut self, stream: Stream, source: u64, event_id: u64, timestamp: i64) -> Option<EventGap> {
+        if !self.config.enabled {
+            return None;
+        }
+        // every symbol's book is snapshotted on its own , so snapshot ids always run per symbol
+        let source = (self.config.per_source || stream == Stream::Snapshots).then_some(source);
+        let last = match self.last.get_mut(&(stream, source)) {
+            Some(last) => last,
+            None => {
+                // nothing to compare the first id against
+                self.last.insert((stream, source), event_id);
+                return None;
+            }
+        };
+
+        // a sequence at u64::MAX carries on from 0
+        let expected = last.wrapping_add(1);
+        let kind = if event_id == expected {
+            *last = event_id;
+            return None;
+        } else if event_id > expected {
+            *last = event_id;
+            GapKind::Gap
+        } else if event_id == *last {
+            GapKind::Duplicate
+        } else {
+            GapKind::OutOfOrder
+        };
+
+        let gap = EventGap { stream, source, kind, expected, got: event_id, timestamp };
+        // the details go out as an event_gaps row and are logged by the flusher , nothing is printed from the poll loop
+        let counters = &self.counters[stream.index()];
+        match kind {
+            GapKind::Gap => {
+                bump(&counters.gaps, 1);
+                bump(&counters.missing, gap.missing());
+            }
+            GapKind::OutOfOrder => bump(&counters.out_of_order, 1),
+            GapKind::Duplicate => bump(&counters.duplicates, 1),
+        }
+        Some(gap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> SequenceTracker {
+        SequenceTracker::new(SequenceConfig::default(), SharedCounters::default())
+    }
+
+    fn counts(tracker: &SequenceTracker, stream: Stream) -> [u64; 4] {
+        let c = &tracker.counters[stream.index()];
+        [&c.gaps, &c.missing, &c.out_of_order, &c.duplicates].map(|n| n.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn consecutive_ids_are_quiet() {
+        let mut tracker = tracker();
+        for id in 5..10 {
+            assert!(tracker.observe(Stream::OrderLogs, 0, id, 0).is_none());
+        }
+        assert_eq!(counts(&tracker, Stream::OrderLogs), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn skipped_ids_are_a_gap() {
+        let mut tracker = tracker();
+        tracker.observe(Stream::OrderLogs, 0, 1, 0);
+        let gap = tracker.observe(Stream::OrderLogs, 0, 5, 42).unwrap();
+        assert_eq!((gap.kind, gap.expected, gap.got, gap.missing(), gap.timestamp), (GapKind::Gap, 2, 5, 3, 42));
+        // the sequence carries on from the new id
+        assert!(tracker.observe(Stream::OrderLogs, 0, 6, 0).is_none());
+        assert_eq!(counts(&tracker, Stream::OrderLogs), [1, 3, 0, 0]);
+    }
+
+    #[test]
+    fn repeated_and_older_ids_do_not_move_the_sequence() {
+        let mut tracker = tracker();
+        tracker.observe(Stream::BalanceLogs, 0, 10, 0);
+        let duplicate = tracker.observe(Stream::BalanceLogs, 0, 10, 0).unwrap();
+        assert_eq!((duplicate.kind, duplicate.missing()), (GapKind::Duplicate, 0));
+        let reordered = tracker.observe(Stream::BalanceLogs, 0, 8, 0).unwrap();
+        assert_eq!((reordered.kind, reordered.expected, reordered.got), (GapKind::OutOfOrder, 11, 8));
+        assert!(tracker.observe(Stream::BalanceLogs, 0, 11, 0).is_none());
+        assert_eq!(counts(&tracker, Stream::BalanceLogs), [0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn streams_are_tracked_apart() {
+        let mut tracker = tracker();
+        tracker.observe(Stream::OrderLogs, 0, 1, 0);
+        tracker.observe(Stream::HoldingLogs, 0, 100, 0);
+        assert!(tracker.observe(Stream::OrderLogs, 0, 2, 0).is_none());
+        assert!(tracker.observe(Stream::HoldingLogs, 0, 101, 0).is_none());
+    }
+
+    #[test]
+    fn disabled_tracker_sees_nothing() {
+        let mut tracker = SequenceTracker::new(SequenceConfig { enabled: false, per_source: false }, SharedCounters::default());
+        tracker.observe(Stream::OrderLogs, 0, 1, 0);
+        assert!(tracker.observe(Stream::OrderLogs, 0, 9, 0).is_none());
+    }
+
+    #[test]
+    fn sources_are_tracked_apart_when_asked() {
+        let mut shared = tracker();
+        shared.observe(Stream::OrderLogs, 1, 10, 0);
+        assert_eq!(shared.observe(Stream::OrderLogs, 2, 20, 0).unwrap().source, None);
+
+        let mut tracker = SequenceTracker::new(SequenceConfig { enabled: true, per_source: true }, SharedCounters::default());
+        tracker.observe(Stream::OrderLogs, 1, 10, 0);
+        tracker.observe(Stream::OrderLogs, 2, 20, 0);
+        assert!(tracker.observe(Stream::OrderLogs, 1, 11, 0).is_none());
+        let gap = tracker.observe(Stream::OrderLogs, 2, 23, 0).unwrap();
+        assert_eq!((gap.source, gap.expected, gap.got), (Some(2), 21, 23));
+    }
+
+    #[test]
+    fn snapshots_are_tracked_per_symbol() {
+        let mut tracker = tracker();
+        tracker.observe(Stream::Snapshots, 7, 100, 0);
+        tracker.observe(Stream::Snapshots, 8, 500, 0);
+        assert!(tracker.observe(Stream::Snapshots, 7, 101, 0).is_none());
+        let gap = tracker.observe(Stream::Snapshots, 8, 502, 0).unwrap();
+        assert_eq!((gap.source, gap.expected), (Some(8), 501));
+    }
+
+    #[test]
+    fn sequence_wraps_at_the_top() {
+        let mut tracker = tracker();
+        tracker.observe(Stream::OrderLogs, 0, u64::MAX, 0);
+        assert!(tracker.observe(Stream::OrderLogs, 0, 0, 0).is_none());
+        assert!(tracker.observe(Stream::OrderLogs, 0, 1, 0).is_none());
+    }
+}